@@ -0,0 +1,207 @@
+//!
+//! Streaming base64 for `--base64-decode` and `--base64-encode`, the base64 crate does the
+//! coding a quantum at a time. Decoding skips whitespace wherever it is, a quantum may be split
+//! across any number of reads and the `=` padding may be left off. Encoding carries up to two
+//! bytes over between writes and pads at the very end.
+//!
+
+use base64::alphabet::STANDARD;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::{DecodeError, Engine};
+
+// padding is optional on input and always written on output
+const ENGINE: GeneralPurpose = GeneralPurpose::new(
+    &STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+#[derive(Debug, Default)]
+pub struct Decoder {
+    // characters of the quantum being collected, whitespace left out, and their offsets in the input
+    quantum: Vec<u8>,
+    offsets: Vec<u64>,
+    // input bytes seen so far, for the error offsets
+    offset: u64,
+    // once padding was seen only more of it and whitespace may follow
+    padded: bool,
+}
+
+impl Decoder {
+    /// Decodes `input` onto the end of `out`, an empty `input` is the end of the stream.
+    pub fn decode(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<(), String> {
+        if input.is_empty() {
+            return self.flush(out);
+        }
+
+        for &byte in input {
+            let offset = self.offset;
+            self.offset += 1;
+
+            match byte {
+                b' ' | b'\t' | b'\n' | b'\r' | 0x0b | 0x0c => continue,
+                b'=' if self.padded => continue,
+                b'=' if self.quantum.len() >= 2 => {},
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'+' | b'/' if !self.padded && self.quantum.last() != Some(&b'=') => {},
+                _ => return Err(invalid(byte, offset)),
+            }
+
+            self.quantum.push(byte);
+            self.offsets.push(offset);
+            if self.quantum.len() == 4 {
+                self.padded = byte == b'=';
+                self.flush(out)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // writes out the bytes of the quantum collected so far, one less than there are characters
+    fn flush(&mut self, out: &mut Vec<u8>) -> Result<(), String> {
+        if self.quantum.is_empty() {
+            return Ok(());
+        }
+
+        let result = ENGINE.decode_vec(&self.quantum, out).map_err(|e| match e {
+            DecodeError::InvalidByte(at, byte) | DecodeError::InvalidLastSymbol(at, byte) => invalid(byte, self.offsets[at]),
+            DecodeError::InvalidLength(_) => format!("truncated base64 input at byte {}", self.offset),
+            DecodeError::InvalidPadding => format!("invalid base64 padding at byte {}", self.offsets[0]),
+        });
+        self.quantum.clear();
+        self.offsets.clear();
+        result
+    }
+}
+
+fn invalid(byte: u8, offset: u64) -> String {
+    format!("invalid base64 character '{}' at byte {offset}", byte.escape_ascii())
+}
+
+#[derive(Debug)]
+pub struct Encoder {
+    // bytes of the group being collected
+    group: [u8; 3],
+    len: usize,
+    // characters on the current output line, and how many fit, 0 for no wrapping
+    column: usize,
+    wrap: usize,
+}
+
+impl Encoder {
+    pub fn new(wrap: usize) -> Self {
+        Self { group: [0; 3], len: 0, column: 0, wrap }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8], out: &mut Vec<u8>) {
+        for &byte in bytes {
+            self.group[self.len] = byte;
+            self.len += 1;
+            if self.len == 3 {
+                self.flush(out);
+            }
+        }
+    }
+
+    /// The padded last group, then the newline ending the last line when lines are wrapped.
+    pub fn finish(&mut self, out: &mut Vec<u8>) {
+        self.flush(out);
+        if self.wrap > 0 && self.column > 0 {
+            out.push(b'\n');
+            self.column = 0;
+        }
+    }
+
+    fn flush(&mut self, out: &mut Vec<u8>) {
+        if self.len == 0 {
+            return;
+        }
+
+        let mut encoded = [0; 4];
+        ENGINE.encode_slice(&self.group[..self.len], &mut encoded).expect("4 characters fit a group of 3 bytes");
+        for char in encoded {
+            if self.wrap > 0 && self.column == self.wrap {
+                out.push(b'\n');
+                self.column = 0;
+            }
+            out.push(char);
+            self.column += 1;
+        }
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_chunks(chunks: &[&[u8]]) -> Result<Vec<u8>, String> {
+        let mut decoder = Decoder::default();
+        let mut out = Vec::new();
+        for chunk in chunks.iter().chain([&&b""[..]]) {
+            decoder.decode(chunk, &mut out)?;
+        }
+        Ok(out)
+    }
+
+    fn encode_chunks(wrap: usize, chunks: &[&[u8]]) -> String {
+        let mut encoder = Encoder::new(wrap);
+        let mut out = Vec::new();
+        for chunk in chunks {
+            encoder.feed(chunk, &mut out);
+        }
+        encoder.finish(&mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn base64_encode() {
+        assert_eq!(encode_chunks(0, &[b"rat"]), "cmF0");
+        assert_eq!(encode_chunks(0, &[b"ra"]), "cmE=");
+        assert_eq!(encode_chunks(0, &[b"r"]), "cg==");
+        assert_eq!(encode_chunks(0, &[b""]), "");
+        assert_eq!(encode_chunks(0, &[b"\xfb\xff\xbf"]), "+/+/");
+        assert_eq!(encode_chunks(76, &[b"hello, world"]), "aGVsbG8sIHdvcmxk\n");
+        assert_eq!(encode_chunks(76, &[b""]), "");
+    }
+
+    #[test]
+    fn base64_encode_split() {
+        assert_eq!(encode_chunks(0, &[b"h", b"el", b"lo, w", b"o", b"rld"]), "aGVsbG8sIHdvcmxk");
+        assert_eq!(encode_chunks(0, &[b"r", b"a"]), "cmE=");
+    }
+
+    #[test]
+    fn base64_encode_wrap() {
+        assert_eq!(encode_chunks(4, &[b"hello, world"]), "aGVs\nbG8s\nIHdv\ncmxk\n");
+        assert_eq!(encode_chunks(5, &[b"hello"]), "aGVsb\nG8=\n");
+        assert_eq!(encode_chunks(1, &[b"r"]), "c\ng\n=\n=\n");
+    }
+
+    #[test]
+    fn base64_decode() {
+        assert_eq!(decode_chunks(&[b"aGVsbG8sIHdvcmxk"]).unwrap(), b"hello, world");
+        assert_eq!(decode_chunks(&[b"cmF0\n"]).unwrap(), b"rat");
+        assert_eq!(decode_chunks(&[b"cmE=\n"]).unwrap(), b"ra");
+        assert_eq!(decode_chunks(&[b"cg==\n"]).unwrap(), b"r");
+        assert_eq!(decode_chunks(&[b"cg"]).unwrap(), b"r");
+        assert_eq!(decode_chunks(&[b"+/+/"]).unwrap(), b"\xfb\xff\xbf");
+        assert_eq!(decode_chunks(&[b""]).unwrap(), b"");
+    }
+
+    #[test]
+    fn base64_decode_split() {
+        assert_eq!(decode_chunks(&[b"aGVs", b"bG8s", b"IHdvcmxk"]).unwrap(), b"hello, world");
+        assert_eq!(decode_chunks(&[b"a", b"GVsb", b"G8", b"sI", b"Hdv\n", b"cmx", b"k"]).unwrap(), b"hello, world");
+        assert_eq!(decode_chunks(&[b"cmE", b"=", b"\n"]).unwrap(), b"ra");
+        assert_eq!(decode_chunks(&[b"cg=", b"="]).unwrap(), b"r");
+    }
+
+    #[test]
+    fn base64_decode_invalid() {
+        assert_eq!(decode_chunks(&[b"cmF0", b"!"]).unwrap_err(), "invalid base64 character '!' at byte 4");
+        assert_eq!(decode_chunks(&[b"cg==cmF0"]).unwrap_err(), "invalid base64 character 'c' at byte 4");
+        assert_eq!(decode_chunks(&[b"c\xff"]).unwrap_err(), "invalid base64 character '\\xff' at byte 1");
+        assert_eq!(decode_chunks(&[b"=cmF0"]).unwrap_err(), "invalid base64 character '=' at byte 0");
+        assert_eq!(decode_chunks(&[b"cmF0c"]).unwrap_err(), "truncated base64 input at byte 5");
+    }
+}
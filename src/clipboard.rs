@@ -0,0 +1,30 @@
+//!
+//! The clipboard for `--clipboard`, read as text through arboard: the pasteboard on macOS, the
+//! clipboard on Windows and the X11 or Wayland clipboard elsewhere.
+//!
+
+pub fn read() -> std::io::Result<Vec<u8>> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .map(String::into_bytes)
+        .map_err(io_error)
+}
+
+// no text on it at all reads better than what arboard says about that
+fn io_error(e: arboard::Error) -> std::io::Error {
+    match e {
+        arboard::Error::ContentNotAvailable => std::io::Error::other("clipboard is empty"),
+        e => std::io::Error::other(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clipboard_errors() {
+        assert_eq!(io_error(arboard::Error::ContentNotAvailable).to_string(), "clipboard is empty");
+        assert_eq!(io_error(arboard::Error::ClipboardNotSupported).to_string(), arboard::Error::ClipboardNotSupported.to_string());
+    }
+}
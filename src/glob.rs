@@ -0,0 +1,88 @@
+//!
+//! Shell style wildcards for `--include` and `--exclude`, matched by globset: `?` is any one
+//! character, `*` any run of characters but `/`, `**` as a whole path component any number of
+//! directories, `[abc]`, `[a-z]`, `[!a-z]` are bracket classes and `{a,b}` alternatives. `\` makes
+//! the next character literal. A pattern without a `/` is matched against the file name only,
+//! one with a `/` against the whole path.
+//!
+
+#[derive(Debug, Clone)]
+pub struct Glob {
+    matcher: globset::GlobMatcher,
+    whole_path: bool,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let glob = globset::GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .backslash_escape(true)
+            .build()
+            .map_err(|e| format!("invalid glob '{pattern}': {}", e.kind()))?;
+
+        Ok(Self { matcher: glob.compile_matcher(), whole_path: pattern.contains('/') })
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        let text = match self.whole_path {
+            true => path,
+            false => path.rsplit('/').next().unwrap_or(path),
+        };
+
+        self.matcher.is_match(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(matches("*.rs", "src/lib.rs"));
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "lib.rs.bak"));
+        assert!(matches("lib.?s", "lib.rs"));
+        assert!(!matches("lib.?s", "lib.s"));
+        assert!(matches("*", ""));
+        assert!(matches("a\\*", "a*"));
+        assert!(!matches("a\\*", "ab"));
+    }
+
+    #[test]
+    fn glob_paths() {
+        assert!(matches("src/*.rs", "src/lib.rs"));
+        assert!(!matches("src/*.rs", "src/nested/lib.rs"));
+        assert!(matches("src/**/*.rs", "src/nested/lib.rs"));
+        assert!(matches("**/test/*", "a/b/test/c"));
+        assert!(!matches("src/*.rs", "lib.rs"));
+    }
+
+    #[test]
+    fn glob_classes() {
+        assert!(matches("[abc].txt", "b.txt"));
+        assert!(!matches("[abc].txt", "d.txt"));
+        assert!(matches("file[0-9]", "file7"));
+        assert!(matches("file[!0-9]", "filex"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+    }
+
+    #[test]
+    fn glob_alternatives() {
+        assert!(matches("gt/*.{rs,txt}", "gt/a.rs"));
+        assert!(matches("gt/*.{rs,txt}", "gt/b.txt"));
+        assert!(!matches("gt/*.{rs,txt}", "gt/c.md"));
+    }
+
+    #[test]
+    fn glob_invalid() {
+        assert!(Glob::new("[abc").is_err());
+        assert!(Glob::new("a\\").is_err());
+        assert!(Glob::new("{a,b").is_err());
+    }
+}
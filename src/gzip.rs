@@ -0,0 +1,133 @@
+//!
+//! Streaming gzip decompression for `--gzip` through flate2, members may follow each other.
+//! Input that doesn't start with the gzip magic bytes is passed through as it is, the two bytes
+//! it takes to tell are held back until then.
+//!
+
+use std::io::Write;
+
+use flate2::write::MultiGzDecoder;
+
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Default)]
+pub struct Decoder {
+    // the first bytes, until there are enough of them to tell whether it's gzip at all
+    start: Vec<u8>,
+    // set once that's decided, None when it isn't gzip
+    inflate: Option<Option<MultiGzDecoder<Vec<u8>>>>,
+}
+
+impl Decoder {
+    /// Decodes `input` onto the end of `out`, an empty `input` is the end of the stream.
+    pub fn decode(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<(), String> {
+        let end = input.is_empty();
+        let input = match &self.inflate {
+            Some(_) => input,
+            None => {
+                self.start.extend_from_slice(input);
+                if self.start.len() < MAGIC.len() && !end {
+                    return Ok(());
+                }
+                self.inflate = Some(self.start.starts_with(&MAGIC).then(|| MultiGzDecoder::new(Vec::new())));
+                &self.start[..]
+            },
+        };
+
+        let Some(Some(inflate)) = &mut self.inflate else {
+            out.extend_from_slice(input);
+            self.start.clear();
+            return Ok(());
+        };
+
+        // flushing hands over everything the input decoded to so far
+        let mut result = inflate.write_all(input).and_then(|()| inflate.flush());
+        if end {
+            result = result.and_then(|()| inflate.try_finish());
+        }
+        // like zcat, what was decoded before the stream broke off is shown
+        out.append(inflate.get_mut());
+        self.start.clear();
+        result.map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // gzip -9 of "the cat sat on the mat, the rat sat on the cat\n" three times, a fixed Huffman block
+    const FIXED: &str = "1f8b08000000000002032bc94855484e2c512806e2fc3c8512203737b14407cc284215072ae32aa1a972002351ea058d000000";
+    // gzip -9 of "0 squared is 0\n" up to "11 squared is 121\n", a dynamic Huffman block
+    const DYNAMIC: &str = "1f8b080000000000020355ce310e80300c03c0ddafe81392340dcd73906060848affc3e88c2759b6a5adfbdd9ff368d76a02652a8ce9e8cc84977060b06d20d83db095b6c4648723d953a15206e4bf57ff99e2032fabb8f1c0000000";
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+
+    // "hello\n" behind a header with a file name, a comment and a header CRC
+    fn with_header() -> Vec<u8> {
+        let mut encoder = flate2::GzBuilder::new()
+            .filename("name")
+            .comment("comment")
+            .write(Vec::new(), flate2::Compression::none());
+        encoder.write_all(b"hello\n").unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn inflate(input: &[u8], chunk: usize) -> Result<Vec<u8>, String> {
+        let mut decoder = Decoder::default();
+        let mut out = Vec::new();
+        for piece in input.chunks(chunk) {
+            decoder.decode(piece, &mut out)?;
+        }
+        decoder.decode(&[], &mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn gzip_blocks() {
+        let squares: String = (0..12).map(|i| format!("{i} squared is {}\n", i * i)).collect();
+        let cases = [
+            (unhex(FIXED), "the cat sat on the mat, the rat sat on the cat\n".repeat(3)),
+            (unhex(DYNAMIC), squares),
+            (with_header(), "hello\n".into()),
+        ];
+
+        // every way of splitting the input gives the same output
+        for (input, expected) in cases {
+            for chunk in 1..=input.len() {
+                assert_eq!(inflate(&input, chunk).unwrap(), expected.as_bytes(), "chunk {chunk}");
+            }
+        }
+    }
+
+    #[test]
+    fn gzip_members_and_passthrough() {
+        let mut members = with_header();
+        members.extend(with_header());
+        assert_eq!(inflate(&members, 7).unwrap(), b"hello\nhello\n");
+
+        assert_eq!(inflate(b"plain text\n", 1).unwrap(), b"plain text\n");
+        assert_eq!(inflate(b"\x1f", 1).unwrap(), b"\x1f");
+        assert_eq!(inflate(b"", 1).unwrap(), b"");
+    }
+
+    #[test]
+    fn gzip_errors() {
+        let input = unhex(FIXED);
+        assert!(inflate(&input[..input.len() - 3], 4).is_err());
+
+        let mut corrupt = input.clone();
+        corrupt[input.len() - 8] ^= 1;
+        assert!(inflate(&corrupt, 4).is_err());
+
+        let mut trailing = input.clone();
+        trailing.extend_from_slice(b"junk");
+        assert!(inflate(&trailing, 4).is_err());
+
+        let mut method = input;
+        method[2] = 7;
+        assert_eq!(inflate(&method, 4).unwrap_err(), "invalid gzip header");
+    }
+}
@@ -3,332 +3,7299 @@
 //! By JerryImMouse
 //! 
 
-use std::io::{Read, Write};
+#[cfg(feature = "base64")]
+mod base64;
+#[cfg(feature = "clipboard")]
+mod clipboard;
+#[cfg(feature = "glob")]
+mod glob;
+#[cfg(feature = "gzip")]
+mod gzip;
+pub mod transform;
+
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::io::{Read, Seek, Write};
+use std::rc::Rc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+#[cfg(feature = "encoding")]
+use transform::Encoding;
+use transform::{nonprinting, Case, LineState, Numbering, Overflow, Radix, Reveal, Stage, Transform};
+
+// a tracing event at `level` (debug or trace), the fields and message like tracing's macros take them; without the
+// trace feature it's nothing at all, the fields aren't even evaluated
+macro_rules! trace_event {
+    ($level:ident, $($fields:tt)*) => {
+        #[cfg(feature = "trace")]
+        tracing::$level!($($fields)*);
+    };
+}
 
 static IO_BUFSIZE: usize = 512 * 1024;
+// smallest output buffer, smaller sizes are raised to it
+const MIN_BUFSIZE: usize = 4 * 1024;
+
+// --base64-encode line length without --wrap, same as base64(1)
+#[cfg(feature = "base64")]
+const BASE64_WRAP: usize = 76;
+
+// what stdin is called without --stdin-name, same as the coreutils
+const STDIN_NAME: &str = "standard input";
+
+// what --tap starts lines with, a TAP diagnostic
+const TAP_PREFIX: &[u8] = b"# ";
+
+// exit status when --read-timeout expires, same as timeout(1)
+pub const READ_TIMEOUT_STATUS: i32 = 124;
+
+// first and longest pause between --retry-open tries, and --retry-read ones
+const RETRY_OPEN_FIRST: Duration = Duration::from_millis(10);
+const RETRY_OPEN_MAX: Duration = Duration::from_secs(1);
+
+// bytes copied between the offsets --checkpoint records
+const CHECKPOINT_EVERY: u64 = 1 << 20;
+
+// how long opening a named pipe may block before telling the user about it
+#[cfg(unix)]
+const FIFO_NOTICE_AFTER: Duration = Duration::from_millis(200);
 
 const RAT_VERSION: &str = env!("CARGO_PKG_VERSION");
 const RAT_NAME: &str = env!("CARGO_PKG_NAME");
 
-static RAT_USAGE: &str = r#"
+static RAT_USAGE_HEAD: &str = r#"
 Usage: rat [OPTION]... [FILE]...
 Concatenate FILE(s) to standard output.
 
-With no FILE, or when FILE is -, read standard input.
-
-  -A, --show-all           equivalent to -vET
-  -b, --number-nonblank    number nonempty output lines, overrides -n
-  -e                       equivalent to -vE
-  -E, --show-ends          display $ at end of each line
-  -n, --number             number all output lines
-  -s, --squeeze-blank      suppress repeated empty output lines
-  -t                       equivalent to -vT
-  -T, --show-tabs          display TAB characters as ^I
-  -u                       (ignored)
-  -v, --show-nonprinting   use ^ and M- notation, except for LFD and TAB
-      --help        display this help and exit
-      --version     output version information and exit
+With no FILE, or when FILE is -, read standard input. Arguments after -- are
+FILEs even if they start with -.
+"#;
+
+static RAT_USAGE_TAIL: &str = r#"
+Options in the RAT_OPTIONS environment variable are read before the ones on
+the command line, which win where they disagree. Quote with ' or " and escape
+with \ like in a shell.
 
 Examples:
   rat f - g  Output f's contents, then standard input, then g's contents.
   rat        Copy standard input to standard output.
 "#;
 
-#[derive(Debug)]
-enum Source {
-    File(String, Option<std::fs::File>),
-    Stdin(std::io::Stdin),
-    #[cfg(test)]
-    Mock(Option<Vec<String>>, usize, String),
+// where the descriptions start in --help, and how wide its lines get
+const HELP_COLUMN: usize = 27;
+const HELP_WIDTH: usize = 80;
+
+/// A command line option, both [`RatArgs::new`] and `--help` go by these.
+struct OptionSpec {
+    short: Option<char>,
+    long: Option<&'static str>,
+    // how the value is shown after the long name, like "=N" or "[=STYLE]"
+    value: &'static str,
+    help: &'static str,
 }
 
-impl Source {
-    fn read_to_buf(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
-        match self {
-            Source::File(path, file_option) => {
-                if file_option.is_none() {
-                    let file = std::fs::File::open(path)?;
-                    *file_option = Some(file);
-                }
+const fn flag(short: Option<char>, long: &'static str, help: &'static str) -> OptionSpec {
+    OptionSpec { short, long: Some(long), value: "", help }
+}
 
-                let file = file_option.as_mut().unwrap();
+const fn valued(long: &'static str, value: &'static str, help: &'static str) -> OptionSpec {
+    OptionSpec { short: None, long: Some(long), value, help }
+}
 
-                let bytes_read = file.read(buf)?;
-                Ok(bytes_read)
+const fn short_only(short: char, help: &'static str) -> OptionSpec {
+    OptionSpec { short: Some(short), long: None, value: "", help }
+}
+
+static OPTIONS: &[OptionSpec] = &[
+    flag(Some('A'), "--show-all", "equivalent to -vET"),
+    flag(None, "--plain", "ignore every option that changes what is written, wherever it comes from, and copy the FILEs byte for byte; --stats-only becomes --stats"),
+    flag(Some('b'), "--number-nonblank", "number nonempty output lines, overrides -n"),
+    short_only('e', "equivalent to -vE"),
+    flag(Some('E'), "--show-ends", "display $ at end of each line"),
+    flag(None, "--show-ends-eof", "with -E, also display $ at the end of a FILE that doesn't end in a newline"),
+    valued("--fold-case", "[=CASE]", "write the letters of the FILEs in lower (the default) or upper CASE, ASCII ones only unless built with the unicode feature; done before -v and --from-encoding, so it doesn't touch what they write"),
+    flag(None, "--tap", "start every line with # so the FILEs pass for TAP diagnostics, ending the last line if it isn't"),
+    flag(Some('Z'), "--null-output", "end output lines with NUL instead of newline, after the -E marker"),
+    valued("--normalize-newlines", "=EOL", "take LF, CRLF and lone CR as line ends and write all of them as EOL: lf, crlf or cr"),
+    valued("--record-delimiter", "=STR", "end lines at STR instead of at newlines, which become ordinary bytes, for -n, -s, -E and the other options numbering or marking lines; STR is written back after the -E marker and understands the same escapes as --replace-tabs-with"),
+    valued("--end-marker", "=STR", "display STR instead of $ for -E, understands the same escapes as --replace-tabs-with"),
+    flag(Some('n'), "--number", "number all output lines"),
+    valued("--numbers-from", "=N", "start numbering lines at N instead of 1"),
+    valued("--max-line-number", "=N", "number no line higher than N, --number-overflow says what the lines after that get"),
+    valued("--number-overflow", "=ACTION", "past --max-line-number, stop numbering (stop, the default) or start over at --numbers-from (wrap)"),
+    valued("--byte-offset", "[=RADIX]", "start every line with the offset it starts at in the FILEs read one after another, after -n's number; in RADIX, dec (default) or hex"),
+    valued("--number-radix", "=RADIX", "write line numbers in RADIX, dec (default) or hex (as 0x0000ff)"),
+    #[cfg(feature = "regex")]
+    valued("--number-matching", "=PATTERN", "number only lines matching PATTERN, overrides -n and -b"),
+    #[cfg(feature = "regex")]
+    valued("--grep", "=PATTERN", "write only the lines matching PATTERN"),
+    #[cfg(feature = "regex")]
+    valued("--count-matching", "[=per-file]", "write how many lines --grep matches instead of the lines, in total or as FILE:COUNT for every FILE"),
+    #[cfg(feature = "regex")]
+    OptionSpec {
+        short: Some('C'),
+        long: Some("--context"),
+        value: "=N",
+        help: "with --grep, also write N lines before and after every match and -- between groups that aren't adjacent, -n then numbers lines as in the input",
+    },
+    valued("--sample", "=N", "write only every Nth line, -n still numbers them as in the input"),
+    valued("--sample-offset", "=K", "with --sample, write the lines whose number leaves K when divided by N (0 to N-1, default 0)"),
+    flag(Some('s'), "--squeeze-blank", "suppress repeated empty output lines"),
+    flag(None, "--squeeze-whitespace", "like -s, but lines of only spaces and tabs count as empty too"),
+    flag(None, "--no-squeeze-across-files", "squeeze empty lines within each FILE only, instead of also where one FILE's end and the next one's start meet"),
+    valued("--fields", "=LIST", "write only the fields of every line LIST names, like cut -f but in the order given; LIST is numbers and ranges like 1,3-5,7- or -2"),
+    valued("--delimiter", "=STR", r"split --fields and --key at STR instead of TAB and join them with it, understands the same escapes as --replace-tabs-with"),
+    flag(None, "--collapse-spaces", "squeeze runs of spaces into a single space, tabs are left alone"),
+    #[cfg(feature = "crc32")]
+    flag(None, "--checksum-per-line", "end every line with a tab and the CRC-32 of its content in hex, after the line number and before the -E marker"),
+    flag(None, "--uniq", "drop lines that are the same as the one right before them, like uniq"),
+    flag(None, "--uniq-global", "drop lines that are the same as any earlier one, which keeps every distinct line in memory, see --max-memory"),
+    flag(None, "--strip-trailing-whitespace", "drop the spaces and tabs at the end of every line, can't be combined with -T"),
+    short_only('t', "equivalent to -vT"),
+    flag(Some('T'), "--show-tabs", "display TAB characters as ^I"),
+    flag(None, "--expand-tabs", "display TAB characters as spaces up to the next tab stop; -T, --replace-tabs-with and --reveal win over it, in that order from weakest"),
+    valued("--tab-size", "=N", "put tab stops N columns apart (1 to 32, default 8), for --expand-tabs and --columns"),
+    valued("--replace-tabs-with", "=STR", r"display TAB characters as STR, understands \t, \n, \r, \0 and \\ escapes"),
+    short_only('u', "(ignored)"),
+    flag(Some('v'), "--show-nonprinting", "use ^ and M- notation, except for LFD and TAB"),
+    valued("--reveal", "[=STYLE]", "show line endings and tabs as glyphs (⏎, ␍, →) and mark FILEs without a final newline; STYLE ascii uses <LF>, <CR> and -> instead"),
+    valued("--transform-order", "=STAGES", "run the byte by byte display options in this order, a comma list of squeeze, nonprinting, tabs, number and ends (the default order); each stage sees what the earlier ones wrote, the ones left out follow in the default order"),
+    flag(None, "--detect-binary", "when writing to a terminal, skip FILEs that look binary instead of dumping them, unless -v is given"),
+    flag(None, "--force", "show binary FILEs anyway, overrides --detect-binary"),
+    flag(None, "--text-only", "stop with an error at the first FILE that looks binary, before any of it is written"),
+    valued("--binary-threshold", "=N", "take FILEs with a NUL or more than N percent (default 30) of control characters in their first 4K as binary, for --detect-binary and --text-only"),
+    flag(None, "--ascii-only", "replace every byte >= 128 with ?, can't be combined with -v or --from-encoding"),
+    valued("--ascii-placeholder", "=STR", "use STR instead of ? for --ascii-only, understands the same escapes as --replace-tabs-with"),
+    #[cfg(feature = "encoding")]
+    valued("--from-encoding", "=ENC", "transcode input from ENC (latin1, cp1252) to UTF-8"),
+    #[cfg(feature = "base64")]
+    flag(None, "--base64-decode", "decode base64 input before displaying it, whitespace in it is ignored"),
+    #[cfg(feature = "base64")]
+    flag(None, "--base64-encode", "show all FILEs as one base64 stream instead, can't be combined with the other display options"),
+    #[cfg(feature = "gzip")]
+    flag(None, "--gzip", "decompress FILEs starting with the gzip magic bytes, stdin included, others are shown as they are"),
+    valued("--wrap", "=COLS", "break lines after COLS bytes of input, the breaks aren't numbered; --base64-encode lines are wrapped after COLS characters (default 76) instead; 0 disables wrapping"),
+    valued("--wrap-marker", "=STR", "start the lines --wrap breaks off with STR, understands the same escapes as --replace-tabs-with"),
+    valued("--output-separator", "=STR", "write STR between the contents of consecutive FILEs, understands the same escapes as --replace-tabs-with"),
+    valued("--frame", "=FORMAT", "send the output as frames for a consumer that reads them one at a time; FORMAT length puts a 4-byte big-endian length before every frame"),
+    valued("--frame-unit", "=UNIT", "with --frame, make a frame of the output of every FILE (source, the default, kept in memory until the FILE is done) or of every line (line)"),
+    valued("--max-open-files", "=N", "refuse to keep more than N FILEs open at once, which only --columns, --merge-sorted and --interleave do; defaults to a little below the soft RLIMIT_NOFILE on Unix"),
+    flag(None, "--hexdump", "show the bytes of all FILEs as offset, hex and ASCII columns like hexdump -C (every line, no *), can't be combined with the other display options"),
+    flag(None, "--tsv-align", "line up the tab separated columns of every FILE, which is kept in memory as a whole for that"),
+    valued("--align", "=MODE", "with --tsv-align, left-justify every column (left, the default) or right-justify the ones holding only numbers below the first line (auto)"),
+    flag(None, "--columns", "show FILEs side by side, one line of each per row"),
+    flag(None, "--diff", "compare exactly two FILEs line by line, writing the lines only the first has with -, the ones only the second has with + and the common ones with a space; both are kept in memory as a whole"),
+    flag(None, "--merge-sorted", "merge FILEs that are each sorted into one sorted stream like sort -m, reading a line at a time from all of them, so they all stay open until the end"),
+    valued("--interleave", "=N", "write N lines of every FILE in turn instead of one FILE after another, leaving out the ones that ran out; they all stay open until the end"),
+    valued("--key", "=N", "with --merge-sorted, order the lines by their Nth field instead of as a whole, split at TAB or --delimiter"),
+    flag(None, "--check-sorted", "with --merge-sorted, report the first line of every FILE that sorts before the one above it to stderr and exit with status 1"),
+    valued("--column-width", "=N", "pad every column but the last to N characters with spaces (default 40), longer lines push the rest of the row right; tabs are expanded to --tab-size"),
+    valued("--max-memory", "=SIZE", "stop with an error when --tsv-align, --diff or --uniq-global would keep more than SIZE bytes of input in memory, SIZE may end in K, M or G"),
+    valued("--limit-rate", "=RATE", "write at most about RATE bytes per second, RATE may end in K, M or G (powers of 1024)"),
+    valued("--buffer-size", "=SIZE", "read and write in chunks of SIZE bytes (default 512K, at least 4K), SIZE may end in K, M or G; falls back to the default if SIZE can't be allocated"),
+    valued("--input-buffer", "=SIZE", "read in chunks of SIZE bytes, like --buffer-size but for reading only and without the minimum"),
+    valued("--output-buffer", "=SIZE", "write in chunks of SIZE bytes (at least 4K), like --buffer-size but for writing only"),
+    flag(None, "--add-bom", "start the output with a UTF-8 byte order mark"),
+    flag(None, "--line-buffered", "flush output after every line"),
+    valued("--delay", "=MS", "pause MS milliseconds after every FILE but the last, with what was written of it flushed, so the output comes in stages"),
+    valued("--lines-per-page", "=N", "write a form feed after every N lines of output, for printing; -n numbers carry on across pages"),
+    flag(None, "--pager", "pipe output through $PAGER (or less when unset) if standard output is a terminal"),
+    flag(None, "--verbose", "print a summary of what was read to stderr"),
+    flag(None, "--time-it", "print how long copying the FILEs took and the throughput to stderr, not for --columns and --diff"),
+    valued("--fifo-timeout", "=SECS", "give up on a named pipe FILE that gets no writer within SECS seconds (by default wait forever)"),
+    valued("--retry-open", "=SECS", "when a FILE doesn't exist, keep trying to open it for up to SECS seconds, waiting 10ms after the first try and twice as long after each next one, up to a second"),
+    valued("--retry-read", "=N", "when reading a FILE fails with an error that may go away by itself (would block, timed out, interrupted), try again up to N times, backing off like --retry-open"),
+    valued("--read-timeout", "=SECS", "give up waiting for standard input after SECS seconds without data and exit with status 124"),
+    flag(None, "--stats", "print byte frequency statistics to stderr"),
+    flag(None, "--count-bytes-only", "write only the total number of bytes in the FILEs, ignoring the options that change the output; a regular FILE's size is taken from the file system without reading it, unless that says 0"),
+    flag(None, "--stats-only", "like --stats, but suppress normal output"),
+    flag(None, "--line-stats", "print the number of lines and their shortest, longest and mean length in bytes, without the newline, to stderr; the last line of a FILE counts with or without one"),
+    #[cfg(feature = "glob")]
+    valued("--include", "=GLOB", "only read the FILEs matching GLOB, may be repeated"),
+    #[cfg(feature = "glob")]
+    valued("--exclude", "=GLOB", "skip the FILEs matching GLOB, may be repeated and wins over --include; a GLOB without / is matched against the file name only"),
+    #[cfg(feature = "clipboard")]
+    flag(None, "--clipboard", "read the clipboard where it's given among the FILEs"),
+    #[cfg(unix)]
+    valued("--fd", "=N", "read the already open file descriptor N where it's given among the FILEs, like one a shell redirected with 3<"),
+    flag(None, "--quote-names", "quote the names of FILEs in diagnostics and headers like a shell would need them, with $'...' escapes for control characters"),
+    valued("--stdin-name", "=LABEL", "call standard input LABEL in diagnostics and reports like --count-matching=per-file, instead of 'standard input'"),
+    valued("--files-from", "=FILE", "read the FILEs to show from FILE, one per line, where it's given among the FILEs; a - line is standard input, unless FILE is - itself"),
+    flag(None, "--cache-stdin", "keep standard input in memory so every - outputs all of it, instead of only the first one getting it"),
+    #[cfg(unix)]
+    flag(None, "--raw-tty", "read a terminal on standard input in raw mode, every key as it's pressed; Ctrl-D ends the input and Ctrl-C is just another byte"),
+    valued("--fail-if-empty", "[=N]", "exit with status N (1 to 255, default 1) when nothing at all was written, like when --grep matched no line; a failure's status wins over it"),
+    flag(None, "--abort-on-error", "stop at the first FILE that can't be read"),
+    valued("--checkpoint", "=FILE", "copy a single FILE as it is, with no other options, writing how many bytes of it reached the output to FILE every 1M; FILE is removed once the copy is done"),
+    flag(None, "--resume", "with --checkpoint, carry on from the offset in its FILE; the output has to be a regular file opened for appending that was empty when the copy started, it's cut back to that offset"),
+    flag(None, "--reverse-files", "concatenate the FILEs last to first, each one's content still in order"),
+    valued("--on-missing", "=POLICY", "what to do about a FILE that doesn't exist: report it and go on (the default), skip it silently, stop with an error, or read it as empty"),
+    valued("--on-empty", "=POLICY", "what to do about a FILE without a single byte: nothing (ignore, the default), report it and go on (warn), or stop with an error"),
+    valued("--max-sources", "=N", "refuse to read anything when given more than N sources, after --include and --exclude"),
+    flag(None, "--metadata", "write a ==> FILE (SIZE bytes, modified TIME, mode MODE) <== header before every FILE, TIME in UTC and MODE in octal on Unix only; standard input is a (stream)"),
+    valued("--preview", "=N", "write only the first N bytes of every FILE under a ==> FILE <== header like head's, and a ... line after the ones that had more"),
+    flag(None, "--skip-header", "drop the first line of every FILE, for joining CSV files and the like"),
+    valued("--stop-at", "=STR", "stop reading and writing at the first line that is exactly STR, which isn't written"),
+    flag(None, "--include-marker", "with --stop-at, write the line it stops at too"),
+    valued("--replace", "=FROM/TO", r"replace every FROM in the FILEs with TO, where \/ is a / that doesn't end FROM; understands the same escapes as --replace-tabs-with and may be given again, every one replacing in what the ones before it wrote"),
+    flag(None, "--keep-first-header", "with --skip-header, keep the first FILE's first line"),
+    flag(None, "--check-final-newline", "report FILEs that don't end in a newline to stderr and exit with status 1; empty FILEs are fine"),
+    flag(None, "--check-utf8", "report the first invalid UTF-8 sequence of every FILE to stderr"),
+    flag(None, "--no-env", "ignore RAT_OPTIONS"),
+    flag(None, "--help", "display this help and exit"),
+    flag(None, "--version", "output version information and exit"),
+];
+
+impl OptionSpec {
+    fn find_long(name: &str) -> Option<&'static OptionSpec> {
+        OPTIONS.iter().find(|option| option.long == Some(name))
+    }
+
+    fn find_short(short: char) -> Option<&'static OptionSpec> {
+        OPTIONS.iter().find(|option| option.short == Some(short))
+    }
+
+    // what RatArgs::apply_option knows the option by, the long name if it has one
+    fn name(&self) -> String {
+        match (self.long, self.short) {
+            (Some(long), _) => long.to_string(),
+            (None, Some(short)) => format!("-{short}"),
+            (None, None) => unreachable!("an option needs a name"),
+        }
+    }
+
+    // the option's --help entry, the description wrapped at HELP_WIDTH
+    fn help_entry(&self, out: &mut String) {
+        let names = match (self.short, self.long) {
+            (Some(short), Some(long)) => format!("  -{short}, {long}{}", self.value),
+            (None, Some(long)) => format!("      {long}{}", self.value),
+            (Some(short), None) => format!("  -{short}"),
+            (None, None) => unreachable!("an option needs a name"),
+        };
+
+        out.push_str(&names);
+        let mut column = names.chars().count();
+        // a name too long to leave a gap gets the description on the next line
+        if column >= HELP_COLUMN {
+            out.push('\n');
+            column = 0;
+        }
+
+        let mut first_word = true;
+        for word in self.help.split(' ') {
+            let width = word.chars().count();
+            if !first_word && column + 1 + width > HELP_WIDTH {
+                out.push('\n');
+                column = 0;
             }
-            Source::Stdin(stdin) => {
-                let bytes_read = stdin.read(buf)?;
-    
-                if bytes_read == 0 {
-                    return Ok(0); // Properly handle EOF
-                }
+            if column < HELP_COLUMN {
+                out.extend(std::iter::repeat_n(' ', HELP_COLUMN - column));
+                column = HELP_COLUMN;
+            } else {
+                out.push(' ');
+                column += 1;
+            }
+            out.push_str(word);
+            column += width;
+            first_word = false;
+        }
+        out.push('\n');
+    }
+}
 
-                Ok(bytes_read)
-            },
-            #[cfg(test)]
-            Source::Mock(lines, pos, s) => {
-                if lines.is_none() {
-                    let collected_lines: Vec<String> = s.lines().map(|s| s.to_string()).collect();
-                    *lines = Some(collected_lines);
-                }
-            
-                let lines = lines.as_ref().unwrap();
-            
-                if *pos >= lines.len() {
-                    return Ok(0);
+// the --help text, with an entry for every option this build knows
+fn usage() -> String {
+    let mut usage = RAT_USAGE_HEAD.to_string();
+    usage.push('\n');
+    for option in OPTIONS {
+        option.help_entry(&mut usage);
+    }
+    usage.push_str(RAT_USAGE_TAIL);
+    usage
+}
+
+/// Byte frequencies collected by `--stats`, counted on raw input before any transformation.
+struct ByteStats {
+    counts: [u64; 256],
+}
+
+impl ByteStats {
+    fn new() -> Self {
+        Self { counts: [0; 256] }
+    }
+
+    fn record(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.counts[byte as usize] += 1;
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    // printable ASCII plus the usual text whitespace
+    fn printable(&self) -> u64 {
+        (0u8..128)
+            .filter(|b| (0x20..0x7f).contains(b) || matches!(b, b'\t' | b'\n' | b'\r'))
+            .map(|b| self.counts[b as usize])
+            .sum()
+    }
+
+    fn high(&self) -> u64 {
+        self.counts[128..].iter().sum()
+    }
+
+    fn control(&self) -> u64 {
+        self.total() - self.printable() - self.high()
+    }
+
+    fn report<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        writeln!(w, "{RAT_NAME}: {} bytes, {} printable, {} control, {} high", self.total(), self.printable(), self.control(), self.high())?;
+
+        let max = self.counts.iter().copied().max().unwrap_or(0).max(1);
+        for (byte, &count) in self.counts.iter().enumerate().filter(|(_, &c)| c > 0) {
+            let mut label = [0u8; 4];
+            let len = nonprinting(byte as u8, &mut label);
+            let bar = "#".repeat(((count * 40).div_ceil(max)) as usize);
+            writeln!(w, "  0x{byte:02x} {:<4} {count:>10} {bar}", String::from_utf8_lossy(&label[..len]))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Line lengths for `--line-stats`, counted as the bytes go by, so a line can span any number of reads.
+#[derive(Default)]
+struct LineStats {
+    // bytes of the line not finished yet, and whether it has any
+    current: u64,
+    in_line: bool,
+    lines: u64,
+    shortest: u64,
+    longest: u64,
+    total: u64,
+}
+
+impl LineStats {
+    fn record(&mut self, bytes: &[u8]) {
+        let mut rest = bytes;
+        while let Some(end) = rest.iter().position(|&byte| byte == b'\n') {
+            self.current += end as u64;
+            self.end_line();
+            rest = &rest[end + 1..];
+        }
+        self.current += rest.len() as u64;
+        self.in_line |= !rest.is_empty();
+    }
+
+    // a source ending without a newline ends its last line all the same
+    fn end_source(&mut self) {
+        if self.in_line {
+            self.end_line();
+        }
+    }
+
+    fn end_line(&mut self) {
+        let length = std::mem::take(&mut self.current);
+        self.in_line = false;
+        self.shortest = if self.lines == 0 { length } else { self.shortest.min(length) };
+        self.longest = self.longest.max(length);
+        self.total += length;
+        self.lines += 1;
+    }
+
+    fn report<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        if self.lines == 0 {
+            return writeln!(w, "{RAT_NAME}: 0 lines");
+        }
+        let mean = self.total as f64 / self.lines as f64;
+        writeln!(w, "{RAT_NAME}: {} lines, length min {}, max {}, mean {mean:.2}", self.lines, self.shortest, self.longest)
+    }
+}
+
+/// Reads from another thread so a stalled reader can be given up on after `timeout`.
+/// Works the same on every platform, the reading thread is simply left blocked on expiry.
+struct TimedReader {
+    reader: Option<Box<dyn Read + Send>>,
+    rx: Option<Receiver<std::io::Result<Vec<u8>>>>,
+    timeout: Duration,
+    // leftover of a chunk that didn't fit into the caller's buffer
+    pending: Vec<u8>,
+    pending_pos: usize,
+}
+
+impl TimedReader {
+    fn new(reader: impl Read + Send + 'static, timeout: Duration) -> Self {
+        Self { reader: Some(Box::new(reader)), rx: None, timeout, pending: Vec::new(), pending_pos: 0 }
+    }
+
+    // the thread is spawned lazily, so a second `-` doesn't race the first one for stdin
+    fn spawn(&mut self) {
+        let Some(mut reader) = self.reader.take() else { return };
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        std::thread::spawn(move || {
+            let mut chunk = vec![0u8; 64 * 1024];
+            loop {
+                let result = reader.read(&mut chunk).map(|n| chunk[..n].to_vec());
+                let stop = !matches!(&result, Ok(data) if !data.is_empty());
+                if tx.send(result).is_err() || stop {
+                    break;
                 }
-            
-                let line = &lines[*pos];
-                
-                // TODO
-                *pos += 1;
-            
-                Ok(line.len())
-            }            
+            }
+        });
+
+        self.rx = Some(rx);
+    }
+}
+
+impl Read for TimedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pending_pos >= self.pending.len() {
+            self.spawn();
+            let Some(rx) = self.rx.as_ref() else { return Ok(0) };
+
+            match rx.recv_timeout(self.timeout) {
+                Ok(Ok(data)) => {
+                    self.pending = data;
+                    self.pending_pos = 0;
+                },
+                Ok(Err(e)) => return Err(e),
+                Err(RecvTimeoutError::Timeout) =>
+                    return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "read timed out")),
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            }
         }
+
+        let available = &self.pending[self.pending_pos..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.pending_pos += len;
+        Ok(len)
     }
 }
 
-impl std::fmt::Display for Source {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Source::File(s, _) => write!(f, "{s}"),
-            Source::Stdin(_) => write!(f, "stdin"),
-            #[cfg(test)]
-            Source::Mock(..) => write!(f, "mock"),
+// --raw-tty: the terminal on stdin stays in raw mode for as long as this lives, dropping it puts
+// back the settings from before, on a panic too
+#[cfg(unix)]
+struct RawTty {
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawTty {
+    // None when stdin isn't a terminal, there's nothing to put in raw mode then
+    fn enable() -> std::io::Result<Option<Self>> {
+        use std::io::IsTerminal;
+
+        if !std::io::stdin().is_terminal() {
+            return Ok(None);
+        }
+
+        let mut saved = std::mem::MaybeUninit::<libc::termios>::uninit();
+        // SAFETY: tcgetattr fills in the whole struct when it succeeds
+        let saved = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, saved.as_mut_ptr()) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            saved.assume_init()
+        };
+
+        let mut raw = saved;
+        // SAFETY: raw holds what tcgetattr filled in, cfmakeraw and tcsetattr go by that
+        if unsafe {
+            libc::cfmakeraw(&mut raw);
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw)
+        } == -1 {
+            return Err(std::io::Error::last_os_error());
         }
+
+        Ok(Some(Self { saved }))
     }
 }
 
-#[derive(Debug, Default)]
-pub struct RatArgs {
-    // display $ at end of each line
-    show_ends: bool,
-    // number all output lines
-    number_lines: bool,
-    // number nonempty output lines, overrides number_lines
-    number_nonblank: bool,
-    // suppress repeated empty output lines
-    squeeze_blank: bool,
-    // display TAB characters as ^I
-    show_tabs: bool,
-    // use ^ and M- notation, except for LFD and TAB
-    show_nonprinting: bool,
-    // sources to get data from
-    files: Vec<Source>,
+#[cfg(unix)]
+impl Drop for RawTty {
+    fn drop(&mut self) {
+        // SAFETY: saved is what tcgetattr returned for the same terminal
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved) };
+    }
+}
 
-    // overrides all arguments above...
-    version: bool, // show program version
-    help: bool, // show help message
+/// Ends the input at the first Ctrl-D (EOT) byte. A terminal in raw mode passes Ctrl-D on as a
+/// byte instead of ending the input with it, so `--raw-tty` does that here.
+#[cfg(unix)]
+struct EndAtEot<R> {
+    inner: R,
+    ended: bool,
 }
 
-impl RatArgs {
-    pub fn files(files: Vec<String>) -> Self {
-        let files = files.iter().map(|f| Source::File(f.to_string(), None)).collect();
-        Self {
-            files,
-            ..Self::default()
+#[cfg(unix)]
+impl<R: Read> EndAtEot<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, ended: false }
+    }
+}
+
+#[cfg(unix)]
+impl<R: Read> Read for EndAtEot<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.ended {
+            return Ok(0);
+        }
+
+        let size = self.inner.read(buf)?;
+        match buf[..size].iter().position(|&byte| byte == 0x04) {
+            Some(eot) => {
+                self.ended = true;
+                Ok(eot)
+            },
+            None => Ok(size),
         }
     }
+}
 
-    pub fn new(raw: Vec<String>) -> Self {
-        let slice = &raw[1..];
-        let mut rat_args = RatArgs::default();
+/// What every `-` replays with `--cache-stdin`. Standard input is read into memory as a whole
+/// the first time one of them is read, so it costs as much memory as there is input.
+struct StdinCache {
+    data: Rc<OnceCell<Vec<u8>>>,
+    pos: usize,
+    // --read-timeout, for filling the cache
+    timeout: Option<Duration>,
+}
 
-        // if no args provided - just use stdin as a source
-        if raw.len() == 1 {
-            rat_args.files.push(Source::Stdin(std::io::stdin()));
-            return rat_args;
+impl Read for StdinCache {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.data.get().is_none() {
+            let mut data = Vec::new();
+            match self.timeout {
+                Some(timeout) => TimedReader::new(std::io::stdin(), timeout).read_to_end(&mut data)?,
+                None => std::io::stdin().read_to_end(&mut data)?,
+            };
+            let _ = self.data.set(data);
         }
 
-        slice.iter().for_each(|arg| {
-            if arg.contains("--") && &arg[1..=2] == "--" {
-                match arg.as_str() {
-                    "--help" => 
-                        rat_args.help = true,
-                    
-                    "--version" => 
-                        rat_args.version = true,
+        let available = &self.data.get().unwrap()[self.pos..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+// descriptors left over for stdio, the pager and whatever else is open besides FILEs
+const OPEN_FILES_HEADROOM: usize = 16;
+
+// default --max-open-files, a little below the soft RLIMIT_NOFILE
+#[cfg(unix)]
+fn open_files_limit() -> Option<usize> {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    // SAFETY: getrlimit only writes the struct it's given
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return None;
+    }
+
+    let soft = usize::try_from(limit.rlim_cur).unwrap_or(usize::MAX);
+    Some(soft.saturating_sub(OPEN_FILES_HEADROOM).max(1))
+}
 
-                    "--show-tabs" => 
-                        rat_args.show_tabs = true,
+#[cfg(not(unix))]
+fn open_files_limit() -> Option<usize> {
+    None
+}
 
-                    "--number" => 
-                        rat_args.number_lines = true,
+// --fd: an inherited descriptor as a FILE named "fd N", which has to be open already since a
+// closed number could get reused by whatever rat opens next. The source reads a duplicate, the
+// descriptor itself stays open and its owner's; 0 to 2 are rat's own standard streams
+#[cfg(unix)]
+fn fd_source(fd: i32) -> Result<Source, RatError> {
+    use std::os::unix::io::BorrowedFd;
 
-                    "--number-nonblank" => 
-                        rat_args.number_nonblank = true,
+    if fd <= 2 {
+        return Err(format!("--fd={fd} is a standard stream, use - for standard input").into());
+    }
 
-                    "--show-ends" => 
-                        rat_args.show_ends = true,
+    let name = format!("fd {fd}");
+    // SAFETY: F_GETFD only reads the descriptor's flags, failing for one that isn't open
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return Err(RatError::OpenFailed { path: name, source: std::io::Error::last_os_error() });
+    }
 
-                    "--show-nonprinting" => 
-                        rat_args.show_nonprinting = true,
+    // SAFETY: the descriptor is open, and only borrowed for as long as it takes to duplicate it
+    let file = unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned()
+        .map_err(|source| RatError::OpenFailed { path: name.clone(), source })?;
+    Ok(Source::File(name, Some(file.into())))
+}
 
-                    "--squeeze-blank" =>
-                        rat_args.squeeze_blank = true,
+// how much of a source --detect-binary and --text-only look at
+const BINARY_SAMPLE: usize = 4 * 1024;
 
-                    "--show-all" => {
-                        rat_args.show_nonprinting = true;
-                        rat_args.show_ends = true;
-                        rat_args.show_tabs = true;
-                    },
+// percentage of control characters that makes a sample binary without --binary-threshold
+const BINARY_THRESHOLD: usize = 30;
 
-                    _ => {} // TODO: output some warning message, maybe?
-                }
-            } else if arg == "-" && arg.len() == 1 {
-                // stdin source is here baby
-                rat_args.files.push(Source::Stdin(std::io::stdin()));
-            } else if arg.contains("-") && arg.chars().nth(0).unwrap() == '-' {
-                // get all chars as vec
-                let chars = arg[1..].chars();
-                chars.for_each(|c| {
-                    match c {
-                        'b' =>
-                            rat_args.number_nonblank = true,
-                        
-                        'E' =>
-                            rat_args.show_ends = true,
-
-                        'n' => 
-                            rat_args.number_lines = true,
-
-                        's' => 
-                            rat_args.squeeze_blank = true,
-
-                        'T' =>
-                            rat_args.show_tabs = true,
-                        
-                        'u' => 
-                            todo!(), // tf is this?
-                        
-                        'v' =>
-                            rat_args.show_nonprinting = true,
-                        
-                        't' => {
-                            rat_args.show_tabs = true;
-                            rat_args.show_nonprinting = true;
-                        },
+// NULs give binaries away, otherwise more than `threshold` percent of control characters do.
+// Bytes >= 128 don't count since they are all over UTF-8 text
+fn looks_binary(sample: &[u8], threshold: usize) -> bool {
+    let sample = &sample[..sample.len().min(BINARY_SAMPLE)];
+    if sample.contains(&0) {
+        return true;
+    }
 
-                        'e' => {
-                            rat_args.show_nonprinting = true;
-                            rat_args.show_ends = true;
-                        },
+    let control = sample.iter()
+        .filter(|&&b| (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b)) || b == 0x7f)
+        .count();
+    control * 100 > sample.len() * threshold
+}
 
-                        'A' => {
-                            rat_args.show_nonprinting = true;
-                            rat_args.show_ends = true;
-                            rat_args.show_tabs = true;
-                        },
+/// Turns CRLF and lone CR into LF for `--normalize-newlines`, the output then writes every LF
+/// as the chosen line end. A CR ending a read waits for the next one to see if an LF follows.
+#[derive(Default)]
+struct NewlineNormalizer {
+    pending_cr: bool,
+}
+
+impl NewlineNormalizer {
+    // normalizes `input` onto the end of `out`, an empty `input` is the end of the source
+    fn feed(&mut self, input: &[u8], out: &mut Vec<u8>) {
+        if input.is_empty() && std::mem::take(&mut self.pending_cr) {
+            out.push(b'\n');
+        }
 
-                        _ => {}
+        for &byte in input {
+            match byte {
+                b'\r' => {
+                    if std::mem::replace(&mut self.pending_cr, true) {
+                        out.push(b'\n');
                     }
-                });
-            } else {
-                rat_args.files
-                    .push(Source::File(arg.into(), None));
+                },
+                // a CRLF is a single line end
+                b'\n' => {
+                    self.pending_cr = false;
+                    out.push(b'\n');
+                },
+                _ => {
+                    if std::mem::take(&mut self.pending_cr) {
+                        out.push(b'\n');
+                    }
+                    out.push(byte);
+                },
             }
-        });
-
-        rat_args
+        }
     }
 }
 
-#[derive(Debug)]
-pub struct Rat<T: Write> {
-    args: RatArgs,
-    write_to: T,
+/// Does the `--replace`s, each one on what the ones before it wrote. Matches don't overlap and are
+/// found left to right, like `str::replace`; the end of what a replacement was fed is held back as long
+/// as a match could start in it and go on in the next read.
+struct Replace<'a> {
+    // FROM, TO and the bytes held back, for every replacement
+    steps: Vec<(&'a [u8], &'a [u8], Vec<u8>)>,
 }
 
-impl<T: Write> Rat<T> {
-    pub fn new(args: RatArgs, write_to: T) -> Self {
-        Self { args, write_to }
+impl<'a> Replace<'a> {
+    fn new(replacements: &'a [(Vec<u8>, Vec<u8>)]) -> Self {
+        Self { steps: replacements.iter().map(|(from, to)| (&from[..], &to[..], Vec::new())).collect() }
     }
 
-    pub fn exec(mut self) -> Self {
-        let args = &mut self.args;
+    // writes `input` with the replacements done to `out`, the `end` of the source lets go of
+    // everything held back
+    fn feed(&mut self, input: &[u8], end: bool, out: &mut Vec<u8>) {
+        let mut current = input.to_vec();
 
-        if args.help {
-            println!("{}", RAT_USAGE);
-            return self;
-        }
+        for (from, to, held) in self.steps.iter_mut() {
+            held.extend_from_slice(&current);
+            current.clear();
 
-        if args.version {
-            println!("{} {}", RAT_NAME, RAT_VERSION);
-            return self;
+            let (mut start, mut at) = (0, 0);
+            while at + from.len() <= held.len() {
+                if held[at..].starts_with(from) {
+                    current.extend_from_slice(&held[start..at]);
+                    current.extend_from_slice(to);
+                    at += from.len();
+                    start = at;
+                } else {
+                    at += 1;
+                }
+            }
+
+            // no match starts before the last FROM.len() - 1 bytes anymore
+            let keep = match end {
+                true => held.len(),
+                false => start.max((held.len() + 1).saturating_sub(from.len())),
+            };
+            current.extend_from_slice(&held[start..keep]);
+            held.drain(..keep);
         }
 
-        let mut index = 1u64;
+        out.append(&mut current);
+    }
+}
 
-        let mut prev_byte = b'\n';
-        let mut buf = [0u8; IO_BUFSIZE];
+/// Finds the `--stop-at` line, passing on what comes before it. The start of a line that could
+/// still turn out to be the marker is held back until its line end shows whether it is.
+struct StopAt<'a> {
+    marker: &'a [u8],
+    include_marker: bool,
+    // the current line so far, while it matches the start of the marker
+    held: Vec<u8>,
+    // false once the current line is known not to be the marker
+    candidate: bool,
+}
 
-        // i should explain now, this one exists because of -s flag
-        // in original cat.c its logic implented via counting newlines, but i think this is more simple
-        let mut prev_prev_byte = b' ';
+impl<'a> StopAt<'a> {
+    fn new(marker: &'a [u8], include_marker: bool) -> Self {
+        Self { marker, include_marker, held: Vec::new(), candidate: true }
+    }
 
-        for source in self.args.files.iter_mut() {
-            loop {
-                match source.read_to_buf(&mut buf) {
-                    Ok(0) => break,
-                    Ok(size) => {
-                        let mut out_buf = [0u8; IO_BUFSIZE];
-                        let mut out_pos = 0;
-                        for byte in &mut buf[..size] {
-                            if out_pos >= out_buf.len() {
-                                self.write_to.write_all(&out_buf[..out_pos]).unwrap();
-                                out_pos = 0; // Reset after flush
-                            }
-        
-                            if self.args.squeeze_blank && *byte == b'\n' && prev_byte == b'\n' && prev_prev_byte == b'\n' {
-                                continue;
-                            }
-                            if ((self.args.number_lines && !self.args.number_nonblank) || (self.args.number_nonblank && *byte != b'\n')) && prev_byte == b'\n' {
-                                let num = format!("{index:6} ");
-                                out_buf[out_pos..out_pos + num.len()].copy_from_slice(num.as_bytes());
-                                out_pos += num.len();
-                                index += 1;
-                            }
-        
-                            if self.args.show_nonprinting {
-                                if *byte >= 128 {
-                                    out_buf[out_pos..out_pos + 2].copy_from_slice(b"M-");
-                                    out_pos += 2;
-                                    *byte -= 128;
-                                }
-        
-                                if *byte < 32 || *byte == 127 {
-                                    out_buf[out_pos] = b'^';
-                                    out_buf[out_pos + 1] = *byte ^ 0x40;
-                                    out_pos += 2;
-                                    continue;
-                                }
-                            }
-        
-                            if self.args.show_tabs && *byte == b'\t' {
-                                out_buf[out_pos..out_pos + 2].copy_from_slice(b"^I");
-                                out_pos += 2;
-                            } else {
-                                out_buf[out_pos] = *byte;
-                                out_pos += 1;
-                            }
-        
-                            prev_prev_byte = prev_byte;
-                            prev_byte = *byte;
-                        }
-                        self.write_to.write_all(&out_buf[..out_pos]).unwrap();
+    // passes `input` on to `out` up to the marker line, true once that was found; at the `end` of
+    // the source an unfinished last line counts too
+    fn feed(&mut self, mut input: &[u8], end: bool, out: &mut Vec<u8>) -> bool {
+        while !input.is_empty() {
+            let newline = input.iter().position(|&byte| byte == b'\n');
+            let line = &input[..newline.unwrap_or(input.len())];
+
+            if self.candidate {
+                let rest = &self.marker[self.held.len()..];
+                if newline.is_some() && line == rest {
+                    if self.include_marker {
+                        out.append(&mut self.held);
+                        out.extend_from_slice(line);
+                        out.push(b'\n');
                     }
-                    Err(_) => break,
+                    return true;
                 }
+                if newline.is_none() && rest.starts_with(line) {
+                    self.held.extend_from_slice(line);
+                    break;
+                }
+                out.append(&mut self.held);
+            }
+
+            match newline {
+                Some(newline) => {
+                    out.extend_from_slice(&input[..=newline]);
+                    input = &input[newline + 1..];
+                    self.candidate = true;
+                },
+                None => {
+                    out.extend_from_slice(input);
+                    self.candidate = false;
+                    break;
+                },
             }
         }
-        self
+
+        if !end {
+            return false;
+        }
+        let found = self.candidate && !self.held.is_empty() && self.held == self.marker;
+        if !found || self.include_marker {
+            out.append(&mut self.held);
+        }
+        found
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Incremental UTF-8 validation for `--check-utf8`, sequences may span several `feed` calls.
+#[derive(Default)]
+struct Utf8Check {
+    // bytes fed so far
+    offset: u64,
+    // where the sequence being decoded started
+    seq_start: u64,
+    // continuation bytes still expected and the range the next one must be in
+    need: u8,
+    lower: u8,
+    upper: u8,
+    invalid_at: Option<u64>,
+}
+
+impl Utf8Check {
+    fn feed(&mut self, bytes: &[u8]) {
+        if self.invalid_at.is_some() {
+            return;
+        }
+
+        for &byte in bytes {
+            if self.need == 0 {
+                // ranges from table 3-7 of the unicode standard, rejects overlongs and surrogates
+                let (need, lower, upper) = match byte {
+                    0x00..=0x7f => (0, 0, 0),
+                    0xc2..=0xdf => (1, 0x80, 0xbf),
+                    0xe0 => (2, 0xa0, 0xbf),
+                    0xe1..=0xec | 0xee..=0xef => (2, 0x80, 0xbf),
+                    0xed => (2, 0x80, 0x9f),
+                    0xf0 => (3, 0x90, 0xbf),
+                    0xf1..=0xf3 => (3, 0x80, 0xbf),
+                    0xf4 => (3, 0x80, 0x8f),
+                    _ => {
+                        self.invalid_at = Some(self.offset);
+                        return;
+                    }
+                };
+                self.seq_start = self.offset;
+                (self.need, self.lower, self.upper) = (need, lower, upper);
+            } else if (self.lower..=self.upper).contains(&byte) {
+                self.need -= 1;
+                (self.lower, self.upper) = (0x80, 0xbf);
+            } else {
+                self.invalid_at = Some(self.seq_start);
+                return;
+            }
+
+            self.offset += 1;
+        }
+    }
+
+    // offset of the first invalid sequence, a sequence cut short by EOF counts too when there was one
+    fn finish(&self, at_eof: bool) -> Option<u64> {
+        self.invalid_at.or((at_eof && self.need > 0).then_some(self.seq_start))
+    }
+}
+
+// decodes the \t, \n, \r, \0 and \\ escapes of an option value, anything else stays as is
+fn unescape(value: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            out.push(byte);
+            continue;
+        }
+
+        match bytes.next() {
+            Some(b't') => out.push(b'\t'),
+            Some(b'n') => out.push(b'\n'),
+            Some(b'r') => out.push(b'\r'),
+            Some(b'0') => out.push(0),
+            Some(b'\\') => out.push(b'\\'),
+            Some(other) => out.extend_from_slice(&[b'\\', other]),
+            None => out.push(b'\\'),
+        }
+    }
+
+    out
+}
+
+// FROM/TO of --replace, split at the first / that isn't escaped as \/
+fn parse_replacement(value: &str) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let mut from = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' => {
+                if from.is_empty() {
+                    return Err(format!("invalid replacement '{value}', FROM cannot be empty"));
+                }
+                return Ok((unescape(&from), unescape(chars.as_str())));
+            },
+            // the other escapes are left for unescape
+            '\\' => match chars.next() {
+                Some('/') => from.push('/'),
+                Some(other) => from.extend(['\\', other]),
+                None => from.push('\\'),
+            },
+            c => from.push(c),
+        }
+    }
+    Err(format!("invalid replacement '{value}', expected FROM/TO"))
+}
+
+// splits RAT_OPTIONS into words the way a shell would, minus everything but quoting
+fn split_words(value: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => match chars.next() {
+                Some(escaped @ ('"' | '\\')) => word.push(escaped),
+                Some(other) => word.extend(['\\', other]),
+                None => return Err("unterminated quote in RAT_OPTIONS".into()),
+            },
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            },
+            (None, '\\') => {
+                word.extend(chars.next());
+                in_word = true;
+            },
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            },
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err("unterminated quote in RAT_OPTIONS".into());
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+// positive amount of seconds, fractions allowed
+fn parse_timeout(name: &str, value: Option<&str>) -> Result<Duration, String> {
+    let value = value.ok_or_else(|| format!("option '{name}' requires an argument"))?;
+    value.parse::<f64>().ok()
+        .filter(|secs| secs.is_finite() && *secs > 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| format!("invalid timeout '{value}'"))
+}
+
+// positive whole number
+fn parse_count(name: &str, value: Option<&str>) -> Result<usize, String> {
+    let value = value.ok_or_else(|| format!("option '{name}' requires an argument"))?;
+    value.parse::<usize>().ok()
+        .filter(|n| *n > 0)
+        .ok_or_else(|| format!("invalid value '{value}' for '{name}'"))
+}
+
+// a --fields list like 1,3-5,7-, the ranges 1-based and inclusive, an open end as usize::MAX
+fn parse_fields(value: Option<&str>) -> Result<Vec<std::ops::RangeInclusive<usize>>, String> {
+    let value = value.ok_or("option '--fields' requires an argument")?;
+    let field = |n: &str| n.parse::<usize>().ok().filter(|&n| n > 0);
+
+    value.split(',').map(|part| {
+        let range = match part.split_once('-') {
+            None => field(part).map(|n| n..=n),
+            Some(("", end)) => field(end).map(|end| 1..=end),
+            Some((start, "")) => field(start).map(|start| start..=usize::MAX),
+            Some((start, end)) => field(start).zip(field(end)).map(|(start, end)| start..=end),
+        };
+        range.filter(|range| !range.is_empty()).ok_or_else(|| format!("invalid field list '{value}'"))
+    }).collect()
+}
+
+// --transform-order: the stages named first, then the rest in their usual order
+fn parse_transform_order(value: Option<&str>) -> Result<[Stage; 5], String> {
+    let value = value.ok_or("option '--transform-order' requires an argument")?;
+    let mut order = Vec::with_capacity(Stage::DEFAULT_ORDER.len());
+    for name in value.split(',') {
+        let stage = Stage::parse(name)
+            .ok_or_else(|| format!("invalid stage '{name}', expected squeeze, nonprinting, tabs, number or ends"))?;
+        if order.contains(&stage) {
+            return Err(format!("stage '{name}' is named twice in '--transform-order'"));
+        }
+        order.push(stage);
+    }
+
+    for stage in Stage::DEFAULT_ORDER {
+        if !order.contains(&stage) {
+            order.push(stage);
+        }
+    }
+    Ok(order.try_into().unwrap())
+}
+
+// --fields: the chosen fields of a line in the order they were asked for, the ones it doesn't
+// have left out
+fn select_fields(line: &[u8], fields: &[std::ops::RangeInclusive<usize>], delimiter: &[u8]) -> Vec<u8> {
+    let (content, newline) = match line.strip_suffix(b"\n") {
+        Some(content) => (content, &b"\n"[..]),
+        None => (line, &b""[..]),
+    };
+
+    let mut split = Vec::new();
+    let mut rest = content;
+    while let Some(at) = rest.windows(delimiter.len()).position(|window| window == delimiter) {
+        split.push(&rest[..at]);
+        rest = &rest[at + delimiter.len()..];
+    }
+    split.push(rest);
+
+    let mut selected = Vec::with_capacity(line.len());
+    let chosen = fields.iter().flat_map(|range| {
+        let end = (*range.end()).min(split.len());
+        split.get(range.start() - 1..end).unwrap_or_default()
+    });
+    for (i, field) in chosen.enumerate() {
+        if i > 0 {
+            selected.extend_from_slice(delimiter);
+        }
+        selected.extend_from_slice(field);
+    }
+    selected.extend_from_slice(newline);
+    selected
+}
+
+/// Everything that can make [`RatArgs::new`] or [`Rat::exec`] fail.
+#[derive(Debug)]
+pub enum RatError {
+    /// A source couldn't be opened.
+    OpenFailed { path: String, source: std::io::Error },
+    /// A source failed while being read.
+    ReadFailed { path: String, source: std::io::Error },
+    /// The output (or stderr) couldn't be written.
+    WriteFailed { source: std::io::Error },
+    /// A source is a directory, there's nothing to read.
+    IsDirectory { path: String },
+    /// The command line makes no sense.
+    Parse { message: String },
+    /// More files would have to be open at once than `--max-open-files` allows for `mode`.
+    TooManyOpenFiles { mode: &'static str, needed: usize, limit: usize },
+    /// More sources were given than `--max-sources` allows.
+    TooManySources { count: usize, limit: usize },
+    /// A mode that keeps input in memory would keep more than `--max-memory` allows.
+    MemoryLimit { limit: u64 },
+    /// `--text-only` came across a source that looks binary.
+    BinaryFile { path: String },
+    /// `--on-empty=error` came across a source without a single byte.
+    EmptySource { path: String },
+    /// The `--checkpoint` FILE couldn't be read or written.
+    Checkpoint { path: String, source: std::io::Error },
+    /// `--resume` couldn't take the output back to where the checkpoint says the copy got to.
+    Resume { source: std::io::Error },
+}
+
+impl RatError {
+    // a source that failed, either while opening it or later on
+    fn from_source(path: String, e: std::io::Error, opening: bool) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::IsADirectory => RatError::IsDirectory { path },
+            _ if opening => RatError::OpenFailed { path, source: e },
+            _ => RatError::ReadFailed { path, source: e },
+        }
+    }
+
+    // the output, or stderr, that couldn't be written
+    fn from_write(source: std::io::Error) -> Self {
+        RatError::WriteFailed { source }
+    }
+
+    /// The status to exit with, 2 for a mistake on the command line or when nothing was read because
+    /// of a safety limit.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RatError::Parse { .. } | RatError::TooManySources { .. } => 2,
+            _ => 1,
+        }
+    }
+
+    /// Whether this is a mistake on the command line, worth pointing at `--help` for.
+    pub fn is_usage(&self) -> bool {
+        matches!(self, RatError::Parse { .. })
+    }
+}
+
+impl std::fmt::Display for RatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RatError::OpenFailed { path, source } | RatError::ReadFailed { path, source } =>
+                write!(f, "{path}: {}", io_error_message(source)),
+            RatError::WriteFailed { source } => write!(f, "write error: {}", io_error_message(source)),
+            RatError::IsDirectory { path } => write!(f, "{path}: Is a directory"),
+            RatError::Parse { message } => f.write_str(message),
+            RatError::TooManyOpenFiles { mode, needed, limit } =>
+                write!(f, "{mode} needs {needed} files open at once, but only {limit} may be"),
+            RatError::TooManySources { count, limit } => write!(f, "too many input sources ({count} > {limit})"),
+            RatError::MemoryLimit { .. } => f.write_str("input exceeds memory limit"),
+            RatError::BinaryFile { path } => write!(f, "{path}: binary file detected"),
+            RatError::EmptySource { path } => write!(f, "{path}: empty"),
+            RatError::Checkpoint { path, source } => write!(f, "checkpoint {path}: {}", io_error_message(source)),
+            RatError::Resume { source } => write!(f, "cannot resume the output: {}", io_error_message(source)),
+        }
+    }
+}
+
+impl std::error::Error for RatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RatError::OpenFailed { source, .. } | RatError::ReadFailed { source, .. } | RatError::WriteFailed { source }
+                | RatError::Checkpoint { source, .. } | RatError::Resume { source } => Some(source),
+            RatError::IsDirectory { .. } | RatError::Parse { .. } | RatError::TooManyOpenFiles { .. }
+                | RatError::TooManySources { .. } | RatError::MemoryLimit { .. } | RatError::BinaryFile { .. }
+                | RatError::EmptySource { .. } => None,
+        }
+    }
+}
+
+impl From<String> for RatError {
+    fn from(message: String) -> Self {
+        RatError::Parse { message }
+    }
+}
+
+impl From<&str> for RatError {
+    fn from(message: &str) -> Self {
+        RatError::Parse { message: message.into() }
+    }
+}
+
+// coreutils style message for an io error, without rust's "(os error N)" suffix
+fn io_error_message(e: &std::io::Error) -> String {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => "No such file or directory".into(),
+        std::io::ErrorKind::PermissionDenied => "Permission denied".into(),
+        std::io::ErrorKind::IsADirectory => "Is a directory".into(),
+        _ => {
+            let message = e.to_string();
+            match message.find(" (os error") {
+                Some(end) => message[..end].to_string(),
+                None => message,
+            }
+        }
+    }
+}
+
+enum Source {
+    File(String, Option<std::fs::File>),
+    Stdin(std::io::Stdin),
+    TimedStdin(TimedReader),
+    CachedStdin(StdinCache),
+    #[cfg(unix)]
+    RawStdin(EndAtEot<std::io::Stdin>),
+    // read as a whole when it's opened
+    #[cfg(feature = "clipboard")]
+    Clipboard(Option<std::io::Cursor<Vec<u8>>>),
+    #[cfg(test)]
+    Mock(Box<dyn Read>),
+}
+
+// opening a FIFO blocks until somebody opens it for writing, so it's done on another thread
+// and `waiting` is called when that takes long enough for the user to wonder what's going on
+#[cfg(unix)]
+fn open_fifo(path: &str, timeout: Option<Duration>, waiting: &mut dyn FnMut()) -> std::io::Result<std::fs::File> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let owned_path = path.to_string();
+    std::thread::spawn(move || {
+        let _ = tx.send(std::fs::File::open(owned_path));
+    });
+
+    let notice_after = timeout.map_or(FIFO_NOTICE_AFTER, |timeout| timeout.min(FIFO_NOTICE_AFTER));
+    if let Ok(result) = rx.recv_timeout(notice_after) {
+        return result;
+    }
+    waiting();
+
+    let result = match timeout {
+        Some(timeout) => rx.recv_timeout(timeout.saturating_sub(notice_after)).ok(),
+        None => rx.recv().ok(),
+    };
+    result.unwrap_or_else(|| Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out waiting for a writer")))
+}
+
+// named pipes get a notice if they keep us waiting, which calls them `name`
+fn open_file<E: Write>(path: &str, name: &str, fifo_timeout: Option<Duration>, err_to: &mut E) -> std::io::Result<std::fs::File> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if std::fs::metadata(path).is_ok_and(|meta| meta.file_type().is_fifo()) {
+            let mut waiting = || {
+                let _ = writeln!(err_to, "{RAT_NAME}: {name}: waiting for data");
+            };
+            return open_fifo(path, fifo_timeout, &mut waiting);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (name, fifo_timeout, err_to);
+
+    std::fs::File::open(path)
+}
+
+impl Source {
+    // opens a file source ahead of reading it, with --retry-open a missing file is waited for,
+    // checking again after 10ms, 20ms, 40ms and so on up to a second between tries
+    fn open<E: Write>(&mut self, args: &RatArgs, err_to: &mut E) -> std::io::Result<()> {
+        #[cfg(feature = "clipboard")]
+        if let Source::Clipboard(contents @ None) = self {
+            *contents = Some(std::io::Cursor::new(clipboard::read()?));
+            return Ok(());
+        }
+
+        let name = args.source_name(self);
+        let Source::File(path, file_option @ None) = self else { return Ok(()) };
+
+        let deadline = args.retry_open.map(|retry| std::time::Instant::now() + retry);
+        let mut backoff = RETRY_OPEN_FIRST;
+        loop {
+            let left = deadline.map_or(Duration::ZERO, |deadline| deadline.saturating_duration_since(std::time::Instant::now()));
+            match open_file(path, &name, args.fifo_timeout, err_to) {
+                // anything but a missing file, like a permission problem, won't go away by waiting
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && !left.is_zero() => {
+                    std::thread::sleep(backoff.min(left));
+                    backoff = (backoff * 2).min(RETRY_OPEN_MAX);
+                },
+                result => {
+                    *file_option = Some(result?);
+                    trace_event!(debug, source = %name, "opened source");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn read_to_buf(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        let read = self.read_chunk(buf);
+        trace_event!(trace, source = %self, ?read, "read");
+        read
+    }
+
+    fn read_chunk(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        match self {
+            Source::File(path, file_option) => {
+                if file_option.is_none() {
+                    let file = std::fs::File::open(&*path)?;
+                    *file_option = Some(file);
+                    trace_event!(debug, source = %path, "opened source");
+                }
+
+                let file = file_option.as_mut().unwrap();
+
+                let bytes_read = file.read(buf)?;
+                Ok(bytes_read)
+            }
+            Source::Stdin(stdin) => {
+                let bytes_read = stdin.read(buf)?;
+    
+                if bytes_read == 0 {
+                    return Ok(0); // Properly handle EOF
+                }
+
+                Ok(bytes_read)
+            },
+            Source::TimedStdin(reader) => reader.read(buf),
+            Source::CachedStdin(cache) => cache.read(buf),
+            #[cfg(unix)]
+            Source::RawStdin(stdin) => stdin.read(buf),
+            #[cfg(feature = "clipboard")]
+            Source::Clipboard(contents) => {
+                if contents.is_none() {
+                    *contents = Some(std::io::Cursor::new(clipboard::read()?));
+                }
+
+                contents.as_mut().unwrap().read(buf)
+            },
+            #[cfg(test)]
+            Source::Mock(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl Source {
+    // read_to_buf, tried again up to `retries` times after errors that may go away by themselves,
+    // waiting 10ms before the first retry and twice as long before each next one, up to a second
+    fn read_retrying(&mut self, buf: &mut [u8], retries: usize) -> std::io::Result<usize> {
+        let mut backoff = RETRY_OPEN_FIRST;
+        let mut left = retries;
+        loop {
+            match self.read_to_buf(buf) {
+                Err(e) if left > 0 && self.is_transient(&e) => {
+                    std::thread::sleep(backoff);
+                    backoff = (backoff * 2).min(RETRY_OPEN_MAX);
+                    left -= 1;
+                },
+                result => return result,
+            }
+        }
+    }
+
+    // whether a read that failed with `e` may work when tried again, --read-timeout expiring is final though
+    fn is_transient(&self, e: &std::io::Error) -> bool {
+        match e.kind() {
+            std::io::ErrorKind::TimedOut => !self.is_timed_stdin(),
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted => true,
+            _ => false,
+        }
+    }
+
+    // moves a FILE to `offset`, for --resume, the other sources can't go anywhere but forward
+    fn seek_to(&mut self, offset: u64) -> std::io::Result<()> {
+        match self {
+            Source::File(_, Some(file)) => file.seek(std::io::SeekFrom::Start(offset)).map(|_| ()),
+            _ => Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "cannot seek")),
+        }
+    }
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.read_to_buf(buf)
+    }
+}
+
+impl std::fmt::Debug for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::File(path, file) => f.debug_tuple("File").field(path).field(file).finish(),
+            Source::Stdin(stdin) => f.debug_tuple("Stdin").field(stdin).finish(),
+            Source::TimedStdin(reader) => f.debug_tuple("TimedStdin").field(&reader.timeout).finish(),
+            Source::CachedStdin(cache) => f.debug_tuple("CachedStdin").field(&cache.pos).finish(),
+            #[cfg(unix)]
+            Source::RawStdin(stdin) => f.debug_tuple("RawStdin").field(&stdin.ended).finish(),
+            #[cfg(feature = "clipboard")]
+            Source::Clipboard(contents) => f.debug_tuple("Clipboard").field(contents).finish(),
+            #[cfg(test)]
+            Source::Mock(_) => f.write_str("Mock"),
+        }
+    }
+}
+
+impl Source {
+    fn is_stdin(&self) -> bool {
+        match self {
+            Source::Stdin(_) | Source::TimedStdin(_) | Source::CachedStdin(_) => true,
+            #[cfg(unix)]
+            Source::RawStdin(_) => true,
+            _ => false,
+        }
+    }
+
+    // the name of a FILE as --quote-names shows it, the other sources have names that need no quoting
+    fn quoted_name(&self) -> String {
+        match self {
+            Source::File(path, _) => shell_quote(path),
+            _ => self.to_string(),
+        }
+    }
+
+    // whether a TimedOut error is --read-timeout expiring
+    fn is_timed_stdin(&self) -> bool {
+        match self {
+            Source::TimedStdin(_) => true,
+            Source::CachedStdin(cache) => cache.timeout.is_some(),
+            _ => false,
+        }
+    }
+}
+
+// `name` the way coreutils' shell-escape quoting style shows it: as is when nothing in it is special to a shell,
+// in single quotes otherwise, with runs of control characters in $'...' between them
+fn shell_quote(name: &str) -> String {
+    let plain = |c: char| c.is_alphanumeric() || "%+,-./:=@_^".contains(c);
+    if !name.is_empty() && name.chars().all(plain) {
+        return name.to_string();
+    }
+
+    let mut quoted = String::new();
+    // which quotes are open, ' or $'
+    let mut open = None;
+    for c in name.chars() {
+        let wanted = match c.is_control() {
+            true => "$'",
+            false => "'",
+        };
+        if open != Some(wanted) {
+            if open.is_some() {
+                quoted.push('\'');
+            }
+            quoted.push_str(wanted);
+            open = Some(wanted);
+        }
+        match c {
+            '\'' => quoted.push_str("'\\''"),
+            '\x07' => quoted.push_str("\\a"),
+            '\x08' => quoted.push_str("\\b"),
+            '\x0c' => quoted.push_str("\\f"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '\x0b' => quoted.push_str("\\v"),
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\{:03o}", c as u32)),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    match open {
+        Some(_) => quoted.push('\''),
+        None => quoted.push_str("''"),
+    }
+    quoted
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::File(s, _) => write!(f, "{s}"),
+            Source::Stdin(_) | Source::TimedStdin(_) | Source::CachedStdin(_) => write!(f, "{STDIN_NAME}"),
+            #[cfg(unix)]
+            Source::RawStdin(_) => write!(f, "{STDIN_NAME}"),
+            #[cfg(feature = "clipboard")]
+            Source::Clipboard(_) => write!(f, "clipboard"),
+            #[cfg(test)]
+            Source::Mock(..) => write!(f, "mock"),
+        }
+    }
+}
+
+// how --count-matching reports
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CountMatching {
+    Total,
+    PerFile,
+}
+
+// the line ends --normalize-newlines writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Newline {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl Newline {
+    fn bytes(self) -> &'static [u8] {
+        match self {
+            Newline::Lf => b"\n",
+            Newline::CrLf => b"\r\n",
+            Newline::Cr => b"\r",
+        }
+    }
+}
+
+// which repeated lines --uniq and --uniq-global drop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Uniq {
+    Adjacent,
+    Global,
+}
+
+// what to do about a FILE that doesn't exist
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum OnMissing {
+    // like any other FILE that can't be read: a message, the next FILE, exit status 1
+    #[default]
+    Report,
+    Skip,
+    Error,
+    Empty,
+}
+
+// what --frame makes a frame of
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum FrameUnit {
+    #[default]
+    Source,
+    Line,
+}
+
+// what to do about a source without a single byte
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum OnEmpty {
+    #[default]
+    Ignore,
+    Warn,
+    Error,
+}
+
+#[derive(Debug)]
+pub struct RatArgs {
+    // drop every option changing the output once they're all parsed
+    plain: bool,
+    // display $ at end of each line
+    show_ends: bool,
+    // and at the end of a source without a final newline
+    show_ends_eof: bool,
+    // write newlines as NUL
+    null_output: bool,
+    // line ends are normalized on input and written as this
+    normalize_newlines: Option<Newline>,
+    // what ends lines instead of newlines, and is written as the line end
+    record_delimiter: Option<Vec<u8>>,
+    // what show_ends puts before each newline
+    end_marker: Vec<u8>,
+    // number all output lines
+    number_lines: bool,
+    // start every line with "# " and end the output with a line end
+    tap: bool,
+    // map the letters of the input to one case
+    fold_case: Option<Case>,
+    // first line number
+    number_start: u64,
+    // highest line number, and what comes after it
+    max_line_number: Option<u64>,
+    number_overflow: Option<Overflow>,
+    // how line numbers are written
+    number_radix: Radix,
+    // start lines with their offset in the input, which makes the output line oriented
+    byte_offset: Option<Radix>,
+    // number nonempty output lines, overrides number_lines
+    number_nonblank: bool,
+    // number only lines matching this, overrides both above
+    #[cfg(feature = "regex")]
+    number_matching: Option<regex::bytes::Regex>,
+    // only lines matching this are written
+    #[cfg(feature = "regex")]
+    grep: Option<regex::bytes::Regex>,
+    // count the lines grep matches instead of writing them
+    #[cfg(feature = "regex")]
+    count_matching: Option<CountMatching>,
+    // lines around the ones grep matches that are written too
+    #[cfg(feature = "regex")]
+    context: Option<usize>,
+    // suppress repeated empty output lines
+    squeeze_blank: bool,
+    // squeeze_blank, treating lines of only spaces and tabs as empty
+    squeeze_whitespace: bool,
+    // start squeezing over with every source instead of running on across them like cat
+    squeeze_per_source: bool,
+    // drop repeated lines
+    uniq: Option<Uniq>,
+    // end lines with the CRC-32 of their content
+    #[cfg(feature = "crc32")]
+    checksum_per_line: bool,
+    // squeeze runs of spaces into one
+    collapse_spaces: bool,
+    // drop spaces and tabs right before every newline
+    strip_trailing_whitespace: bool,
+    // write only these fields of every line, split at and joined with field_delimiter (TAB when empty)
+    fields: Option<Vec<std::ops::RangeInclusive<usize>>>,
+    field_delimiter: Vec<u8>,
+    // write only the lines whose number divided by sample leaves sample_offset
+    sample: Option<u64>,
+    sample_offset: u64,
+    // display TAB characters as ^I
+    show_tabs: bool,
+    // display TAB characters as these bytes instead, wins over show_tabs
+    tab_replacement: Option<Vec<u8>>,
+    // display TAB characters as spaces up to the next tab stop, the other tab options win
+    expand_tabs: bool,
+    // columns between tab stops, for everything that needs them
+    tab_size: usize,
+    // the order the display options render in, Stage::DEFAULT_ORDER when unset
+    transform_order: Option<[Stage; 5]>,
+    // glyphs for line endings and tabs, wins over show_ends and the tab options
+    reveal: Option<Reveal>,
+    // use ^ and M- notation, except for LFD and TAB
+    show_nonprinting: bool,
+    // replace bytes >= 128 with ascii_placeholder, excludes show_nonprinting and from_encoding
+    ascii_only: bool,
+    ascii_placeholder: Vec<u8>,
+    // transcode bytes >= 128 from this encoding to UTF-8, excludes show_nonprinting
+    #[cfg(feature = "encoding")]
+    from_encoding: Option<Encoding>,
+    // decode the input as base64 before anything else sees it
+    #[cfg(feature = "base64")]
+    base64_decode: bool,
+    // decompress sources that start like gzip, after base64_decode
+    #[cfg(feature = "gzip")]
+    gzip: bool,
+    // replaces the display options like hexdump, its lines wrapped after `wrap` characters
+    #[cfg(feature = "base64")]
+    base64_encode: bool,
+    // break lines after this many bytes, 0 for never, base64_encode has a default of its own
+    wrap: Option<usize>,
+    // starts the lines broken off by wrap
+    wrap_marker: Vec<u8>,
+    // print byte statistics of the raw input to stderr
+    stats: bool,
+    // print line length statistics of the raw input to stderr
+    line_stats: bool,
+    // print the wall clock time and throughput to stderr
+    time_it: bool,
+    // suppress normal output, implies stats
+    stats_only: bool,
+    // write nothing but the total size of the sources
+    count_bytes_only: bool,
+    // print "N files, M bytes" to stderr once done
+    verbose: bool,
+    // written between consecutive sources
+    output_separator: Option<Vec<u8>>,
+    // send the output as length prefixed frames of frame_unit
+    frame: bool,
+    frame_unit: Option<FrameUnit>,
+    // size of the buffer sources are read into
+    input_buffer: usize,
+    // size of the buffer output is collected in before it's written
+    output_buffer: usize,
+    // most files to hold open at once, the platform's limit when unset
+    max_open_files: Option<usize>,
+    // most sources to accept at all, unlimited when unset
+    max_sources: Option<usize>,
+    // canonical hex+ASCII display of the raw bytes, excludes the other display options
+    hexdump: bool,
+    // most input bytes the whole source modes may keep in memory
+    max_memory: Option<u64>,
+    // pad tab separated fields into aligned columns, a whole source at a time
+    tsv_align: bool,
+    // with tsv_align, right-justify the columns that hold nothing but numbers
+    align_numbers: bool,
+    // show sources side by side instead of one after another
+    columns: bool,
+    // compare two sources line by line instead of writing them
+    diff: bool,
+    // interleave the lines of sorted sources so the result is sorted too
+    merge_sorted: bool,
+    // take turns between the sources, this many lines of each
+    interleave: Option<usize>,
+    // with merge_sorted, the field the lines are ordered by, counting from 1
+    sort_field: Option<usize>,
+    // with merge_sorted, report the sources that turn out not to be sorted
+    check_sorted: bool,
+    // width columns are padded to
+    column_width: usize,
+    // skip binary looking sources when writing to a terminal
+    detect_binary: bool,
+    // show binary sources anyway
+    force: bool,
+    // fail the whole run at a binary looking source, --force or not
+    text_only: bool,
+    // percentage of control characters that makes a source look binary
+    binary_threshold: usize,
+    // page output when stdout is a terminal, handled by the binary
+    pager: bool,
+    // bytes per second output is throttled to
+    limit_rate: Option<u64>,
+    // flush output after every newline instead of once per chunk
+    line_buffered: bool,
+    // pause between sources
+    delay: Option<Duration>,
+    // write a UTF-8 BOM before anything else
+    add_bom: bool,
+    // form feed after every this many lines of output
+    lines_per_page: Option<usize>,
+    // give up on stdin after this long without data
+    read_timeout: Option<Duration>,
+    // give up on a named pipe without a writer after this long
+    fifo_timeout: Option<Duration>,
+    // keep trying to open missing files for this long
+    retry_open: Option<Duration>,
+    // try failed reads again this many times when the error may be transient
+    retry_read: Option<usize>,
+    // report invalid UTF-8 per source
+    check_utf8: bool,
+    // report sources without a final newline
+    check_final_newline: bool,
+    // drop the first line of every source, but the first source's with keep_first_header
+    skip_header: bool,
+    keep_first_header: bool,
+    // write this many bytes of every source under a header, and a marker when there was more
+    preview: Option<u64>,
+    // put the size, modification time and permissions of every source in a header before it
+    metadata: bool,
+    // stop everything at the first line that is exactly this, writing it too with include_marker
+    stop_at: Option<Vec<u8>>,
+    include_marker: bool,
+    // literal FROM and TO of every --replace, applied one after another
+    replacements: Vec<(Vec<u8>, Vec<u8>)>,
+    // stop at the first failing source instead of reporting and moving on
+    abort_on_error: bool,
+    // exit status when not a single byte was written
+    fail_if_empty: Option<i32>,
+    // where the plain copy records how far it got
+    checkpoint: Option<String>,
+    // with checkpoint, carry on from where it says the last run got
+    resume: bool,
+    // that offset, read from the checkpoint once the options are parsed
+    resume_from: Option<u64>,
+    // read the sources last to first
+    reverse_files: bool,
+    on_missing: OnMissing,
+    on_empty: OnEmpty,
+    // FILEs have to match one of these if there are any
+    #[cfg(feature = "glob")]
+    includes: Vec<glob::Glob>,
+    // FILEs matching any of these are skipped
+    #[cfg(feature = "glob")]
+    excludes: Vec<glob::Glob>,
+    // what diagnostics call standard input instead of STDIN_NAME
+    stdin_name: Option<String>,
+    // shell-quote the names of FILEs in diagnostics and headers
+    quote_names: bool,
+    // standard input as read by the first `-`, replayed by every other one
+    stdin_cache: Option<Rc<OnceCell<Vec<u8>>>>,
+    // put a terminal on stdin in raw mode while reading
+    #[cfg(unix)]
+    raw_tty: bool,
+    // sources to get data from
+    files: Vec<Source>,
+
+    // overrides all arguments above...
+    version: bool, // show program version
+    help: bool, // show help message
+}
+
+// what every option is without it being given, for RatArgs::new and RatArgs::files alike
+impl Default for RatArgs {
+    fn default() -> Self {
+        Self {
+            plain: false,
+            show_ends: false,
+            show_ends_eof: false,
+            null_output: false,
+            normalize_newlines: None,
+            record_delimiter: None,
+            end_marker: b"$".to_vec(),
+            number_lines: false,
+            tap: false,
+            fold_case: None,
+            number_start: 1,
+            max_line_number: None,
+            number_overflow: None,
+            number_radix: Radix::default(),
+            byte_offset: None,
+            number_nonblank: false,
+            #[cfg(feature = "regex")]
+            number_matching: None,
+            #[cfg(feature = "regex")]
+            grep: None,
+            #[cfg(feature = "regex")]
+            count_matching: None,
+            #[cfg(feature = "regex")]
+            context: None,
+            squeeze_blank: false,
+            squeeze_whitespace: false,
+            squeeze_per_source: false,
+            uniq: None,
+            #[cfg(feature = "crc32")]
+            checksum_per_line: false,
+            collapse_spaces: false,
+            strip_trailing_whitespace: false,
+            fields: None,
+            field_delimiter: Vec::new(),
+            sample: None,
+            sample_offset: 0,
+            show_tabs: false,
+            tab_replacement: None,
+            expand_tabs: false,
+            tab_size: 8,
+            transform_order: None,
+            reveal: None,
+            show_nonprinting: false,
+            ascii_only: false,
+            ascii_placeholder: b"?".to_vec(),
+            #[cfg(feature = "encoding")]
+            from_encoding: None,
+            #[cfg(feature = "base64")]
+            base64_decode: false,
+            #[cfg(feature = "gzip")]
+            gzip: false,
+            #[cfg(feature = "base64")]
+            base64_encode: false,
+            wrap: None,
+            wrap_marker: Vec::new(),
+            stats: false,
+            line_stats: false,
+            time_it: false,
+            stats_only: false,
+            count_bytes_only: false,
+            verbose: false,
+            output_separator: None,
+            frame: false,
+            frame_unit: None,
+            input_buffer: IO_BUFSIZE,
+            output_buffer: IO_BUFSIZE,
+            max_open_files: None,
+            max_sources: None,
+            hexdump: false,
+            max_memory: None,
+            tsv_align: false,
+            align_numbers: false,
+            columns: false,
+            diff: false,
+            merge_sorted: false,
+            interleave: None,
+            sort_field: None,
+            check_sorted: false,
+            column_width: 40,
+            detect_binary: false,
+            force: false,
+            text_only: false,
+            binary_threshold: BINARY_THRESHOLD,
+            pager: false,
+            limit_rate: None,
+            line_buffered: false,
+            delay: None,
+            add_bom: false,
+            lines_per_page: None,
+            read_timeout: None,
+            fifo_timeout: None,
+            retry_open: None,
+            retry_read: None,
+            check_utf8: false,
+            check_final_newline: false,
+            skip_header: false,
+            keep_first_header: false,
+            preview: None,
+            metadata: false,
+            stop_at: None,
+            include_marker: false,
+            replacements: Vec::new(),
+            abort_on_error: false,
+            fail_if_empty: None,
+            checkpoint: None,
+            resume: false,
+            resume_from: None,
+            reverse_files: false,
+            on_missing: OnMissing::default(),
+            on_empty: OnEmpty::default(),
+            #[cfg(feature = "glob")]
+            includes: Vec::new(),
+            #[cfg(feature = "glob")]
+            excludes: Vec::new(),
+            stdin_name: None,
+            quote_names: false,
+            stdin_cache: None,
+            #[cfg(unix)]
+            raw_tty: false,
+            files: Vec::new(),
+            version: false,
+            help: false,
+        }
+    }
+}
+
+impl RatArgs {
+    pub fn files(files: Vec<String>) -> Self {
+        let files = files.iter().map(|f| Source::File(f.to_string(), None)).collect();
+        Self {
+            files,
+            ..Self::default()
+        }
+    }
+
+    /// Like [`RatArgs::new`], with the options in `RAT_OPTIONS` going before the ones in `raw`
+    /// unless `raw` has `--no-env`.
+    pub fn from_env_and_args(raw: Vec<String>) -> Result<Self, RatError> {
+        Self::with_env_options(raw, std::env::var("RAT_OPTIONS").ok())
+    }
+
+    fn with_env_options(mut raw: Vec<String>, env_options: Option<String>) -> Result<Self, RatError> {
+        let env_options = env_options.filter(|_| !raw.iter().skip(1).take_while(|arg| *arg != "--").any(|arg| arg == "--no-env"));
+        if let Some(env_options) = env_options {
+            let words = split_words(&env_options)?;
+            raw.splice(1..1, words);
+        }
+
+        Self::new(raw)
+    }
+
+    pub fn new(raw: Vec<String>) -> Result<Self, RatError> {
+        let slice = &raw[1..];
+        let mut rat_args = RatArgs::default();
+
+        // if no args provided - just use stdin as a source
+        if raw.len() == 1 {
+            rat_args.files.push(Source::Stdin(std::io::stdin()));
+            return Ok(rat_args);
+        }
+
+        // set by the first `--`, everything after it is a FILE, a later `--` included
+        let mut options_ended = false;
+
+        let mut args = slice.iter();
+        while let Some(arg) = args.next() {
+            if options_ended && arg != "-" {
+                rat_args.files.push(Source::File(arg.into(), None));
+            } else if arg == "--" {
+                options_ended = true;
+            } else if arg.starts_with("--") {
+                let (name, value) = match arg.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (arg.as_str(), None),
+                };
+
+                let Some(option) = OptionSpec::find_long(name) else {
+                    return Err(format!("unrecognized option '{name}'").into());
+                };
+                if option.value.is_empty() && value.is_some() {
+                    return Err(format!("option '{name}' doesn't allow an argument").into());
+                }
+                rat_args.apply_option(&option.name(), value)?;
+            } else if arg == "-" && arg.len() == 1 {
+                // stdin source is here baby
+                rat_args.files.push(Source::Stdin(std::io::stdin()));
+            } else if arg.contains("-") && arg.chars().nth(0).unwrap() == '-' {
+                // a bunch of short flags, like -nE, the last one may take a value like -C3 or -C 3
+                let shorts = &arg[1..];
+                for (i, short) in shorts.char_indices() {
+                    let Some(option) = OptionSpec::find_short(short) else {
+                        return Err(format!("invalid option -- '{short}'").into());
+                    };
+
+                    if option.value.is_empty() {
+                        rat_args.apply_option(&option.name(), None)?;
+                        continue;
+                    }
+
+                    let value = match &shorts[i + short.len_utf8()..] {
+                        "" => args.next().map(String::as_str),
+                        rest => Some(rest),
+                    };
+                    rat_args.apply_option(&option.name(), value)?;
+                    break;
+                }
+            } else {
+                rat_args.files
+                    .push(Source::File(arg.into(), None));
+            }
+        }
+
+        // before the checks, a combination that makes no sense is fine when none of it is left
+        if rat_args.plain {
+            rat_args.make_plain();
+        }
+
+        #[cfg(feature = "encoding")]
+        if rat_args.from_encoding.is_some() && rat_args.show_nonprinting {
+            return Err("--from-encoding cannot be combined with -v".into());
+        }
+
+        if rat_args.ascii_only && rat_args.show_nonprinting {
+            return Err("--ascii-only cannot be combined with -v".into());
+        }
+        #[cfg(feature = "encoding")]
+        if rat_args.ascii_only && rat_args.from_encoding.is_some() {
+            return Err("--ascii-only cannot be combined with --from-encoding".into());
+        }
+
+        #[cfg(feature = "regex")]
+        if rat_args.count_matching.is_some() && rat_args.grep.is_none() {
+            return Err("--count-matching needs --grep".into());
+        }
+
+        if !rat_args.field_delimiter.is_empty() && rat_args.fields.is_none() && rat_args.sort_field.is_none() {
+            return Err("--delimiter needs --fields or --key".into());
+        }
+
+        if rat_args.sort_field.is_some() && !rat_args.merge_sorted {
+            return Err("--key needs --merge-sorted".into());
+        }
+
+        if rat_args.check_sorted && !rat_args.merge_sorted {
+            return Err("--check-sorted needs --merge-sorted".into());
+        }
+
+        if rat_args.merge_sorted && (rat_args.columns || rat_args.diff || rat_args.tsv_align) {
+            return Err("--merge-sorted cannot be combined with --columns, --diff or --tsv-align".into());
+        }
+
+        if rat_args.interleave.is_some() && (rat_args.columns || rat_args.diff || rat_args.merge_sorted || rat_args.tsv_align) {
+            return Err("--interleave cannot be combined with --columns, --diff, --merge-sorted or --tsv-align".into());
+        }
+
+        // the modes reading the sources a line at a time, none of the stages working on the bytes of a source see them
+        let line_mode = [(rat_args.columns, "--columns"), (rat_args.merge_sorted, "--merge-sorted"), (rat_args.interleave.is_some(), "--interleave")]
+            .into_iter()
+            .find_map(|(set, mode)| set.then_some(mode));
+        let whole_lines_mode = line_mode.or(rat_args.diff.then_some("--diff"));
+
+        if rat_args.show_ends_eof && !rat_args.show_ends {
+            return Err("--show-ends-eof needs -E".into());
+        }
+
+        // the output buffer is at least MIN_BUFSIZE, what a single byte renders to has to fit in it;
+        // long markers take it past that, more so when --transform-order has stages expand each other's
+        let expansion = rat_args.transform_options().max_expansion();
+        if expansion > MIN_BUFSIZE {
+            return Err(format!("the markers are too long, a byte could render to {expansion} bytes and the output buffer holds {MIN_BUFSIZE}").into());
+        }
+
+        if rat_args.frame_unit.is_some() && !rat_args.frame {
+            return Err("--frame-unit needs --frame".into());
+        }
+
+        // the frames are cut where exec ends a source or the output a line
+        if let Some(mode) = whole_lines_mode.filter(|_| rat_args.frame) {
+            return Err(format!("--frame cannot be combined with {mode}").into());
+        }
+
+        if rat_args.number_overflow.is_some() && rat_args.max_line_number.is_none() {
+            return Err("--number-overflow needs --max-line-number".into());
+        }
+
+        if rat_args.max_line_number.is_some_and(|max| max < rat_args.number_start) {
+            return Err("--max-line-number cannot be less than --numbers-from".into());
+        }
+
+        if rat_args.align_numbers && !rat_args.tsv_align {
+            return Err("--align needs --tsv-align".into());
+        }
+
+        #[cfg(feature = "regex")]
+        if rat_args.context.is_some() && rat_args.grep.is_none() {
+            return Err("--context needs --grep".into());
+        }
+
+        if rat_args.diff && (rat_args.columns || rat_args.tsv_align) {
+            return Err("--diff cannot be combined with --columns or --tsv-align".into());
+        }
+
+        if let Some(mode) = whole_lines_mode.filter(|_| !rat_args.replacements.is_empty()) {
+            return Err(format!("--replace cannot be combined with {mode}").into());
+        }
+
+        if let Some(mode) = whole_lines_mode.filter(|_| rat_args.stop_at.is_some()) {
+            return Err(format!("--stop-at cannot be combined with {mode}").into());
+        }
+
+        if let Some(mode) = whole_lines_mode.or(rat_args.tsv_align.then_some("--tsv-align")).filter(|_| rat_args.preview.is_some()) {
+            return Err(format!("--preview cannot be combined with {mode}").into());
+        }
+
+        if let Some(mode) = whole_lines_mode.filter(|_| rat_args.metadata) {
+            return Err(format!("--metadata cannot be combined with {mode}").into());
+        }
+
+        if rat_args.sample.is_some_and(|every| rat_args.sample_offset >= every) {
+            return Err("--sample-offset must be less than --sample".into());
+        }
+
+        if rat_args.null_output && rat_args.normalize_newlines.is_some() {
+            return Err("-Z cannot be combined with --normalize-newlines".into());
+        }
+
+        // those read up to an LF
+        if let Some(mode) = line_mode.filter(|_| rat_args.normalize_newlines.is_some()) {
+            return Err(format!("--normalize-newlines cannot be combined with {mode}").into());
+        }
+
+        // only the transform knows about records, everything splitting lines on its own looks for newlines
+        if rat_args.record_delimiter.is_some() {
+            let conflicts = [
+                (rat_args.line_oriented(), "options that work on whole lines"),
+                (whole_lines_mode.is_some(), "--columns, --diff, --merge-sorted or --interleave"),
+                (rat_args.tsv_align, "--tsv-align"),
+                (rat_args.skip_header || rat_args.stop_at.is_some(), "--skip-header or --stop-at"),
+                (rat_args.normalize_newlines.is_some(), "--normalize-newlines"),
+            ];
+            if let Some((_, what)) = conflicts.iter().find(|(set, _)| *set) {
+                return Err(format!("--record-delimiter cannot be combined with {what}").into());
+            }
+        }
+
+        // the tabs -T would show are the ones being stripped
+        if rat_args.strip_trailing_whitespace && rat_args.show_tabs {
+            return Err("--strip-trailing-whitespace cannot be combined with -T".into());
+        }
+
+        #[cfg(feature = "base64")]
+        if let Some(mode) = line_mode.filter(|_| rat_args.base64_decode) {
+            return Err(format!("--base64-decode cannot be combined with {mode}").into());
+        }
+
+        #[cfg(feature = "gzip")]
+        if let Some(mode) = line_mode.filter(|_| rat_args.gzip) {
+            return Err(format!("--gzip cannot be combined with {mode}").into());
+        }
+
+        #[cfg(feature = "glob")]
+        {
+            let (includes, excludes) = (&rat_args.includes, &rat_args.excludes);
+            rat_args.files.retain(|source| match source {
+                Source::File(path, _) =>
+                    (includes.is_empty() || includes.iter().any(|glob| glob.is_match(path)))
+                        && !excludes.iter().any(|glob| glob.is_match(path)),
+                _ => true,
+            });
+        }
+
+        if let Some(limit) = rat_args.max_sources {
+            let count = rat_args.files.len();
+            if count > limit {
+                return Err(RatError::TooManySources { count, limit });
+            }
+        }
+
+        // the modes that show the bytes some other way instead of the display options
+        let replaces_display = match () {
+            _ if rat_args.hexdump => Some("--hexdump"),
+            #[cfg(feature = "base64")]
+            _ if rat_args.base64_encode => Some("--base64-encode"),
+            _ => None,
+        };
+
+        #[cfg(feature = "base64")]
+        if rat_args.hexdump && rat_args.base64_encode {
+            return Err("--hexdump cannot be combined with --base64-encode".into());
+        }
+
+        if let Some(mode) = replaces_display {
+            let conflicts = [
+                (rat_args.number_lines || rat_args.number_nonblank || rat_args.byte_offset.is_some(), "-n, -b or --byte-offset"),
+                (rat_args.show_ends, "-E"),
+                (rat_args.tap, "--tap"),
+                (rat_args.show_tabs || rat_args.tab_replacement.is_some() || rat_args.expand_tabs, "tab display options"),
+                (rat_args.show_nonprinting, "-v"),
+                (rat_args.squeeze_blank || rat_args.squeeze_whitespace || rat_args.collapse_spaces, "squeezing"),
+                (rat_args.strip_trailing_whitespace, "--strip-trailing-whitespace"),
+                (rat_args.sample.is_some(), "--sample"),
+                (rat_args.fields.is_some(), "--fields"),
+                (rat_args.preview.is_some() || rat_args.metadata, "--preview or --metadata"),
+                (rat_args.reveal.is_some(), "--reveal"),
+                (rat_args.columns || rat_args.tsv_align, "--columns or --tsv-align"),
+                (rat_args.lines_per_page.is_some(), "--lines-per-page"),
+                (rat_args.normalize_newlines.is_some(), "--normalize-newlines"),
+            ];
+            if let Some((_, what)) = conflicts.iter().find(|(set, _)| *set) {
+                return Err(format!("{mode} cannot be combined with {what}").into());
+            }
+
+            #[cfg(feature = "regex")]
+            if rat_args.number_matching.is_some() || rat_args.grep.is_some() {
+                return Err(format!("{mode} cannot be combined with --number-matching or --grep").into());
+            }
+            #[cfg(feature = "encoding")]
+            if rat_args.from_encoding.is_some() {
+                return Err(format!("{mode} cannot be combined with --from-encoding").into());
+            }
+            #[cfg(feature = "crc32")]
+            if rat_args.checksum_per_line {
+                return Err(format!("{mode} cannot be combined with --checksum-per-line").into());
+            }
+        }
+
+        // the copy has to be the bytes of the FILE as they are for an offset into it to be one into the output too
+        if rat_args.checkpoint.is_some() {
+            if !matches!(rat_args.files.as_slice(), [Source::File(..)]) {
+                return Err("--checkpoint needs exactly one FILE".into());
+            }
+            if !rat_args.copies_verbatim() {
+                return Err("--checkpoint cannot be combined with options that change or check what is copied".into());
+            }
+        }
+
+        if rat_args.resume {
+            let Some(path) = rat_args.checkpoint.as_deref() else {
+                return Err("--resume needs --checkpoint".into());
+            };
+            rat_args.resume_from = Some(read_checkpoint(path)?);
+        }
+
+        // the cache reads all of stdin at once, which a raw terminal doesn't end
+        #[cfg(unix)]
+        if rat_args.raw_tty && rat_args.stdin_cache.is_some() {
+            return Err("--raw-tty cannot be combined with --cache-stdin".into());
+        }
+
+        // the plain stdin sources become whatever the stdin options make of them
+        for i in 0..rat_args.files.len() {
+            if matches!(rat_args.files[i], Source::Stdin(_)) {
+                rat_args.files[i] = rat_args.stdin();
+            }
+        }
+
+        Ok(rat_args)
+    }
+
+    // sets what the option with this name asks for, `name` is the long one unless there is none
+    fn apply_option(&mut self, name: &str, value: Option<&str>) -> Result<(), RatError> {
+        match name {
+            "--help" => 
+                self.help = true,
+            
+            "--version" => 
+                self.version = true,
+
+            "--show-tabs" => 
+                self.show_tabs = true,
+
+            "--number" => 
+                self.number_lines = true,
+
+            "--number-nonblank" => 
+                self.number_nonblank = true,
+
+            "--show-ends" => 
+                self.show_ends = true,
+
+            "--count-bytes-only" =>
+                self.count_bytes_only = true,
+
+            "--plain" =>
+                self.plain = true,
+
+            "--show-ends-eof" =>
+                self.show_ends_eof = true,
+
+            "--show-nonprinting" => 
+                self.show_nonprinting = true,
+
+            "--squeeze-blank" =>
+                self.squeeze_blank = true,
+
+            "--squeeze-whitespace" =>
+                self.squeeze_whitespace = true,
+
+            "--no-squeeze-across-files" =>
+                self.squeeze_per_source = true,
+
+            "--collapse-spaces" =>
+                self.collapse_spaces = true,
+
+            "--sample" =>
+                self.sample = Some(parse_count(name, value)? as u64),
+
+            "--fields" =>
+                self.fields = Some(parse_fields(value)?),
+
+            "--delimiter" => {
+                let value = value.ok_or("option '--delimiter' requires an argument")?;
+                if value.is_empty() {
+                    return Err("the delimiter cannot be empty".into());
+                }
+                self.field_delimiter = unescape(value);
+            },
+
+            "--sample-offset" => {
+                let value = value.ok_or("option '--sample-offset' requires an argument")?;
+                self.sample_offset = value.parse::<u64>()
+                    .map_err(|_| format!("invalid value '{value}' for '--sample-offset'"))?;
+            },
+
+            "--strip-trailing-whitespace" =>
+                self.strip_trailing_whitespace = true,
+
+            "--show-all" => {
+                self.show_nonprinting = true;
+                self.show_ends = true;
+                self.show_tabs = true;
+            },
+
+            "--numbers-from" => {
+                let value = value.ok_or("option '--numbers-from' requires an argument")?;
+                self.number_start = value.parse::<u64>()
+                    .map_err(|_| format!("invalid value '{value}' for '--numbers-from'"))?;
+            },
+
+            "--max-line-number" =>
+                self.max_line_number = Some(parse_count(name, value)? as u64),
+
+            "--number-overflow" => {
+                self.number_overflow = match value {
+                    Some("stop") => Some(Overflow::Stop),
+                    Some("wrap") => Some(Overflow::Wrap),
+                    Some(other) => return Err(format!("invalid overflow '{other}', expected stop or wrap").into()),
+                    None => return Err("option '--number-overflow' requires an argument".into()),
+                };
+            },
+
+            "--expand-tabs" =>
+                self.expand_tabs = true,
+
+            "--tab-size" => {
+                let value = value.ok_or("option '--tab-size' requires an argument")?;
+                self.tab_size = value.parse::<usize>().ok()
+                    .filter(|size| (1..=32).contains(size))
+                    .ok_or_else(|| format!("invalid value '{value}' for '--tab-size', expected 1 to 32"))?;
+            },
+
+            "--transform-order" =>
+                self.transform_order = Some(parse_transform_order(value)?),
+
+            "--null-output" =>
+                self.null_output = true,
+
+            #[cfg(feature = "crc32")]
+            "--checksum-per-line" =>
+                self.checksum_per_line = true,
+
+            "--normalize-newlines" => {
+                self.normalize_newlines = match value {
+                    Some("lf") => Some(Newline::Lf),
+                    Some("crlf") => Some(Newline::CrLf),
+                    Some("cr") => Some(Newline::Cr),
+                    Some(other) => return Err(format!("invalid line end '{other}', expected lf, crlf or cr").into()),
+                    None => return Err("option '--normalize-newlines' requires an argument".into()),
+                };
+            },
+
+            "--record-delimiter" => {
+                let value = value.ok_or("option '--record-delimiter' requires an argument")?;
+                if value.is_empty() {
+                    return Err("the record delimiter cannot be empty".into());
+                }
+                self.record_delimiter = Some(unescape(value));
+            },
+
+            "--tap" =>
+                self.tap = true,
+
+            "--fold-case" => {
+                self.fold_case = match value {
+                    None | Some("lower") => Some(Case::Lower),
+                    Some("upper") => Some(Case::Upper),
+                    Some(other) => return Err(format!("invalid case '{other}', expected lower or upper").into()),
+                };
+            },
+
+            "--byte-offset" => {
+                self.byte_offset = match value {
+                    None | Some("dec") => Some(Radix::Decimal),
+                    Some("hex") => Some(Radix::Hex),
+                    Some(other) => return Err(format!("invalid radix '{other}', expected dec or hex").into()),
+                };
+            },
+
+            "--number-radix" => {
+                self.number_radix = match value {
+                    Some("dec") => Radix::Decimal,
+                    Some("hex") => Radix::Hex,
+                    Some(other) => return Err(format!("invalid radix '{other}', expected dec or hex").into()),
+                    None => return Err("option '--number-radix' requires an argument".into()),
+                };
+            },
+
+            "--ascii-only" =>
+                self.ascii_only = true,
+
+            "--ascii-placeholder" => {
+                let value = value.ok_or("option '--ascii-placeholder' requires an argument")?;
+                self.ascii_placeholder = unescape(value);
+            },
+
+            "--end-marker" => {
+                let value = value.ok_or("option '--end-marker' requires an argument")?;
+                self.end_marker = unescape(value);
+            },
+
+            "--replace-tabs-with" => {
+                let value = value.ok_or("option '--replace-tabs-with' requires an argument")?;
+                self.tab_replacement = Some(unescape(value));
+            },
+
+            "--output-separator" => {
+                let value = value.ok_or("option '--output-separator' requires an argument")?;
+                self.output_separator = Some(unescape(value));
+            },
+
+            #[cfg(feature = "regex")]
+            "--number-matching" => {
+                let value = value.ok_or("option '--number-matching' requires an argument")?;
+                self.number_matching = Some(regex::bytes::Regex::new(value).map_err(|e| format!("invalid --number-matching pattern: {e}"))?);
+            },
+
+            #[cfg(feature = "regex")]
+            "--grep" => {
+                let value = value.ok_or("option '--grep' requires an argument")?;
+                self.grep = Some(regex::bytes::Regex::new(value).map_err(|e| format!("invalid --grep pattern: {e}"))?);
+            },
+
+            #[cfg(feature = "regex")]
+            "--count-matching" => {
+                self.count_matching = match value {
+                    None => Some(CountMatching::Total),
+                    Some("per-file") => Some(CountMatching::PerFile),
+                    Some(other) => return Err(format!("invalid value '{other}' for '--count-matching', expected per-file").into()),
+                };
+            },
+
+            #[cfg(feature = "regex")]
+            "--context" => {
+                let value = value.ok_or("option '--context' requires an argument")?;
+                let context = value.parse::<usize>().map_err(|_| format!("invalid value '{value}' for '--context'"))?;
+                self.context = Some(context);
+            },
+
+            "--reveal" => {
+                self.reveal = match value {
+                    None | Some("unicode") => Some(Reveal::Unicode),
+                    Some("ascii") => Some(Reveal::Ascii),
+                    Some(other) => return Err(format!("invalid reveal style '{other}'").into()),
+                };
+            },
+
+            "--time-it" =>
+                self.time_it = true,
+
+            "--stats" =>
+                self.stats = true,
+
+            "--line-stats" =>
+                self.line_stats = true,
+
+            "--stats-only" => {
+                self.stats = true;
+                self.stats_only = true;
+            },
+
+            "--verbose" =>
+                self.verbose = true,
+
+            "--max-open-files" =>
+                self.max_open_files = Some(parse_count(name, value)?),
+
+            "--max-sources" =>
+                self.max_sources = Some(parse_count(name, value)?),
+
+            "--tsv-align" =>
+                self.tsv_align = true,
+
+            "--align" => {
+                self.align_numbers = match value {
+                    Some("left") => false,
+                    Some("auto") => true,
+                    Some(other) => return Err(format!("invalid alignment '{other}', expected left or auto").into()),
+                    None => return Err("option '--align' requires an argument".into()),
+                };
+            },
+
+            "--hexdump" =>
+                self.hexdump = true,
+
+            "--columns" =>
+                self.columns = true,
+
+            "--diff" =>
+                self.diff = true,
+
+            "--merge-sorted" =>
+                self.merge_sorted = true,
+
+            "--interleave" =>
+                self.interleave = Some(parse_count(name, value)?),
+
+            "--key" =>
+                self.sort_field = Some(parse_count(name, value)?),
+
+            "--check-sorted" =>
+                self.check_sorted = true,
+
+            "--column-width" =>
+                self.column_width = parse_count(name, value)?,
+
+            "--detect-binary" =>
+                self.detect_binary = true,
+
+            "--force" =>
+                self.force = true,
+
+            "--text-only" =>
+                self.text_only = true,
+
+            "--binary-threshold" => {
+                let value = value.ok_or("option '--binary-threshold' requires an argument")?;
+                self.binary_threshold = value.parse::<usize>().ok()
+                    .filter(|percent| *percent <= 100)
+                    .ok_or_else(|| format!("invalid percentage '{value}' for '--binary-threshold'"))?;
+            },
+
+            "--pager" =>
+                self.pager = true,
+
+            #[cfg(feature = "glob")]
+            "--include" | "--exclude" => {
+                let value = value.ok_or_else(|| format!("option '{name}' requires an argument"))?;
+                let glob = glob::Glob::new(value)?;
+                match name {
+                    "--include" => self.includes.push(glob),
+                    _ => self.excludes.push(glob),
+                }
+            },
+
+            #[cfg(feature = "clipboard")]
+            "--clipboard" =>
+                self.files.push(Source::Clipboard(None)),
+
+            "--files-from" => {
+                let value = value.ok_or("option '--files-from' requires an argument")?;
+                let (path, list) = match value {
+                    "-" => {
+                        let mut list = Vec::new();
+                        ("standard input", std::io::stdin().read_to_end(&mut list).map(|_| list))
+                    },
+                    path => (path, std::fs::read(path)),
+                };
+                let list = list.map_err(|source| RatError::OpenFailed { path: path.into(), source })?;
+                self.add_listed_files(&list, value == "-")?;
+            },
+
+            #[cfg(unix)]
+            "--fd" => {
+                let value = value.ok_or("option '--fd' requires an argument")?;
+                let fd = value.parse::<i32>().ok()
+                    .filter(|fd| *fd >= 0)
+                    .ok_or_else(|| format!("invalid file descriptor '{value}'"))?;
+
+                self.files.push(fd_source(fd)?);
+            },
+
+            "--quote-names" =>
+                self.quote_names = true,
+
+            "--stdin-name" => {
+                let value = value.ok_or("option '--stdin-name' requires an argument")?;
+                self.stdin_name = Some(value.to_string());
+            },
+
+            "--cache-stdin" =>
+                self.stdin_cache = Some(Rc::default()),
+
+            #[cfg(unix)]
+            "--raw-tty" =>
+                self.raw_tty = true,
+
+            // only means something to from_env_and_args
+            "--no-env" => {},
+
+            "--buffer-size" | "--input-buffer" | "--output-buffer" => {
+                let value = value.ok_or_else(|| format!("option '{name}' requires an argument"))?;
+                let size = parse_size(value).ok_or_else(|| format!("invalid buffer size '{value}'"))?;
+                let size = usize::try_from(size).unwrap_or(usize::MAX);
+
+                if name != "--output-buffer" {
+                    self.input_buffer = size.max(if name == "--buffer-size" { MIN_BUFSIZE } else { 1 });
+                }
+                if name != "--input-buffer" {
+                    self.output_buffer = size.max(MIN_BUFSIZE);
+                }
+            },
+
+            "--max-memory" => {
+                let value = value.ok_or("option '--max-memory' requires an argument")?;
+                let size = parse_size(value).ok_or_else(|| format!("invalid memory limit '{value}'"))?;
+                self.max_memory = Some(size);
+            },
+
+            "--limit-rate" => {
+                let value = value.ok_or("option '--limit-rate' requires an argument")?;
+                let rate = parse_size(value).ok_or_else(|| format!("invalid rate '{value}'"))?;
+                self.limit_rate = Some(rate);
+            },
+
+            "--add-bom" =>
+                self.add_bom = true,
+
+            "--line-buffered" =>
+                self.line_buffered = true,
+
+            "--delay" =>
+                self.delay = Some(Duration::from_millis(parse_count(name, value)? as u64)),
+
+            "--lines-per-page" =>
+                self.lines_per_page = Some(parse_count(name, value)?),
+
+            "--on-missing" => {
+                self.on_missing = match value {
+                    Some("report") => OnMissing::Report,
+                    Some("skip") => OnMissing::Skip,
+                    Some("error") => OnMissing::Error,
+                    Some("empty") => OnMissing::Empty,
+                    Some(other) => return Err(format!("invalid policy '{other}', expected report, skip, error or empty").into()),
+                    None => return Err("option '--on-missing' requires an argument".into()),
+                };
+            },
+
+            "--frame" => {
+                match value {
+                    Some("length") => self.frame = true,
+                    Some(other) => return Err(format!("invalid frame format '{other}', expected length").into()),
+                    None => return Err("option '--frame' requires an argument".into()),
+                };
+            },
+
+            "--frame-unit" => {
+                self.frame_unit = match value {
+                    Some("source") => Some(FrameUnit::Source),
+                    Some("line") => Some(FrameUnit::Line),
+                    Some(other) => return Err(format!("invalid frame unit '{other}', expected source or line").into()),
+                    None => return Err("option '--frame-unit' requires an argument".into()),
+                };
+            },
+
+            "--on-empty" => {
+                self.on_empty = match value {
+                    Some("ignore") => OnEmpty::Ignore,
+                    Some("warn") => OnEmpty::Warn,
+                    Some("error") => OnEmpty::Error,
+                    Some(other) => return Err(format!("invalid policy '{other}', expected ignore, warn or error").into()),
+                    None => return Err("option '--on-empty' requires an argument".into()),
+                };
+            },
+
+            "--fail-if-empty" => {
+                self.fail_if_empty = match value {
+                    None => Some(1),
+                    Some(value) => Some(value.parse::<i32>().ok()
+                        .filter(|status| (1..=255).contains(status))
+                        .ok_or_else(|| format!("invalid exit status '{value}' for '--fail-if-empty'"))?),
+                };
+            },
+
+            "--checkpoint" => {
+                let value = value.ok_or("option '--checkpoint' requires an argument")?;
+                self.checkpoint = Some(value.to_string());
+            },
+
+            "--resume" =>
+                self.resume = true,
+
+            "--abort-on-error" =>
+                self.abort_on_error = true,
+
+            "--reverse-files" =>
+                self.reverse_files = true,
+
+            "--uniq" =>
+                self.uniq = Some(Uniq::Adjacent),
+
+            "--uniq-global" =>
+                self.uniq = Some(Uniq::Global),
+
+            "--check-utf8" =>
+                self.check_utf8 = true,
+
+            "--skip-header" =>
+                self.skip_header = true,
+
+            "--keep-first-header" =>
+                self.keep_first_header = true,
+
+            "--metadata" =>
+                self.metadata = true,
+
+            "--preview" =>
+                self.preview = Some(parse_count(name, value)? as u64),
+
+            "--stop-at" => {
+                let value = value.ok_or("option '--stop-at' requires an argument")?;
+                self.stop_at = Some(value.as_bytes().to_vec());
+            },
+
+            "--include-marker" =>
+                self.include_marker = true,
+
+            "--replace" => {
+                let value = value.ok_or("option '--replace' requires an argument")?;
+                self.replacements.push(parse_replacement(value)?);
+            },
+
+            "--check-final-newline" =>
+                self.check_final_newline = true,
+
+            "--read-timeout" =>
+                self.read_timeout = Some(parse_timeout(name, value)?),
+
+            "--fifo-timeout" =>
+                self.fifo_timeout = Some(parse_timeout(name, value)?),
+
+            "--retry-open" =>
+                self.retry_open = Some(parse_timeout(name, value)?),
+
+            "--retry-read" =>
+                self.retry_read = Some(parse_count(name, value)?),
+
+            #[cfg(feature = "encoding")]
+            "--from-encoding" => {
+                let value = value.ok_or("option '--from-encoding' requires an argument")?;
+                let encoding = Encoding::parse(value)
+                    .ok_or_else(|| format!("unsupported encoding '{value}'"))?;
+                self.from_encoding = Some(encoding);
+            },
+
+            #[cfg(feature = "base64")]
+            "--base64-decode" =>
+                self.base64_decode = true,
+
+            #[cfg(feature = "base64")]
+            "--base64-encode" =>
+                self.base64_encode = true,
+
+            #[cfg(feature = "gzip")]
+            "--gzip" =>
+                self.gzip = true,
+
+            "--wrap" => {
+                let value = value.ok_or("option '--wrap' requires an argument")?;
+                self.wrap = Some(value.parse::<usize>().map_err(|_| format!("invalid value '{value}' for '--wrap'"))?);
+            },
+
+            "--wrap-marker" => {
+                let value = value.ok_or("option '--wrap-marker' requires an argument")?;
+                self.wrap_marker = unescape(value);
+            },
+
+            "-e" => {
+                self.show_nonprinting = true;
+                self.show_ends = true;
+            },
+
+            "-t" => {
+                self.show_nonprinting = true;
+                self.show_tabs = true;
+            },
+
+            // coreutils ignores it too, output is never held back waiting for more input
+            "-u" => {},
+
+            _ => unreachable!("option {name} is in OPTIONS but not handled"),
+        }
+
+        Ok(())
+    }
+
+    /// Whether `--pager` was requested, it's up to the caller to set the pager up.
+    pub fn pager(&self) -> bool {
+        self.pager
+    }
+
+    /// Whether `--resume` was requested, the output has to be handed to [`RatArgs::resume_output`] then.
+    pub fn resumes(&self) -> bool {
+        self.resume
+    }
+
+    /// Cuts `output` back to the offset in the `--checkpoint` FILE and moves it there, so the copy
+    /// carries on right after what the interrupted one had made sure was written. Does nothing without `--resume`.
+    pub fn resume_output(&self, output: &std::fs::File) -> Result<(), RatError> {
+        let Some(offset) = self.resume_from else {
+            return Ok(());
+        };
+
+        let resume_error = |source| RatError::Resume { source };
+        let mismatch = |message: String| resume_error(std::io::Error::new(std::io::ErrorKind::InvalidInput, message));
+        let meta = output.metadata().map_err(resume_error)?;
+        if !meta.is_file() {
+            return Err(mismatch("it isn't a regular file".into()));
+        }
+        // less than the checkpoint says went out means it's not the output the copy went to
+        if meta.len() < offset {
+            return Err(mismatch(format!("it has {} bytes, not the {offset} the checkpoint says were copied", meta.len())));
+        }
+
+        // cutting it back is what counts when it was opened for appending, the seek when it wasn't
+        output.set_len(offset).map_err(resume_error)?;
+        let mut output = output;
+        output.seek(std::io::SeekFrom::Start(offset)).map_err(resume_error)?;
+        Ok(())
+    }
+
+    fn stdin(&self) -> Source {
+        if let Some(cache) = &self.stdin_cache {
+            return Source::CachedStdin(StdinCache { data: Rc::clone(cache), pos: 0, timeout: self.read_timeout });
+        }
+
+        #[cfg(unix)]
+        if self.raw_tty_stdin() {
+            return match self.read_timeout {
+                Some(timeout) => Source::TimedStdin(TimedReader::new(EndAtEot::new(std::io::stdin()), timeout)),
+                None => Source::RawStdin(EndAtEot::new(std::io::stdin())),
+            };
+        }
+
+        match self.read_timeout {
+            Some(timeout) => Source::TimedStdin(TimedReader::new(std::io::stdin(), timeout)),
+            None => Source::Stdin(std::io::stdin()),
+        }
+    }
+
+    // the --files-from entries, a - among them can't be stdin when the list itself came from there
+    fn add_listed_files(&mut self, list: &[u8], list_from_stdin: bool) -> Result<(), String> {
+        let entries = list.split(|&byte| byte == b'\n')
+            .map(|entry| entry.strip_suffix(b"\r").unwrap_or(entry))
+            .filter(|entry| !entry.is_empty());
+
+        for entry in entries {
+            match entry {
+                b"-" if list_from_stdin => return Err("cannot read file list and stdin simultaneously".into()),
+                b"-" => self.files.push(Source::Stdin(std::io::stdin())),
+                path => self.files.push(Source::File(String::from_utf8_lossy(path).into_owned(), None)),
+            }
+        }
+        Ok(())
+    }
+
+    // what --merge-sorted orders a line by, the line without its LF or the --key field of it
+    fn sort_key<'l>(&self, line: &'l [u8]) -> &'l [u8] {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let Some(field) = self.sort_field else {
+            return line;
+        };
+        let delimiter = match self.field_delimiter.as_slice() {
+            [] => b"\t",
+            delimiter => delimiter,
+        };
+        let find = |rest: &[u8]| rest.windows(delimiter.len()).position(|window| window == delimiter);
+
+        // a line with fewer fields sorts like one with an empty field
+        let mut rest = line;
+        for _ in 1..field {
+            match find(rest) {
+                Some(at) => rest = &rest[at + delimiter.len()..],
+                None => return &[],
+            }
+        }
+        &rest[..find(rest).unwrap_or(rest.len())]
+    }
+
+    // what diagnostics call stdin
+    fn stdin_name(&self) -> &str {
+        self.stdin_name.as_deref().unwrap_or(STDIN_NAME)
+    }
+
+    // how diagnostics and reports name a source
+    fn source_name(&self, source: &Source) -> String {
+        match source.is_stdin() {
+            true => self.stdin_name().to_string(),
+            false if self.quote_names => source.quoted_name(),
+            false => source.to_string(),
+        }
+    }
+
+    // whether --raw-tty has a terminal to work with
+    #[cfg(unix)]
+    fn raw_tty_stdin(&self) -> bool {
+        use std::io::IsTerminal;
+        self.raw_tty && std::io::stdin().is_terminal()
+    }
+
+    // --on-empty for a source that turned out to have no bytes, the error ends it all
+    fn source_empty<E: Write>(&self, name: &str, err_to: &mut E) -> Result<(), RatError> {
+        match self.on_empty {
+            OnEmpty::Ignore => {},
+            OnEmpty::Warn => writeln!(err_to, "{RAT_NAME}: {name}: empty").map_err(RatError::from_write)?,
+            OnEmpty::Error => return Err(RatError::EmptySource { path: name.to_string() }),
+        }
+        Ok(())
+    }
+
+    // whether --on-missing=error makes a failed open the end of it all
+    fn stops_at_missing(&self, e: &std::io::Error, opening: bool) -> bool {
+        opening && e.kind() == std::io::ErrorKind::NotFound && self.on_missing == OnMissing::Error
+    }
+
+    // --plain: turns off everything that makes the output anything but the sources byte for byte, and what only goes with that
+    fn make_plain(&mut self) {
+        (self.show_ends, self.show_ends_eof, self.null_output, self.normalize_newlines, self.record_delimiter) = (false, false, false, None, None);
+        (self.number_lines, self.number_nonblank, self.tap, self.fold_case, self.byte_offset) = (false, false, false, None, None);
+        (self.max_line_number, self.number_overflow) = (None, None);
+        (self.squeeze_blank, self.squeeze_whitespace, self.collapse_spaces, self.uniq) = (false, false, false, None);
+        (self.strip_trailing_whitespace, self.fields, self.field_delimiter, self.sample) = (false, None, Vec::new(), None);
+        (self.show_tabs, self.tab_replacement, self.expand_tabs, self.reveal) = (false, None, false, None);
+        (self.show_nonprinting, self.ascii_only, self.wrap, self.output_separator) = (false, false, None, None);
+        (self.frame, self.frame_unit, self.hexdump, self.tsv_align, self.align_numbers) = (false, None, false, false, false);
+        (self.columns, self.diff, self.merge_sorted, self.interleave, self.sort_field, self.check_sorted) = (false, false, false, None, None, false);
+        (self.add_bom, self.lines_per_page, self.skip_header, self.preview, self.metadata) = (false, None, false, None, false);
+        (self.stop_at, self.replacements, self.count_bytes_only, self.reverse_files) = (None, Vec::new(), false, false);
+        // the statistics stay, only without the output going missing
+        self.stats |= std::mem::take(&mut self.stats_only);
+
+        #[cfg(feature = "regex")]
+        {
+            (self.number_matching, self.grep, self.count_matching, self.context) = (None, None, None, None);
+        }
+        #[cfg(feature = "crc32")]
+        {
+            self.checksum_per_line = false;
+        }
+        #[cfg(feature = "encoding")]
+        {
+            self.from_encoding = None;
+        }
+        #[cfg(feature = "base64")]
+        {
+            (self.base64_decode, self.base64_encode) = (false, false);
+        }
+        #[cfg(feature = "gzip")]
+        {
+            self.gzip = false;
+        }
+    }
+
+    // whether the output is the sources byte for byte with nothing looking at them on the way, all that --checkpoint can pick up
+    fn copies_verbatim(&self) -> bool {
+        let changed = [
+            self.line_oriented(), self.show_ends, self.null_output, self.normalize_newlines.is_some(), self.record_delimiter.is_some(),
+            self.number_lines, self.number_nonblank, self.tap, self.fold_case.is_some(), self.squeeze_blank, self.collapse_spaces,
+            self.show_tabs, self.tab_replacement.is_some(), self.expand_tabs, self.reveal.is_some(), self.show_nonprinting, self.ascii_only,
+            self.wrap.is_some_and(|cols| cols > 0), self.output_separator.is_some(), self.frame, self.hexdump, self.tsv_align,
+            self.columns, self.diff, self.merge_sorted, self.interleave.is_some(), self.add_bom, self.lines_per_page.is_some(),
+            self.skip_header, self.preview.is_some(), self.metadata, self.count_bytes_only, self.stop_at.is_some(), !self.replacements.is_empty(), self.limit_rate.is_some(),
+        ];
+        let checked = [
+            self.stats || self.stats_only, self.line_stats, self.check_utf8, self.check_final_newline, self.text_only,
+        ];
+        if changed.into_iter().chain(checked).any(|set| set) {
+            return false;
+        }
+
+        #[cfg(feature = "encoding")]
+        if self.from_encoding.is_some() {
+            return false;
+        }
+        #[cfg(feature = "base64")]
+        if self.base64_decode || self.base64_encode {
+            return false;
+        }
+        #[cfg(feature = "gzip")]
+        if self.gzip {
+            return false;
+        }
+
+        true
+    }
+
+    // whether the output has to be assembled a whole line at a time
+    fn line_oriented(&self) -> bool {
+        if self.squeeze_whitespace || self.strip_trailing_whitespace || self.sample.is_some() || self.uniq.is_some() || self.fields.is_some()
+            || self.byte_offset.is_some() {
+            return true;
+        }
+
+        // letters outside ASCII take more than the one byte the transform sees at a time
+        #[cfg(feature = "unicode")]
+        if self.fold_case.is_some() {
+            return true;
+        }
+
+        #[cfg(feature = "crc32")]
+        if self.checksum_per_line {
+            return true;
+        }
+
+        #[cfg(feature = "regex")]
+        if self.number_matching.is_some() || self.grep.is_some() {
+            return true;
+        }
+
+        false
+    }
+
+    // the flags the byte by byte rendering cares about
+    fn transform_options(&self) -> transform::Options<'_> {
+        let numbering = if self.number_nonblank {
+            Numbering::NonBlank
+        } else if self.number_lines {
+            Numbering::All
+        } else {
+            Numbering::None
+        };
+
+        transform::Options {
+            line_prefix: if self.tap { TAP_PREFIX } else { b"" },
+            fold_case: self.fold_case,
+            #[cfg(feature = "regex")]
+            numbering: if self.number_matching.is_some() { Numbering::Marked } else { numbering },
+            #[cfg(not(feature = "regex"))]
+            numbering,
+            number_start: self.number_start,
+            max_line_number: self.max_line_number,
+            number_overflow: self.number_overflow.unwrap_or_default(),
+            number_radix: self.number_radix,
+            byte_offset: self.byte_offset,
+            squeeze_blank: self.squeeze_blank,
+            collapse_spaces: self.collapse_spaces,
+            line_end: match self.normalize_newlines {
+                _ if self.null_output => b"\0",
+                Some(newline) => newline.bytes(),
+                None => self.record_delimiter.as_deref().unwrap_or(b"\n"),
+            },
+            explicit_line_ends: self.record_delimiter.is_some(),
+            show_ends: self.show_ends,
+            end_marker: &self.end_marker,
+            show_tabs: self.show_tabs,
+            tab_replacement: self.tab_replacement.as_deref(),
+            expand_tabs: self.expand_tabs,
+            tab_size: self.tab_size,
+            show_nonprinting: self.show_nonprinting,
+            ascii_placeholder: self.ascii_only.then_some(&self.ascii_placeholder[..]),
+            reveal: self.reveal,
+            #[cfg(feature = "encoding")]
+            from_encoding: self.from_encoding,
+            order: self.transform_order.unwrap_or(Stage::DEFAULT_ORDER),
+        }
+    }
+}
+
+/// Keeps output under `--limit-rate` bytes per second by sleeping between writes.
+/// The pace is measured from the first write, so it's an average and only approximate.
+struct RateLimiter {
+    rate: u64,
+    start: std::time::Instant,
+    written: u64,
+}
+
+impl RateLimiter {
+    fn new(rate: u64) -> Self {
+        Self { rate, start: std::time::Instant::now(), written: 0 }
+    }
+
+    fn write_all<W: Write>(&mut self, write_to: &mut W, bytes: &[u8]) -> std::io::Result<()> {
+        // small slices, so the output trickles instead of coming in bursts
+        let slice = (self.rate / 10).max(1) as usize;
+
+        for part in bytes.chunks(slice) {
+            write_to.write_all(part)?;
+            // flushed right away, sleeping with the data stuck in some buffer would be pointless
+            write_to.flush()?;
+            self.written += part.len() as u64;
+
+            let due = Duration::from_secs_f64(self.written as f64 / self.rate as f64);
+            if let Some(ahead) = due.checked_sub(self.start.elapsed()) {
+                std::thread::sleep(ahead);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// what --metadata says about a source: size, modification time and, on Unix, permissions of a FILE,
+// nothing for a FILE that can't be looked at, reading it will tell what's wrong
+fn describe_source(source: &Source) -> Option<String> {
+    let Source::File(path, _) = source else {
+        return Some("(stream)".into());
+    };
+    let meta = std::fs::metadata(path).ok()?;
+
+    let mut description = format!("({} bytes", meta.len());
+    if let Ok(modified) = meta.modified() {
+        description += &format!(", modified {}", utc_timestamp(modified));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        description += &format!(", mode {:04o}", meta.permissions().mode() & 0o7777);
+    }
+    description.push(')');
+    Some(description)
+}
+
+// `time` as 2006-01-02T15:04:05Z, times before 1970 as 1970
+fn utc_timestamp(time: std::time::SystemTime) -> String {
+    let secs = time.duration_since(std::time::UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // days since 1970-01-01 to a date, Howard Hinnant's civil_from_days with years starting in March
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+
+    format!("{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+// the offset a --checkpoint FILE holds, in decimal
+fn read_checkpoint(path: &str) -> Result<u64, RatError> {
+    let error = |source| RatError::Checkpoint { path: path.to_string(), source };
+    let contents = std::fs::read_to_string(path).map_err(error)?;
+    contents.trim_end().parse::<u64>()
+        .map_err(|_| error(std::io::Error::new(std::io::ErrorKind::InvalidData, "not a byte offset")))
+}
+
+fn write_checkpoint(path: &str, offset: u64) -> Result<(), RatError> {
+    std::fs::write(path, format!("{offset}\n")).map_err(|source| RatError::Checkpoint { path: path.to_string(), source })
+}
+
+// a byte count with an optional K, M or G (powers of 1024) suffix
+fn parse_size(value: &str) -> Option<u64> {
+    let (digits, multiplier) = match value.as_bytes().last()?.to_ascii_uppercase() {
+        b'K' => (&value[..value.len() - 1], 1 << 10),
+        b'M' => (&value[..value.len() - 1], 1 << 20),
+        b'G' => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+
+    digits.parse::<u64>().ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|rate| *rate > 0)
+}
+
+// a --columns line with its tabs as spaces, so they can't throw off the padding
+fn expand_tabs(line: &[u8], tab_size: usize) -> Vec<u8> {
+    let options = transform::Options { expand_tabs: true, tab_size, ..Default::default() };
+    let mut expanded = Vec::with_capacity(line.len() + tab_size);
+    let mut sink = |bytes: &[u8]| -> Result<(), std::convert::Infallible> {
+        expanded.extend_from_slice(bytes);
+        Ok(())
+    };
+
+    let Ok(()) = Transform::new(options).transform(line, &mut sink);
+    expanded
+}
+
+/// Input kept in memory by `--tsv-align`, `--diff` and `--uniq-global`, which `--max-memory` puts a cap on.
+struct MemoryBudget {
+    limit: Option<u64>,
+    used: u64,
+}
+
+impl MemoryBudget {
+    fn new(limit: Option<u64>) -> Self {
+        Self { limit, used: 0 }
+    }
+
+    // counts `bytes` more as kept, failing once that's more than the limit
+    fn take(&mut self, bytes: usize) -> Result<(), RatError> {
+        self.used += bytes as u64;
+        match self.limit {
+            Some(limit) if self.used > limit => Err(RatError::MemoryLimit { limit }),
+            _ => Ok(()),
+        }
+    }
+
+    // how much more may be read before the limit is known to be exceeded
+    fn read_limit(&self) -> u64 {
+        self.limit.map_or(u64::MAX, |limit| limit.saturating_sub(self.used).saturating_add(1))
+    }
+}
+
+// --tsv-align: pads every field but the last of a line to the widest one of its column,
+// rows with fewer fields just end early. With `numbers_right` the columns whose cells all
+// look like numbers, but for a header, are padded on the left instead, their last fields too.
+fn align_tsv(source: &[u8], numbers_right: bool) -> Vec<u8> {
+    fn content(line: &[u8]) -> Vec<&[u8]> {
+        line.strip_suffix(b"\n").unwrap_or(line).split(|&b| b == b'\t').collect()
+    }
+    let width = |field: &[u8]| String::from_utf8_lossy(field).chars().count();
+
+    let lines = source.split_inclusive(|&b| b == b'\n').collect::<Vec<_>>();
+
+    // empty cells don't decide, a column needs a number to be numeric; a header on the first
+    // line doesn't count against it
+    let mut numeric = Vec::<Option<bool>>::new();
+    if numbers_right {
+        for (row, line) in lines.iter().enumerate() {
+            for (i, field) in content(line).into_iter().enumerate() {
+                if i == numeric.len() {
+                    numeric.push(None);
+                }
+                if !field.is_empty() && (row > 0 || looks_numeric(field)) {
+                    numeric[i] = Some(numeric[i].unwrap_or(true) && looks_numeric(field));
+                }
+            }
+        }
+    }
+    let right = |i: usize| numeric.get(i).copied().flatten().unwrap_or(false);
+
+    let mut widths = Vec::<usize>::new();
+    for line in &lines {
+        let fields = content(line);
+        // the last field is never padded, so it doesn't widen its column, unless it's padded on the left
+        let padded = fields.len() - 1 + right(fields.len() - 1) as usize;
+        for (i, field) in fields[..padded].iter().enumerate() {
+            match widths.get_mut(i) {
+                Some(max) => *max = (*max).max(width(field)),
+                None => widths.push(width(field)),
+            }
+        }
+    }
+
+    let mut aligned = Vec::with_capacity(source.len());
+    for line in lines {
+        let fields = content(line);
+        let last = fields.len() - 1;
+        for (i, field) in fields.into_iter().enumerate() {
+            if right(i) {
+                aligned.resize(aligned.len() + widths[i] - width(field), b' ');
+                aligned.extend_from_slice(field);
+                if i < last {
+                    aligned.extend_from_slice(b"  ");
+                }
+            } else {
+                aligned.extend_from_slice(field);
+                if i < last {
+                    aligned.resize(aligned.len() + widths[i] - width(field) + 2, b' ');
+                }
+            }
+        }
+        if line.ends_with(b"\n") {
+            aligned.push(b'\n');
+        }
+    }
+    aligned
+}
+
+// an optional sign, digits and at most one decimal point somewhere among them
+fn looks_numeric(field: &[u8]) -> bool {
+    let digits = field.strip_prefix(b"-").or_else(|| field.strip_prefix(b"+")).unwrap_or(field);
+    digits.iter().any(u8::is_ascii_digit)
+        && digits.iter().all(|&b| b.is_ascii_digit() || b == b'.')
+        && digits.iter().filter(|&&b| b == b'.').count() <= 1
+}
+
+/// `--hexdump` lines, 16 bytes each, with the offset running on across sources.
+#[derive(Default)]
+struct HexDump {
+    offset: u64,
+    line: [u8; 16],
+    len: usize,
+}
+
+impl HexDump {
+    fn feed(&mut self, bytes: &[u8], out: &mut Vec<u8>) {
+        for &byte in bytes {
+            self.line[self.len] = byte;
+            self.len += 1;
+            if self.len == self.line.len() {
+                self.flush_line(out);
+            }
+        }
+    }
+
+    // the partial last line, then the total like hexdump does
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        if self.len > 0 {
+            self.flush_line(out);
+        }
+        if self.offset > 0 {
+            out.extend_from_slice(format!("{:08x}\n", self.offset).as_bytes());
+        }
+    }
+
+    fn flush_line(&mut self, out: &mut Vec<u8>) {
+        let bytes = &self.line[..self.len];
+        out.extend_from_slice(format!("{:08x}  ", self.offset).as_bytes());
+
+        // a short line is padded so the ASCII column stays in place
+        for i in 0..self.line.len() {
+            match bytes.get(i) {
+                Some(byte) => out.extend_from_slice(format!("{byte:02x} ").as_bytes()),
+                None => out.extend_from_slice(b"   "),
+            }
+            if i == 7 {
+                out.push(b' ');
+            }
+        }
+
+        out.extend_from_slice(b" |");
+        out.extend(bytes.iter().map(|&b| if (0x20..0x7f).contains(&b) { b } else { b'.' }));
+        out.extend_from_slice(b"|\n");
+
+        self.offset += self.len as u64;
+        self.len = 0;
+    }
+}
+
+// what --add-bom starts the output with
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+// renders input bytes the way the flags ask for and buffers them on their way to `write_to`,
+// everything that carries over between chunks and sources lives here
+struct Output<'a, W: Write> {
+    args: &'a RatArgs,
+    write_to: &'a mut W,
+    out_buf: Box<[u8]>,
+    out_pos: usize,
+    // room to leave in out_buf before rendering another byte
+    reserve: usize,
+    transform: Transform<'a>,
+    // current line, for flags that need to see all of it before rendering, and where it started
+    line: Vec<u8>,
+    line_offset: u64,
+    prev_line_blank: bool,
+    // the last line written for --uniq, every one so far for --uniq-global, without newlines
+    prev_line: Option<Vec<u8>>,
+    seen_lines: std::collections::HashSet<Vec<u8>>,
+    seen_budget: MemoryBudget,
+    // input bytes of the current source, and of all of them
+    source_bytes: u64,
+    total_bytes: u64,
+    // lines written so far, squeezed ones don't count, for --lines-per-page
+    lines_out: usize,
+    // bytes rendered since the last line break, for --wrap
+    wrap_column: usize,
+    limiter: Option<RateLimiter>,
+    // input lines seen so far, for --sample
+    lines_in: u64,
+    // lines --grep matched in the current source
+    #[cfg(feature = "regex")]
+    matched: u64,
+    // --context lines waiting for a match, lines still to write after the last one, whether
+    // lines were left out since the last written one and whether any were written yet
+    #[cfg(feature = "regex")]
+    before_context: std::collections::VecDeque<(Vec<u8>, u64)>,
+    #[cfg(feature = "regex")]
+    after_context: usize,
+    #[cfg(feature = "regex")]
+    context_gap: bool,
+    #[cfg(feature = "regex")]
+    lines_in_context: bool,
+    hexdump: Option<HexDump>,
+    #[cfg(feature = "base64")]
+    base64: Option<base64::Encoder>,
+    line_transform: Option<&'a mut LineTransform>,
+    // the rendered output line being collected for line_transform
+    staged: Vec<u8>,
+    // what was written of a --record-delimiter so far, held back until it's clear whether it's complete
+    partial_delimiter: Vec<u8>,
+    // with --frame, the output not sent as a frame yet
+    frame: Option<FrameUnit>,
+    framed: Vec<u8>,
+    // bytes handed to write_to so far, for --fail-if-empty
+    written: u64,
+}
+
+impl<'a, W: Write> Output<'a, W> {
+    fn new(args: &'a RatArgs, write_to: &'a mut W, mut out_buf: Box<[u8]>, line_transform: Option<&'a mut LineTransform>) -> Self {
+        // the buffer is at least MIN_BUFSIZE, there's always room for it
+        let mut out_pos = 0;
+        if args.add_bom {
+            out_buf[..UTF8_BOM.len()].copy_from_slice(UTF8_BOM);
+            out_pos = UTF8_BOM.len();
+        }
+
+        Self {
+            args,
+            write_to,
+            out_buf,
+            out_pos,
+            reserve: args.transform_options().max_expansion(),
+            transform: Transform::new(args.transform_options()),
+            line: Vec::new(),
+            line_offset: 0,
+            prev_line_blank: false,
+            prev_line: None,
+            seen_lines: std::collections::HashSet::new(),
+            seen_budget: MemoryBudget::new(args.max_memory),
+            source_bytes: 0,
+            total_bytes: 0,
+            lines_out: 0,
+            wrap_column: 0,
+            lines_in: 0,
+            limiter: args.limit_rate.map(RateLimiter::new),
+            #[cfg(feature = "regex")]
+            matched: 0,
+            #[cfg(feature = "regex")]
+            before_context: std::collections::VecDeque::new(),
+            #[cfg(feature = "regex")]
+            after_context: 0,
+            #[cfg(feature = "regex")]
+            context_gap: false,
+            #[cfg(feature = "regex")]
+            lines_in_context: false,
+            hexdump: args.hexdump.then(HexDump::default),
+            #[cfg(feature = "base64")]
+            base64: args.base64_encode.then(|| base64::Encoder::new(args.wrap.unwrap_or(BASE64_WRAP))),
+            line_transform,
+            staged: Vec::new(),
+            partial_delimiter: Vec::new(),
+            frame: args.frame.then(|| args.frame_unit.unwrap_or_default()),
+            framed: Vec::new(),
+            written: 0,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), RatError> {
+        let offset = self.total_bytes;
+        self.source_bytes += bytes.len() as u64;
+        self.total_bytes += bytes.len() as u64;
+
+        if let Some(mut hexdump) = self.hexdump.take() {
+            let mut lines = Vec::new();
+            hexdump.feed(bytes, &mut lines);
+            self.hexdump = Some(hexdump);
+            return self.push(&lines);
+        }
+
+        #[cfg(feature = "base64")]
+        if let Some(mut base64) = self.base64.take() {
+            let mut encoded = Vec::new();
+            base64.feed(bytes, &mut encoded);
+            self.base64 = Some(base64);
+            return self.push(&encoded);
+        }
+
+        if self.args.line_oriented() {
+            for (i, &byte) in bytes.iter().enumerate() {
+                if self.line.is_empty() {
+                    self.line_offset = offset + i as u64;
+                }
+                self.line.push(byte);
+                if byte == b'\n' {
+                    self.render_line()?;
+                }
+            }
+            return Ok(());
+        }
+
+        if let Some(delimiter) = self.args.record_delimiter.as_deref() {
+            for &byte in bytes {
+                self.partial_delimiter.push(byte);
+                if self.partial_delimiter == delimiter {
+                    self.partial_delimiter.clear();
+                    self.render_unit(b'\n', true)?;
+                    continue;
+                }
+
+                // what can't be the start of the delimiter anymore is content
+                let content = (0..self.partial_delimiter.len())
+                    .find(|&start| delimiter.starts_with(&self.partial_delimiter[start..]))
+                    .unwrap_or(self.partial_delimiter.len());
+                for i in 0..content {
+                    self.render(self.partial_delimiter[i])?;
+                }
+                self.partial_delimiter.drain(..content);
+            }
+            return Ok(());
+        }
+
+        for &byte in bytes {
+            self.render(byte)?;
+        }
+        Ok(())
+    }
+
+    fn render_line(&mut self) -> Result<(), RatError> {
+        let mut line = std::mem::take(&mut self.line);
+        let offset = self.line_offset;
+
+        // a line without a newline yet may still get more content, so it keeps its whitespace
+        if self.args.strip_trailing_whitespace && line.last() == Some(&b'\n') {
+            let newline = line.len() - 1;
+            let content_end = line[..newline].iter().rposition(|&b| b != b' ' && b != b'\t').map_or(0, |i| i + 1);
+            line.drain(content_end..newline);
+        }
+
+        if let Some(every) = self.args.sample {
+            self.lines_in += 1;
+            if self.lines_in % every != self.args.sample_offset {
+                self.skip_line(&line);
+
+                self.line = line;
+                self.line.clear();
+                return Ok(());
+            }
+        }
+
+        #[cfg(feature = "regex")]
+        if let Some(grep) = &self.args.grep {
+            let content = line.strip_suffix(b"\n").unwrap_or(&line);
+            let matched = grep.is_match(content);
+            self.matched += matched as u64;
+
+            if self.args.count_matching.is_some() || (!matched && self.args.context.is_none()) {
+                self.line = line;
+                self.line.clear();
+                return Ok(());
+            }
+
+            if let Some(context) = self.args.context {
+                return self.write_in_context(line, offset, matched, context);
+            }
+        }
+
+        self.write_line(line, offset)
+    }
+
+    // a line that isn't written still counts for the numbering
+    fn skip_line(&mut self, line: &[u8]) {
+        #[cfg(feature = "regex")]
+        if let Some(regex) = &self.args.number_matching {
+            self.transform.mark_line(regex.is_match(line.strip_suffix(b"\n").unwrap_or(line)));
+        }
+        self.transform.skip_line(line == b"\n");
+    }
+
+    // --context: non matching lines wait in before_context until a match takes them along or
+    // they are pushed out, after a match the next `context` lines are written right away
+    #[cfg(feature = "regex")]
+    fn write_in_context(&mut self, line: Vec<u8>, offset: u64, matched: bool, context: usize) -> Result<(), RatError> {
+        if !matched && self.after_context == 0 {
+            self.before_context.push_back((line, offset));
+            if self.before_context.len() > context {
+                let (dropped, _) = self.before_context.pop_front().unwrap();
+                self.skip_line(&dropped);
+                self.context_gap = true;
+            }
+            return Ok(());
+        }
+
+        // lines were left out since the last group
+        if std::mem::take(&mut self.context_gap) && self.lines_in_context {
+            let args = self.args;
+            self.push(b"--")?;
+            self.push(args.transform_options().line_end)?;
+        }
+        self.lines_in_context = true;
+
+        while let Some((before, before_offset)) = self.before_context.pop_front() {
+            self.write_line(before, before_offset)?;
+        }
+        self.after_context = if matched { context } else { self.after_context - 1 };
+        self.write_line(line, offset)
+    }
+
+    // what's left to do about a line that is written, `offset` is where it started in the input
+    fn write_line(&mut self, mut line: Vec<u8>, offset: u64) -> Result<(), RatError> {
+        self.transform.set_line_offset(offset);
+        if let Some(fields) = &self.args.fields {
+            let delimiter = match self.args.field_delimiter.as_slice() {
+                [] => b"\t",
+                delimiter => delimiter,
+            };
+            line = select_fields(&line, fields, delimiter);
+        }
+
+        // the transform only folds ASCII, the valid UTF-8 of the line is folded here as a whole
+        #[cfg(feature = "unicode")]
+        if let Some(case) = self.args.fold_case {
+            let mut folded = Vec::with_capacity(line.len());
+            for chunk in line.utf8_chunks() {
+                let valid = match case {
+                    Case::Lower => chunk.valid().to_lowercase(),
+                    Case::Upper => chunk.valid().to_uppercase(),
+                };
+                folded.extend_from_slice(valid.as_bytes());
+                folded.extend_from_slice(chunk.invalid());
+            }
+            line = folded;
+        }
+
+        let repeated = match self.args.uniq {
+            Some(Uniq::Adjacent) => {
+                let content = line.strip_suffix(b"\n").unwrap_or(&line);
+                let repeated = self.prev_line.as_deref() == Some(content);
+                if !repeated {
+                    self.prev_line = Some(content.to_vec());
+                }
+                repeated
+            },
+            Some(Uniq::Global) => {
+                let content = line.strip_suffix(b"\n").unwrap_or(&line);
+                let repeated = self.seen_lines.contains(content);
+                if !repeated {
+                    if let Err(e) = self.seen_budget.take(content.len()) {
+                        // what came before the limit was hit is still written
+                        self.write_out()?;
+                        return Err(e);
+                    }
+                    self.seen_lines.insert(content.to_vec());
+                }
+                repeated
+            },
+            None => false,
+        };
+        if repeated {
+            self.line = line;
+            self.line.clear();
+            return Ok(());
+        }
+
+        if self.args.squeeze_whitespace {
+            let content = line.strip_suffix(b"\n").unwrap_or(&line);
+            let blank = content.iter().all(|&b| b == b' ' || b == b'\t');
+            let squeezed = blank && self.prev_line_blank;
+            self.prev_line_blank = blank;
+
+            if squeezed {
+                self.line = line;
+                self.line.clear();
+                return Ok(());
+            }
+        }
+
+        #[cfg(feature = "regex")]
+        if let Some(regex) = &self.args.number_matching {
+            let content = line.strip_suffix(b"\n").unwrap_or(&line);
+            self.transform.mark_line(regex.is_match(content));
+        }
+
+        // the checksum is rendered as a part of the line, so an empty one still gets its number first
+        #[cfg(feature = "crc32")]
+        if self.args.checksum_per_line {
+            let (content, newline) = match line.strip_suffix(b"\n") {
+                Some(content) => (content, &b"\n"[..]),
+                None => (&line[..], &b""[..]),
+            };
+            let checksum = format!("\t{:08x}", crc32fast::hash(content));
+            for &byte in content.iter().chain(checksum.as_bytes()).chain(newline) {
+                self.render(byte)?;
+            }
+
+            self.line = line;
+            self.line.clear();
+            return Ok(());
+        }
+
+        for &byte in &line {
+            self.render(byte)?;
+        }
+
+        // keep the allocation around for the next line
+        self.line = line;
+        self.line.clear();
+        Ok(())
+    }
+
+    fn render(&mut self, byte: u8) -> Result<(), RatError> {
+        self.render_unit(byte, byte == b'\n' && self.args.record_delimiter.is_none())
+    }
+
+    // renders a byte, or a line end when `end` is set, which is a newline unless there's a --record-delimiter
+    fn render_unit(&mut self, byte: u8, end: bool) -> Result<(), RatError> {
+        let render = |transform: &mut Transform, out: &mut [u8]| match end {
+            true => transform.render_line_end(out),
+            false => transform.render(byte, out),
+        };
+
+        // the break is pushed, so the transform carries on with the same line and doesn't number it
+        if let Some(wrap) = self.args.wrap.filter(|&wrap| wrap > 0) {
+            if end {
+                self.wrap_column = 0;
+            } else if self.wrap_column == wrap {
+                let args = self.args;
+                self.push(args.transform_options().line_end)?;
+                self.push(&args.wrap_marker)?;
+                self.wrap_column = 0;
+            }
+        }
+
+        let written = if self.line_transform.is_some() {
+            let len = self.staged.len();
+            self.staged.resize(len + self.reserve, 0);
+            let written = render(&mut self.transform, &mut self.staged[len..]);
+            self.staged.truncate(len + written);
+            if end && written > 0 {
+                self.push_staged()?;
+            }
+            written
+        } else {
+            if self.out_pos + self.reserve > self.out_buf.len() {
+                self.write_out()?;
+            }
+
+            let written = render(&mut self.transform, &mut self.out_buf[self.out_pos..]);
+            self.out_pos += written;
+            written
+        };
+
+        if !end && written > 0 {
+            self.wrap_column += 1;
+        }
+
+        // a squeezed newline doesn't end a line of output
+        if let Some(lines_per_page) = self.args.lines_per_page.filter(|_| end && written > 0) {
+            self.lines_out += 1;
+            if self.lines_out.is_multiple_of(lines_per_page) {
+                self.push(b"\x0c")?;
+            }
+        }
+        if self.args.line_buffered && end && written > 0 {
+            self.write_out()?;
+            self.write_to.flush().map_err(RatError::from_write)?;
+        }
+        Ok(())
+    }
+
+    // copies bytes that aren't rendered from input, like markers, to the output
+    fn push(&mut self, bytes: &[u8]) -> Result<(), RatError> {
+        // anything pushed ends an unfinished line, which goes first
+        if !self.staged.is_empty() {
+            self.push_staged()?;
+        }
+
+        if self.out_pos + bytes.len() > self.out_buf.len() {
+            self.write_out()?;
+        }
+
+        if bytes.len() > self.out_buf.len() && self.frame.is_some() {
+            self.framed.extend_from_slice(bytes);
+            return self.write_frames(false);
+        }
+        if bytes.len() > self.out_buf.len() {
+            self.written += bytes.len() as u64;
+            return match self.limiter.as_mut() {
+                Some(limiter) => limiter.write_all(self.write_to, bytes),
+                None => self.write_to.write_all(bytes),
+            }.map_err(RatError::from_write);
+        }
+
+        self.out_buf[self.out_pos..self.out_pos + bytes.len()].copy_from_slice(bytes);
+        self.out_pos += bytes.len();
+        Ok(())
+    }
+
+    // hands the staged line to line_transform and pushes what comes back
+    fn push_staged(&mut self) -> Result<(), RatError> {
+        let Some(line_transform) = self.line_transform.as_mut() else {
+            return Ok(());
+        };
+
+        let staged = std::mem::take(&mut self.staged);
+        let line = (line_transform.0)(&staged);
+        self.push(&line)?;
+        drop(line);
+
+        // keep the allocation around for the next line
+        self.staged = staged;
+        self.staged.clear();
+        Ok(())
+    }
+
+    // called once a source has been read completely
+    fn end_source(&mut self) -> Result<(), RatError> {
+        let written = std::mem::take(&mut self.source_bytes);
+
+        // a delimiter doesn't go on in the next source
+        for byte in std::mem::take(&mut self.partial_delimiter) {
+            self.render(byte)?;
+        }
+
+        // the last line of a source is matched on its own
+        #[cfg(feature = "regex")]
+        if self.args.grep.is_some() && !self.line.is_empty() {
+            self.render_line()?;
+        }
+
+        if self.args.reveal.is_some() {
+            if !self.line.is_empty() {
+                self.render_line()?;
+            }
+
+            if written > 0 && !self.transform.at_line_start() {
+                self.push(Reveal::NO_NEWLINE)?;
+                self.transform.end_line();
+            }
+        } else if self.args.show_ends_eof {
+            if !self.line.is_empty() {
+                self.render_line()?;
+            }
+
+            // only the marker, the line itself goes on in the next source like it would without it
+            if written > 0 && !self.transform.at_line_start() {
+                let args = self.args;
+                self.push(&args.end_marker)?;
+            }
+        }
+
+        if self.args.squeeze_per_source {
+            self.transform.reset_squeeze();
+            self.prev_line_blank = false;
+        }
+
+        // an empty source gets an empty frame, so there's one for every source
+        if self.frame == Some(FrameUnit::Source) {
+            self.write_out()?;
+            self.send_frame(0..self.framed.len())?;
+            self.framed.clear();
+        }
+
+        Ok(())
+    }
+
+    // lines --grep matched since the last call
+    #[cfg(feature = "regex")]
+    fn take_matched(&mut self) -> u64 {
+        std::mem::take(&mut self.matched)
+    }
+
+    // ends a line the previous source left unfinished, before something of rat's own
+    fn end_unfinished_line(&mut self) -> Result<(), RatError> {
+        if !self.line.is_empty() {
+            self.render_line()?;
+        }
+        if !self.transform.at_line_start() {
+            let args = self.args;
+            self.push(args.transform_options().line_end)?;
+            self.transform.end_line();
+        }
+        Ok(())
+    }
+
+    // the status exec ends with, given the one it got so far: --fail-if-empty's when that's 0 and nothing was written
+    fn exit_code(&self, so_far: i32) -> i32 {
+        match self.args.fail_if_empty {
+            Some(status) if so_far == 0 && self.written == 0 => status,
+            _ => so_far,
+        }
+    }
+
+    // the ==> NAME <== of --preview and --metadata before every source, with an empty line between them like head
+    fn header(&mut self, name: &str, after_another: bool) -> Result<(), RatError> {
+        self.end_unfinished_line()?;
+        let line_end = self.args.transform_options().line_end;
+        if after_another {
+            self.push(line_end)?;
+        }
+        self.push(format!("==> {name} <==").as_bytes())?;
+        self.push(line_end)
+    }
+
+    // --preview's ... line after a source that had more to it, on a line of its own
+    fn mark_truncated(&mut self) -> Result<(), RatError> {
+        self.end_unfinished_line()?;
+        let line_end = self.args.transform_options().line_end;
+        self.push(b"...")?;
+        self.push(line_end)
+    }
+
+    // writes the --output-separator, after what is left of the previous source
+    fn separate(&mut self, separator: &[u8]) -> Result<(), RatError> {
+        if !self.line.is_empty() {
+            self.render_line()?;
+        }
+
+        self.push(separator)
+    }
+
+    // renders whatever is left of an unfinished line and flushes everything
+    fn finish(&mut self) -> Result<(), RatError> {
+        if !self.line.is_empty() {
+            self.render_line()?;
+        }
+        if !self.staged.is_empty() {
+            self.push_staged()?;
+        }
+        // a TAP consumer reads whole lines, the last one included
+        if self.args.tap {
+            self.end_unfinished_line()?;
+        }
+
+        if let Some(mut hexdump) = self.hexdump.take() {
+            let mut lines = Vec::new();
+            hexdump.finish(&mut lines);
+            self.push(&lines)?;
+        }
+
+        #[cfg(feature = "base64")]
+        if let Some(mut base64) = self.base64.take() {
+            let mut encoded = Vec::new();
+            base64.finish(&mut encoded);
+            self.push(&encoded)?;
+        }
+
+        self.write_out()?;
+        if self.frame.is_some() {
+            self.write_frames(true)?;
+        }
+        self.write_to.flush().map_err(RatError::from_write)
+    }
+
+    // writes out what is buffered so far, unlike finish it leaves an unfinished line unfinished
+    fn flush(&mut self) -> Result<(), RatError> {
+        self.write_out()?;
+        self.write_to.flush().map_err(RatError::from_write)
+    }
+
+    // hands the buffered output to write_to, at the --limit-rate pace if there is one
+    fn write_out(&mut self) -> Result<(), RatError> {
+        let pending = &self.out_buf[..self.out_pos];
+        if self.frame.is_some() {
+            self.framed.extend_from_slice(pending);
+            self.out_pos = 0;
+            return self.write_frames(false);
+        }
+        match self.limiter.as_mut() {
+            Some(limiter) => limiter.write_all(self.write_to, pending),
+            None => self.write_to.write_all(pending),
+        }.map_err(RatError::from_write)?;
+
+        self.written += pending.len() as u64;
+        self.out_pos = 0; // Reset after flush
+        Ok(())
+    }
+
+    // sends what makes whole frames of the output collected for --frame, and the rest too at the end
+    fn write_frames(&mut self, at_end: bool) -> Result<(), RatError> {
+        let mut sent = 0;
+        if self.frame == Some(FrameUnit::Line) {
+            let line_end = self.args.transform_options().line_end;
+            while let Some(at) = self.framed[sent..].windows(line_end.len()).position(|window| window == line_end) {
+                let end = sent + at + line_end.len();
+                self.send_frame(sent..end)?;
+                sent = end;
+            }
+        }
+        if at_end && sent < self.framed.len() {
+            self.send_frame(sent..self.framed.len())?;
+            sent = self.framed.len();
+        }
+        self.framed.drain(..sent);
+        Ok(())
+    }
+
+    // sends `range` of the output collected for --frame after its length
+    fn send_frame(&mut self, range: std::ops::Range<usize>) -> Result<(), RatError> {
+        let header = u32::try_from(range.len())
+            .map_err(|_| RatError::from_write(std::io::Error::new(std::io::ErrorKind::InvalidData, "frame longer than 4G")))?
+            .to_be_bytes();
+        for bytes in [&header[..], &self.framed[range]] {
+            match self.limiter.as_mut() {
+                Some(limiter) => limiter.write_all(self.write_to, bytes),
+                None => self.write_to.write_all(bytes),
+            }.map_err(RatError::from_write)?;
+            self.written += bytes.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+type LineFn = dyn FnMut(&[u8]) -> Cow<'_, [u8]>;
+
+// the hook set by Rat::with_line_transform
+struct LineTransform(Box<LineFn>);
+
+impl std::fmt::Debug for LineTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("LineTransform")
+    }
+}
+
+#[derive(Debug)]
+pub struct Rat<T: Write, E: Write = std::io::Stderr> {
+    args: RatArgs,
+    write_to: T,
+    // diagnostics and reports go here
+    err_to: E,
+    exit_code: i32,
+    // write_to is a terminal, for the flags that only matter then
+    to_terminal: bool,
+    line_transform: Option<LineTransform>,
+    // where the numbering of the last exec left off, the next one carries on from it
+    line_state: Option<LineState>,
+}
+
+impl<T: Write> Rat<T> {
+    pub fn new(args: RatArgs, write_to: T) -> Self {
+        Self { args, write_to, err_to: std::io::stderr(), exit_code: 0, to_terminal: false, line_transform: None, line_state: None }
+    }
+}
+
+impl<T: Write, E: Write> Rat<T, E> {
+    /// Redirects diagnostics and reports (stderr by default) to `err_to`.
+    pub fn err_to<E2: Write>(self, err_to: E2) -> Rat<T, E2> {
+        Rat {
+            args: self.args,
+            write_to: self.write_to,
+            err_to,
+            exit_code: self.exit_code,
+            to_terminal: self.to_terminal,
+            line_transform: self.line_transform,
+            line_state: self.line_state,
+        }
+    }
+
+    /// Calls `f` on every line of output rendered from the sources, line end included, and writes
+    /// what it returns instead. It runs after all of the built in transformations, so it sees the
+    /// numbers, markers and tabs as they would be written, but before `--lines-per-page` and
+    /// `--output-separator` add theirs. Output of `--hexdump` and `--base64-encode` and reports
+    /// like `--count-matching` don't go through it.
+    ///
+    /// This forces line buffering: each line is held back until it is complete, like a line
+    /// continuing into the next source, only a last line without a line end is passed as is.
+    pub fn with_line_transform(mut self, f: impl FnMut(&[u8]) -> Cow<'_, [u8]> + 'static) -> Self {
+        self.line_transform = Some(LineTransform(Box::new(f)));
+        self
+    }
+
+    /// Tells whether `write_to` is a terminal, which `--detect-binary` needs to know.
+    pub fn to_terminal(mut self, to_terminal: bool) -> Self {
+        self.to_terminal = to_terminal;
+        self
+    }
+
+    /// Number the next numbered line gets. It starts at `--numbers-from` and every `exec` carries
+    /// on from where the one before left off, a line it left unfinished included.
+    pub fn line_index(&self) -> u64 {
+        self.line_state.map_or(self.args.number_start, |state| state.index)
+    }
+
+    /// Makes the next numbered line `index`, for the next `exec`.
+    pub fn set_line_index(&mut self, index: u64) {
+        let state = self.line_state.get_or_insert(LineState::new(index));
+        state.index = index;
+    }
+
+    /// Exit status of the last `exec`, 0 on success.
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code
+    }
+
+    /// Copies every source to the output. Sources that fail are reported and skipped
+    /// (making the exit code 1), unless `--abort-on-error` is given, which returns the error instead.
+    /// Failing to write the output is always an error.
+    pub fn exec(mut self) -> Result<Self, RatError> {
+        let args = &self.args;
+
+        if args.help {
+            print!("{}", usage());
+            return Ok(self);
+        }
+
+        if args.version {
+            println!("{} {}", RAT_NAME, RAT_VERSION);
+            return Ok(self);
+        }
+
+        // only flags were given, read stdin like with no arguments at all
+        if self.args.files.is_empty() {
+            let stdin = self.args.stdin();
+            self.args.files.push(stdin);
+        }
+
+        // the sources are put back in the order they were given once done
+        if self.args.reverse_files {
+            self.args.files.reverse();
+            self.args.reverse_files = false;
+            return self.exec().map(|mut rat| {
+                rat.args.files.reverse();
+                rat.args.reverse_files = true;
+                rat
+            });
+        }
+
+        // raw for as long as the sources are read, then back to how it was
+        #[cfg(unix)]
+        let _raw_tty = match self.args.raw_tty_stdin() && self.args.files.iter().any(Source::is_stdin) {
+            true => match RawTty::enable() {
+                Ok(raw_tty) => raw_tty,
+                Err(e) => {
+                    writeln!(self.err_to, "{RAT_NAME}: cannot put the terminal in raw mode: {}", io_error_message(&e)).map_err(RatError::from_write)?;
+                    None
+                },
+            },
+            false => None,
+        };
+
+        if self.args.count_bytes_only {
+            return self.exec_count_bytes();
+        }
+        if let Some(checkpoint) = self.args.checkpoint.clone() {
+            return self.exec_checkpointed(&checkpoint);
+        }
+        if self.args.columns {
+            return self.exec_columns();
+        }
+        if self.args.diff {
+            return self.exec_diff();
+        }
+        if self.args.merge_sorted {
+            return self.exec_merge();
+        }
+        if let Some(turn) = self.args.interleave {
+            return self.exec_interleave(turn);
+        }
+
+        let start = std::time::Instant::now();
+
+        let mut buf = self.alloc_buffer(self.args.input_buffer).map_err(RatError::from_write)?;
+        let out_buf = self.alloc_buffer(self.args.output_buffer).map_err(RatError::from_write)?;
+        let mut stats = self.args.stats.then(ByteStats::new);
+        let mut line_stats = self.args.line_stats.then(LineStats::default);
+
+        let mut total_bytes = 0u64;
+        let mut sources_read = 0usize;
+        #[cfg(feature = "regex")]
+        let mut total_matched = 0u64;
+
+        let detect_binary = self.args.detect_binary && !self.args.force && !self.args.show_nonprinting && self.to_terminal;
+
+        let mut files = std::mem::take(&mut self.args.files);
+        let mut output = Output::new(&self.args, &mut self.write_to, out_buf, self.line_transform.as_mut());
+        if let Some(state) = self.line_state {
+            output.transform.resume(state);
+        }
+
+        trace_event!(debug, options = ?self.args.transform_options(), "transform");
+        'sources: for source in files.iter_mut() {
+            // a skipped FILE leaves no trace, not even a separator
+            if self.args.on_missing == OnMissing::Skip
+                && source.open(&self.args, &mut self.err_to).is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound) {
+                continue;
+            }
+
+            // what the previous source left in the buffer is out before the pause
+            if let Some(delay) = self.args.delay.filter(|_| sources_read > 0) {
+                output.flush()?;
+                std::thread::sleep(delay);
+            }
+
+            sources_read += 1;
+            let name = self.args.source_name(source);
+            if self.args.metadata {
+                let header = describe_source(source).map_or_else(|| name.clone(), |description| format!("{name} {description}"));
+                output.header(&header, sources_read > 1)?;
+            } else if self.args.preview.is_some() {
+                output.header(&name, sources_read > 1)?;
+            }
+            if let Some(separator) = self.args.output_separator.as_deref().filter(|_| sources_read > 1) {
+                output.separate(separator)?;
+            }
+            let mut utf8 = self.args.check_utf8.then(Utf8Check::default);
+            let mut first_read = true;
+            // the whole source, for --tsv-align
+            let mut whole = Vec::new();
+            let mut budget = MemoryBudget::new(self.args.max_memory);
+            let mut last_byte = None;
+            // read to the end without a byte coming out of it
+            let mut empty = false;
+            let mut in_header = self.args.skip_header && !(self.args.keep_first_header && sources_read == 1);
+            #[cfg(feature = "base64")]
+            let mut base64 = self.args.base64_decode.then(base64::Decoder::default);
+            #[cfg(feature = "base64")]
+            let mut decoded = Vec::new();
+            // the sniffing holds the first bytes back, so stdin loses nothing to it
+            #[cfg(feature = "gzip")]
+            let mut gzip = self.args.gzip.then(gzip::Decoder::default);
+            #[cfg(feature = "gzip")]
+            let mut inflated = Vec::new();
+            let mut newlines = self.args.normalize_newlines.map(|_| NewlineNormalizer::default());
+            let mut normalized = Vec::new();
+            let mut stop_at = self.args.stop_at.as_deref().map(|marker| StopAt::new(marker, self.args.include_marker));
+            let mut before_marker = Vec::new();
+            let mut stopped = false;
+            let mut replace = (!self.args.replacements.is_empty()).then(|| Replace::new(&self.args.replacements));
+            let mut replaced = Vec::new();
+            // bytes of the source written so far, for --preview
+            let mut previewed = 0u64;
+            let mut truncated = false;
+
+            loop {
+                // errors remember whether it was the opening that failed
+                #[cfg_attr(not(any(feature = "base64", feature = "gzip")), allow(unused_mut))]
+                let mut read = source.open(&self.args, &mut self.err_to).map_err(|e| (e, true))
+                    .and_then(|()| source.read_retrying(&mut buf, self.args.retry_read.unwrap_or(0)).map_err(|e| (e, false)));
+                let mut data = match read {
+                    Ok(size) => &buf[..size],
+                    Err(_) => &[][..],
+                };
+
+                // the decoded bytes stand in for the raw ones, the end of the source is still a 0 byte read
+                #[cfg(feature = "base64")]
+                if let (Ok(_), Some(decoder)) = (&read, base64.as_mut()) {
+                    decoded.clear();
+                    if let Err(e) = decoder.decode(data, &mut decoded) {
+                        read = Err((std::io::Error::new(std::io::ErrorKind::InvalidData, e), false));
+                    }
+                    data = &decoded;
+                }
+                #[cfg(feature = "gzip")]
+                if let (&Ok(size), Some(decoder)) = (&read, gzip.as_mut()) {
+                    inflated.clear();
+                    let mut inflate = decoder.decode(data, &mut inflated);
+                    // what the base64 decoding flushes at the end still comes before the end of the stream
+                    if size == 0 && !data.is_empty() {
+                        inflate = inflate.and_then(|()| decoder.decode(&[], &mut inflated));
+                    }
+                    if let Err(e) = inflate {
+                        read = Err((std::io::Error::new(std::io::ErrorKind::InvalidData, e), false));
+                    }
+                    data = &inflated;
+                }
+
+                // the first read doubles as the sample, nothing of the source is written yet
+                let binary = std::mem::take(&mut first_read) && read.is_ok()
+                    && (detect_binary || self.args.text_only) && looks_binary(data, self.args.binary_threshold);
+                if binary && self.args.text_only {
+                    output.finish()?;
+                    return Err(RatError::BinaryFile { path: name });
+                }
+                if binary {
+                    writeln!(self.err_to, "{RAT_NAME}: {name}: binary file, use -v to view").map_err(RatError::from_write)?;
+                    break;
+                }
+
+                // everything looking at the raw input goes first
+                if let Ok(size) = read {
+                    total_bytes += size as u64;
+                    if let Some(stats) = stats.as_mut() {
+                        stats.record(data);
+                    }
+                    if let Some(line_stats) = line_stats.as_mut() {
+                        line_stats.record(data);
+                    }
+                    if let Some(utf8) = utf8.as_mut() {
+                        utf8.feed(data);
+                    }
+                    last_byte = data.last().copied().or(last_byte);
+                }
+
+                // like the decoding, the end of the source may still have a CR to give
+                if let (Ok(_), Some(newlines)) = (&read, newlines.as_mut()) {
+                    normalized.clear();
+                    newlines.feed(data, &mut normalized);
+                    data = &normalized;
+                }
+
+                if in_header && read.is_ok() {
+                    match data.iter().position(|&byte| byte == b'\n') {
+                        Some(end) => {
+                            data = &data[end + 1..];
+                            in_header = false;
+                        },
+                        None => data = &[],
+                    }
+                }
+
+                // the marker may come at the end, in what the decoder or normalizer flushed
+                if let (&Ok(size), Some(stop_at)) = (&read, stop_at.as_mut()) {
+                    before_marker.clear();
+                    stopped = stop_at.feed(data, size == 0, &mut before_marker);
+                    data = &before_marker;
+                }
+
+                // the source ends for the replacements where --stop-at ends it too
+                if let (&Ok(size), Some(replace)) = (&read, replace.as_mut()) {
+                    replaced.clear();
+                    replace.feed(data, size == 0 || stopped, &mut replaced);
+                    data = &replaced;
+                }
+
+                // a read past the limit is what tells there was more
+                if let (Ok(_), Some(limit)) = (&read, self.args.preview) {
+                    let left = limit - previewed;
+                    if data.len() as u64 > left {
+                        data = &data[..left as usize];
+                        truncated = true;
+                    }
+                    previewed += data.len() as u64;
+                }
+
+                match read {
+                    Ok(0) if data.is_empty() => {
+                        empty = last_byte.is_none();
+                        break;
+                    },
+                    Ok(_) if self.args.stats_only => {},
+                    Ok(_) if self.args.tsv_align => {
+                        if let Err(e) = budget.take(data.len()) {
+                            output.finish()?;
+                            return Err(e);
+                        }
+                        whole.extend_from_slice(data);
+                    },
+                    Ok(_) => output.write(data)?,
+                    Err((e, _)) if e.kind() == std::io::ErrorKind::TimedOut && source.is_timed_stdin() => {
+                        writeln!(self.err_to, "{RAT_NAME}: read timed out").map_err(RatError::from_write)?;
+                        self.exit_code = READ_TIMEOUT_STATUS;
+                        break 'sources;
+                    },
+                    Err((e, true)) if e.kind() == std::io::ErrorKind::NotFound && self.args.on_missing == OnMissing::Empty => break,
+                    Err((e, opening)) if self.args.abort_on_error || self.args.stops_at_missing(&e, opening) => {
+                        output.finish()?;
+                        return Err(RatError::from_source(name, e, opening));
+                    },
+                    Err((e, _)) => {
+                        writeln!(self.err_to, "{RAT_NAME}: {name}: {}", io_error_message(&e)).map_err(RatError::from_write)?;
+                        self.exit_code = 1;
+                        break;
+                    },
+                }
+
+                // what the end of the source flushed out of the decoder or normalizer is written
+                if let Ok(0) = read {
+                    break;
+                }
+                if stopped || truncated {
+                    break;
+                }
+            }
+
+            if empty {
+                if let Err(e) = self.args.source_empty(&name, &mut self.err_to) {
+                    output.finish()?;
+                    return Err(e);
+                }
+            }
+
+            if !whole.is_empty() {
+                output.write(&align_tsv(&whole, self.args.align_numbers))?;
+            }
+            if truncated {
+                output.mark_truncated()?;
+            }
+            output.end_source()?;
+            if let Some(line_stats) = line_stats.as_mut() {
+                line_stats.end_source();
+            }
+
+            #[cfg(feature = "regex")]
+            if let Some(count_matching) = self.args.count_matching {
+                let matched = output.take_matched();
+                total_matched += matched;
+                if count_matching == CountMatching::PerFile {
+                    output.push(format!("{name}:{matched}\n").as_bytes())?;
+                }
+            }
+
+            // the rest of the source is left unread, so a sequence cut short or whatever it would end in doesn't count
+            if let Some(offset) = utf8.and_then(|utf8| utf8.finish(!stopped)) {
+                writeln!(self.err_to, "{RAT_NAME}: {name}: invalid UTF-8 at byte {offset}").map_err(RatError::from_write)?;
+            }
+            if stopped {
+                break;
+            }
+
+            if self.args.check_final_newline && last_byte.is_some_and(|byte| byte != b'\n') {
+                writeln!(self.err_to, "{RAT_NAME}: {name}: missing final newline").map_err(RatError::from_write)?;
+                self.exit_code = 1;
+            }
+        }
+
+        #[cfg(feature = "regex")]
+        if self.args.count_matching == Some(CountMatching::Total) {
+            output.push(format!("{total_matched}\n").as_bytes())?;
+        }
+
+        output.finish()?;
+        self.line_state = Some(output.transform.state());
+        self.exit_code = output.exit_code(self.exit_code);
+        self.args.files = files;
+
+        if let Some(stats) = stats {
+            stats.report(&mut self.err_to).map_err(RatError::from_write)?;
+        }
+        if let Some(line_stats) = line_stats {
+            line_stats.report(&mut self.err_to).map_err(RatError::from_write)?;
+        }
+
+        if self.args.verbose {
+            let only_stdin = self.args.files.iter().all(Source::is_stdin);
+            let what = match sources_read {
+                1 if only_stdin => self.args.stdin_name().to_string(),
+                1 => "1 file".to_string(),
+                n => format!("{n} files"),
+            };
+            writeln!(self.err_to, "{RAT_NAME}: {what}, {total_bytes} bytes").map_err(RatError::from_write)?;
+        }
+
+        if self.args.time_it {
+            let elapsed = start.elapsed().as_secs_f64();
+            let throughput = total_bytes as f64 / 1e6 / elapsed.max(f64::MIN_POSITIVE);
+            writeln!(self.err_to, "{RAT_NAME}: {total_bytes} bytes in {elapsed:.3}s, {throughput:.2} MB/s").map_err(RatError::from_write)?;
+        }
+
+        Ok(self)
+    }
+
+    // a buffer of the configured size, or a default sized one with a warning when that can't be had
+    fn alloc_buffer(&mut self, size: usize) -> std::io::Result<Box<[u8]>> {
+        let mut buf = Vec::new();
+
+        if buf.try_reserve_exact(size).is_err() {
+            writeln!(self.err_to, "{RAT_NAME}: cannot allocate a {size} byte buffer, using {IO_BUFSIZE} bytes")?;
+            // the other buffer would fail the same way if it's as big, and warn again
+            for setting in [&mut self.args.input_buffer, &mut self.args.output_buffer] {
+                if *setting >= size {
+                    *setting = IO_BUFSIZE;
+                }
+            }
+            return Ok(vec![0u8; IO_BUFSIZE].into_boxed_slice());
+        }
+
+        buf.resize(size, 0);
+        Ok(buf.into_boxed_slice())
+    }
+
+    // --count-bytes-only: adds up the sizes of the sources and writes only the total, a regular FILE's size
+    // comes from its metadata, the rest is read through without anything being done with the bytes
+    fn exec_count_bytes(mut self) -> Result<Self, RatError> {
+        let mut buf = self.alloc_buffer(self.args.input_buffer).map_err(RatError::from_write)?;
+        let mut files = std::mem::take(&mut self.args.files);
+        let mut total = 0u64;
+
+        for source in files.iter_mut() {
+            let name = self.args.source_name(source);
+            match source.open(&self.args, &mut self.err_to) {
+                Ok(()) => {},
+                Err(e) if self.args.abort_on_error || self.args.stops_at_missing(&e, true) =>
+                    return Err(RatError::from_source(name, e, true)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && self.args.on_missing != OnMissing::Report => continue,
+                Err(e) => {
+                    writeln!(self.err_to, "{RAT_NAME}: {name}: {}", io_error_message(&e)).map_err(RatError::from_write)?;
+                    self.exit_code = 1;
+                    continue;
+                },
+            }
+
+            // a regular file that says it's empty may not be, like the ones in /proc
+            let known_size = match source {
+                Source::File(_, Some(file)) => file.metadata().ok().filter(|meta| meta.is_file() && meta.len() > 0).map(|meta| meta.len()),
+                _ => None,
+            };
+            if let Some(size) = known_size {
+                total += size;
+                continue;
+            }
+
+            loop {
+                match source.read_retrying(&mut buf, self.args.retry_read.unwrap_or(0)) {
+                    Ok(0) => break,
+                    Ok(size) => total += size as u64,
+                    Err(e) if self.args.abort_on_error => return Err(RatError::from_source(name, e, false)),
+                    Err(e) => {
+                        writeln!(self.err_to, "{RAT_NAME}: {name}: {}", io_error_message(&e)).map_err(RatError::from_write)?;
+                        self.exit_code = 1;
+                        break;
+                    },
+                }
+            }
+        }
+
+        writeln!(self.write_to, "{total}").map_err(RatError::from_write)?;
+        self.write_to.flush().map_err(RatError::from_write)?;
+        self.args.files = files;
+        Ok(self)
+    }
+
+    // --checkpoint: copies the one FILE straight to the output, recording how far it got every CHECKPOINT_EVERY bytes,
+    // after flushing so the output has at least that much; --resume starts from the last recorded offset
+    fn exec_checkpointed(mut self, checkpoint: &str) -> Result<Self, RatError> {
+        let mut buf = self.alloc_buffer(self.args.input_buffer).map_err(RatError::from_write)?;
+        let mut files = std::mem::take(&mut self.args.files);
+        let source = &mut files[0];
+        let name = self.args.source_name(source);
+
+        let start = self.args.resume_from.unwrap_or(0);
+        let mut offset = start;
+        source.open(&self.args, &mut self.err_to).map_err(|e| RatError::from_source(name.clone(), e, true))?;
+        if offset > 0 {
+            source.seek_to(offset).map_err(|e| RatError::from_source(name.clone(), e, false))?;
+        }
+        // an interrupted run always leaves a checkpoint to resume from, even before the first one is due
+        write_checkpoint(checkpoint, offset)?;
+
+        let mut recorded = offset;
+        loop {
+            let size = source.read_to_buf(&mut buf).map_err(|e| RatError::from_source(name.clone(), e, false))?;
+            if size == 0 {
+                break;
+            }
+            self.write_to.write_all(&buf[..size]).map_err(RatError::from_write)?;
+            offset += size as u64;
+
+            if offset - recorded >= CHECKPOINT_EVERY {
+                self.write_to.flush().map_err(RatError::from_write)?;
+                write_checkpoint(checkpoint, offset)?;
+                recorded = offset;
+            }
+        }
+        self.write_to.flush().map_err(RatError::from_write)?;
+
+        // a finished copy has nothing left to resume
+        std::fs::remove_file(checkpoint).map_err(|source| RatError::Checkpoint { path: checkpoint.to_string(), source })?;
+        self.args.files = files;
+        if let Some(status) = self.args.fail_if_empty.filter(|_| offset == start) {
+            self.exit_code = status;
+        }
+        Ok(self)
+    }
+
+    // --columns: reads a line of every source per row, instead of one source after another
+    fn exec_columns(mut self) -> Result<Self, RatError> {
+        use std::io::BufRead;
+
+        let mut files = std::mem::take(&mut self.args.files);
+        let mut readers = self.open_line_readers(&mut files, "--columns")?;
+
+        let width = self.args.column_width;
+        let last_column = readers.len().saturating_sub(1);
+        let out_buf = self.alloc_buffer(self.args.output_buffer).map_err(RatError::from_write)?;
+        let mut output = Output::new(&self.args, &mut self.write_to, out_buf, self.line_transform.as_mut());
+        if let Some(state) = self.line_state {
+            output.transform.resume(state);
+        }
+        let mut row = Vec::new();
+        let mut line = Vec::new();
+
+        loop {
+            row.clear();
+            let mut content_end = 0;
+            let mut exhausted = true;
+
+            for (i, reader_slot) in readers.iter_mut().enumerate() {
+                line.clear();
+
+                if let Some(reader) = reader_slot {
+                    match reader.read_until(b'\n', &mut line) {
+                        Ok(0) => *reader_slot = None,
+                        Ok(_) => exhausted = false,
+                        Err(e) if self.args.abort_on_error => {
+                            output.finish()?;
+                            return Err(RatError::from_source(self.args.source_name(reader.get_ref()), e, false));
+                        },
+                        Err(e) => {
+                            writeln!(self.err_to, "{RAT_NAME}: {}: {}", self.args.source_name(reader.get_ref()), io_error_message(&e)).map_err(RatError::from_write)?;
+                            self.exit_code = 1;
+                            *reader_slot = None;
+                        }
+                    }
+                }
+
+                if line.last() == Some(&b'\n') {
+                    line.pop();
+                }
+                if line.contains(&b'\t') {
+                    line = expand_tabs(&line, self.args.tab_size);
+                }
+
+                row.extend_from_slice(&line);
+                if !line.is_empty() {
+                    content_end = row.len();
+                }
+
+                // no padding after the last column, nor after the last non empty one
+                if i < last_column {
+                    let chars = String::from_utf8_lossy(&line).chars().count();
+                    row.resize(row.len() + width.saturating_sub(chars) + 1, b' ');
+                }
+            }
+
+            if exhausted {
+                break;
+            }
+
+            row.truncate(content_end);
+            row.push(b'\n');
+            output.write(&row)?;
+        }
+
+        output.finish()?;
+        self.line_state = Some(output.transform.state());
+        self.exit_code = output.exit_code(self.exit_code);
+        drop(readers);
+        self.args.files = files;
+        Ok(self)
+    }
+
+    // opens every source for the modes reading all of them a line at a time, so they stay open until the end;
+    // a source that can't be opened reads as empty, unless --on-missing skips it
+    fn open_line_readers<'f>(&mut self, files: &'f mut [Source], mode: &'static str)
+        -> Result<Vec<Option<std::io::BufReader<&'f mut Source>>>, RatError> {
+        use std::io::BufRead;
+
+        let needed = files.iter().filter(|source| matches!(source, Source::File(..))).count();
+        if let Some(limit) = self.args.max_open_files.or_else(open_files_limit) {
+            if needed > limit {
+                return Err(RatError::TooManyOpenFiles { mode, needed, limit });
+            }
+        }
+
+        let mut readers = Vec::with_capacity(files.len());
+        for source in files.iter_mut() {
+            match source.open(&self.args, &mut self.err_to) {
+                Ok(()) => {
+                    let name = self.args.source_name(source);
+                    let mut reader = std::io::BufReader::with_capacity(self.args.input_buffer, source);
+                    // the lines are read out of what this fills the buffer with, a failure comes up again then
+                    if reader.fill_buf().is_ok_and(|buf| buf.is_empty()) {
+                        self.args.source_empty(&name, &mut self.err_to)?;
+                    }
+                    readers.push(Some(reader));
+                },
+                Err(e) if self.args.abort_on_error || self.args.stops_at_missing(&e, true) =>
+                    return Err(RatError::from_source(self.args.source_name(source), e, true)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && self.args.on_missing == OnMissing::Skip => {},
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && self.args.on_missing == OnMissing::Empty =>
+                    readers.push(None),
+                Err(e) => {
+                    writeln!(self.err_to, "{RAT_NAME}: {}: {}", self.args.source_name(source), io_error_message(&e)).map_err(RatError::from_write)?;
+                    self.exit_code = 1;
+                    readers.push(None);
+                }
+            }
+        }
+        Ok(readers)
+    }
+
+    // --merge-sorted: keeps the next line of every source and writes the smallest of them until all ran out
+    fn exec_merge(mut self) -> Result<Self, RatError> {
+        use std::io::BufRead;
+
+        let mut files = std::mem::take(&mut self.args.files);
+        let mut readers = self.open_line_readers(&mut files, "--merge-sorted")?;
+        let mut next_lines: Vec<Option<Vec<u8>>> = vec![None; readers.len()];
+        // the last line written of every source and how many were read, for --check-sorted
+        let mut previous = vec![Vec::new(); readers.len()];
+        let mut line_numbers = vec![0u64; readers.len()];
+        let mut unsorted = vec![false; readers.len()];
+
+        let out_buf = self.alloc_buffer(self.args.output_buffer).map_err(RatError::from_write)?;
+        let mut output = Output::new(&self.args, &mut self.write_to, out_buf, self.line_transform.as_mut());
+        if let Some(state) = self.line_state {
+            output.transform.resume(state);
+        }
+
+        loop {
+            // only the source the last line came from has to be read again
+            for (i, reader_slot) in readers.iter_mut().enumerate() {
+                let Some(reader) = reader_slot.as_mut().filter(|_| next_lines[i].is_none()) else {
+                    continue;
+                };
+
+                let mut line = Vec::new();
+                match reader.read_until(b'\n', &mut line) {
+                    Ok(0) => *reader_slot = None,
+                    Ok(_) => {
+                        line_numbers[i] += 1;
+                        if self.args.check_sorted && !unsorted[i] && self.args.sort_key(&line) < self.args.sort_key(&previous[i]) {
+                            writeln!(self.err_to, "{RAT_NAME}: {}:{}: out of order", self.args.source_name(reader.get_ref()), line_numbers[i]).map_err(RatError::from_write)?;
+                            self.exit_code = 1;
+                            unsorted[i] = true;
+                        }
+                        // the last line of a source is merged in between the others, so it gets an LF if it has none
+                        if line.last() != Some(&b'\n') {
+                            line.push(b'\n');
+                        }
+                        next_lines[i] = Some(line);
+                    },
+                    Err(e) if self.args.abort_on_error => {
+                        output.finish()?;
+                        return Err(RatError::from_source(self.args.source_name(reader.get_ref()), e, false));
+                    },
+                    Err(e) => {
+                        writeln!(self.err_to, "{RAT_NAME}: {}: {}", self.args.source_name(reader.get_ref()), io_error_message(&e)).map_err(RatError::from_write)?;
+                        self.exit_code = 1;
+                        *reader_slot = None;
+                    }
+                }
+            }
+
+            // min_by keeps the first of equal lines, so those come in the order of the FILEs
+            let smallest = next_lines.iter().enumerate()
+                .filter_map(|(i, line)| line.as_ref().map(|line| (i, line)))
+                .min_by(|(_, a), (_, b)| self.args.sort_key(a).cmp(self.args.sort_key(b)))
+                .map(|(i, _)| i);
+            let Some(i) = smallest else {
+                break;
+            };
+
+            let line = next_lines[i].take().unwrap();
+            output.write(&line)?;
+            previous[i] = line;
+        }
+
+        output.finish()?;
+        self.line_state = Some(output.transform.state());
+        self.exit_code = output.exit_code(self.exit_code);
+        drop(readers);
+        self.args.files = files;
+        Ok(self)
+    }
+
+    // --interleave: writes `turn` lines of every source that has any left, one source after another, until none has
+    fn exec_interleave(mut self, turn: usize) -> Result<Self, RatError> {
+        use std::io::BufRead;
+
+        let mut files = std::mem::take(&mut self.args.files);
+        let mut readers = self.open_line_readers(&mut files, "--interleave")?;
+
+        let out_buf = self.alloc_buffer(self.args.output_buffer).map_err(RatError::from_write)?;
+        let mut output = Output::new(&self.args, &mut self.write_to, out_buf, self.line_transform.as_mut());
+        if let Some(state) = self.line_state {
+            output.transform.resume(state);
+        }
+        let mut line = Vec::new();
+
+        while readers.iter().any(Option::is_some) {
+            for reader_slot in readers.iter_mut() {
+                for _ in 0..turn {
+                    let Some(reader) = reader_slot.as_mut() else {
+                        break;
+                    };
+
+                    line.clear();
+                    match reader.read_until(b'\n', &mut line) {
+                        Ok(0) => *reader_slot = None,
+                        Ok(_) => {
+                            // the last line of a source is followed by the others, so it gets an LF if it has none
+                            if line.last() != Some(&b'\n') {
+                                line.push(b'\n');
+                            }
+                            output.write(&line)?;
+                        },
+                        Err(e) if self.args.abort_on_error => {
+                            output.finish()?;
+                            return Err(RatError::from_source(self.args.source_name(reader.get_ref()), e, false));
+                        },
+                        Err(e) => {
+                            writeln!(self.err_to, "{RAT_NAME}: {}: {}", self.args.source_name(reader.get_ref()), io_error_message(&e)).map_err(RatError::from_write)?;
+                            self.exit_code = 1;
+                            *reader_slot = None;
+                        }
+                    }
+                }
+            }
+        }
+
+        output.finish()?;
+        self.line_state = Some(output.transform.state());
+        self.exit_code = output.exit_code(self.exit_code);
+        drop(readers);
+        self.args.files = files;
+        Ok(self)
+    }
+
+    // --diff: reads both sources as a whole and writes their lines marked like a unified diff
+    fn exec_diff(mut self) -> Result<Self, RatError> {
+        if self.args.files.len() != 2 {
+            return Err("--diff needs exactly two FILEs".into());
+        }
+
+        let mut files = std::mem::take(&mut self.args.files);
+        let mut contents = Vec::with_capacity(files.len());
+        let mut budget = MemoryBudget::new(self.args.max_memory);
+        for source in files.iter_mut() {
+            let mut content = Vec::new();
+            source.open(&self.args, &mut self.err_to).map_err(|e| RatError::from_source(self.args.source_name(source), e, true))?;
+            let read = (&mut *source).take(budget.read_limit()).read_to_end(&mut content);
+            read.map_err(|e| RatError::from_source(self.args.source_name(source), e, false))?;
+            budget.take(content.len())?;
+            if content.is_empty() {
+                self.args.source_empty(&self.args.source_name(source), &mut self.err_to)?;
+            }
+
+            if self.args.normalize_newlines.is_some() {
+                let mut normalized = Vec::with_capacity(content.len());
+                let mut newlines = NewlineNormalizer::default();
+                newlines.feed(&content, &mut normalized);
+                newlines.feed(&[], &mut normalized);
+                content = normalized;
+            }
+            contents.push(content);
+        }
+
+        let out_buf = self.alloc_buffer(self.args.output_buffer).map_err(RatError::from_write)?;
+        let mut output = Output::new(&self.args, &mut self.write_to, out_buf, self.line_transform.as_mut());
+        if let Some(state) = self.line_state {
+            output.transform.resume(state);
+        }
+        let names = [&files[0], &files[1]].map(|source| self.args.source_name(source));
+        output.write(format!("--- {}\n+++ {}\n", names[0], names[1]).as_bytes())?;
+
+        let mut marked = Vec::new();
+        for (mark, line) in diff_lines(&contents[0], &contents[1]) {
+            marked.clear();
+            marked.push(mark);
+            marked.extend_from_slice(line);
+            if !line.ends_with(b"\n") {
+                marked.push(b'\n');
+            }
+            output.write(&marked)?;
+        }
+
+        output.finish()?;
+        self.line_state = Some(output.transform.state());
+        self.exit_code = output.exit_code(self.exit_code);
+        self.args.files = files;
+        Ok(self)
+    }
+}
+
+// the lines of `a` and `b` with a mark each: - for only in a, + for only in b and a space for the
+// ones in a longest common subsequence of both. Myers' algorithm in linear space finds it among the
+// lines both have, which takes time (N + M) * D for D lines that differ and memory for the lines only
+fn diff_lines<'a>(a: &'a [u8], b: &'a [u8]) -> Vec<(u8, &'a [u8])> {
+    let a = a.split_inclusive(|&byte| byte == b'\n').collect::<Vec<_>>();
+    let b = b.split_inclusive(|&byte| byte == b'\n').collect::<Vec<_>>();
+
+    // a line only one of them has can't be common, leaving those out makes FILEs that have
+    // little in common quick to diff
+    let content = |line: &'a [u8]| line.strip_suffix(b"\n").unwrap_or(line);
+    let in_a = a.iter().map(|&line| content(line)).collect::<std::collections::HashSet<_>>();
+    let in_b = b.iter().map(|&line| content(line)).collect::<std::collections::HashSet<_>>();
+    let a_shared = (0..a.len()).filter(|&i| in_b.contains(content(a[i]))).collect::<Vec<_>>();
+    let b_shared = (0..b.len()).filter(|&j| in_a.contains(content(b[j]))).collect::<Vec<_>>();
+
+    let mut common = Vec::new();
+    let shared = |indices: &[usize], lines: &[&'a [u8]]| indices.iter().map(|&i| content(lines[i])).collect::<Vec<_>>();
+    diff_range(&shared(&a_shared, &a), &shared(&b_shared, &b), (0, 0), &mut common);
+
+    // the removed lines of every run of changed ones go before the added ones, like diff has them
+    let mut lines = Vec::with_capacity(a.len().max(b.len()));
+    let (mut i, mut j) = (0, 0);
+    for (x, y) in common.into_iter().map(|(x, y)| (a_shared[x], b_shared[y])).chain([(a.len(), b.len())]) {
+        lines.extend(a[i..x].iter().map(|&line| (b'-', line)));
+        lines.extend(b[j..y].iter().map(|&line| (b'+', line)));
+        if y < b.len() {
+            lines.push((b' ', b[y]));
+        }
+        (i, j) = (x + 1, y + 1);
+    }
+    lines
+}
+
+// the pairs of common lines of a and b, offset by `at`, split at the middle snake until one side is empty
+fn diff_range(mut a: &[&[u8]], mut b: &[&[u8]], mut at: (usize, usize), common: &mut Vec<(usize, usize)>) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    common.extend((0..prefix).map(|i| (at.0 + i, at.1 + i)));
+    (a, b, at) = (&a[prefix..], &b[prefix..], (at.0 + prefix, at.1 + prefix));
+    let suffix = a.iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count();
+    (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+
+    // with the first and last lines differing, a single difference leaves one side empty
+    if !a.is_empty() && !b.is_empty() {
+        let (x, y, u, v) = middle_snake(a, b);
+        diff_range(&a[..x], &b[..y], at, common);
+        common.extend((0..u - x).map(|i| (at.0 + x + i, at.1 + y + i)));
+        diff_range(&a[u..], &b[v..], (at.0 + u, at.1 + v), common);
+    }
+
+    common.extend((0..suffix).map(|i| (at.0 + a.len() + i, at.1 + b.len() + i)));
+}
+
+// Myers' middle snake: the start and end of the common lines that the middle of a shortest edit
+// script goes along, found searching from both ends at once; a and b differ in at least two lines
+fn middle_snake(a: &[&[u8]], b: &[&[u8]]) -> (usize, usize, usize, usize) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    // furthest x reached on every diagonal k = x - y, forward and from the ends
+    let offset = n + m + 1;
+    let mut forward = vec![0isize; 2 * offset as usize + 1];
+    let mut backward = vec![0isize; 2 * offset as usize + 1];
+    let at = |k: isize| (k + offset) as usize;
+
+    for d in 0..=(n + m + 1) / 2 {
+        for k in (-d..=d).step_by(2) {
+            let mut x = match k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                true => forward[at(k + 1)],
+                false => forward[at(k - 1)] + 1,
+            };
+            let (x0, y0) = (x, x - k);
+            while x < n && x - k < m && a[x as usize] == b[(x - k) as usize] {
+                x += 1;
+            }
+            forward[at(k)] = x;
+            if delta % 2 != 0 && (delta - k).abs() < d && x + backward[at(delta - k)] >= n {
+                return (x0 as usize, y0 as usize, x as usize, (x - k) as usize);
+            }
+        }
+
+        for k in (-d..=d).step_by(2) {
+            let mut x = match k == -d || (k != d && backward[at(k - 1)] < backward[at(k + 1)]) {
+                true => backward[at(k + 1)],
+                false => backward[at(k - 1)] + 1,
+            };
+            let (x0, y0) = (x, x - k);
+            while x < n && x - k < m && a[(n - 1 - x) as usize] == b[(m - 1 - (x - k)) as usize] {
+                x += 1;
+            }
+            backward[at(k)] = x;
+            if delta % 2 == 0 && (delta - k).abs() <= d && x + forward[at(delta - k)] >= n {
+                return ((n - x) as usize, (m - (x - k)) as usize, (n - x0) as usize, (m - y0) as usize);
+            }
+        }
+    }
+    unreachable!("the searches from both ends meet by (N + M) / 2 differences")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock(input: &[u8]) -> Source {
+        Source::Mock(Box::new(std::io::Cursor::new(input.to_vec())))
+    }
+
+    fn mock_args(flags: &[&str], inputs: &[&[u8]]) -> RatArgs {
+        let mut raw = vec!["path/to/rat".to_string()];
+        raw.extend(flags.iter().map(|f| f.to_string()));
+
+        // replace whatever the parser picked (stdin when no flags) with the mocks
+        let mut rat_args = RatArgs::new(raw).unwrap();
+        rat_args.files = inputs.iter().map(|input| mock(input)).collect();
+        rat_args
+    }
+
+    fn rat_output(flags: &[&str], input: &[u8]) -> Vec<u8> {
+        Rat::new(mock_args(flags, &[input]), Vec::new()).exec().unwrap().write_to
+    }
+
+    macro_rules! rat_output_test {
+        ($name:ident, [$($flag:expr),*], $input:expr, $expected:expr) => {
+            #[test]
+            #[allow(non_snake_case)]
+            fn $name() {
+                let output = rat_output(&[$($flag),*], $input);
+                assert_eq!(
+                    String::from_utf8_lossy(&output),
+                    String::from_utf8_lossy($expected),
+                    "Failed for flags {}", stringify!($($flag),*)
+                );
+            }
+        };
+    }
+
+    // tab, meta control, plain control, DEL, meta printable, meta tab and newlines
+    const MATRIX_INPUT: &[u8] = b"a\tb\x81c\x01\x7f\n\xe9\x89\n";
+
+    rat_output_test!(rat_output_none, [], MATRIX_INPUT, MATRIX_INPUT);
+    rat_output_test!(rat_output_v, ["-v"], MATRIX_INPUT, b"a\tbM-^Ac^A^?\nM-iM-^I\n");
+    rat_output_test!(rat_output_E, ["-E"], MATRIX_INPUT, b"a\tb\x81c\x01\x7f$\n\xe9\x89$\n");
+    rat_output_test!(rat_output_T, ["-T"], MATRIX_INPUT, b"a^Ib\x81c\x01\x7f\n\xe9\x89\n");
+    rat_output_test!(rat_output_A, ["-A"], MATRIX_INPUT, b"a^IbM-^Ac^A^?$\nM-iM-^I$\n");
+    rat_output_test!(rat_output_e, ["-e"], MATRIX_INPUT, b"a\tbM-^Ac^A^?$\nM-iM-^I$\n");
+    rat_output_test!(rat_output_t, ["-t"], MATRIX_INPUT, b"a^IbM-^Ac^A^?\nM-iM-^I\n");
+    rat_output_test!(rat_output_vET, ["-vET"], MATRIX_INPUT, b"a^IbM-^Ac^A^?$\nM-iM-^I$\n");
+    rat_output_test!(rat_output_show_all, ["--show-all"], MATRIX_INPUT, b"a^IbM-^Ac^A^?$\nM-iM-^I$\n");
+
+    rat_output_test!(rat_output_v_keeps_tab, ["-v"], b"\ta\t\n", b"\ta\t\n");
+    rat_output_test!(rat_output_v_keeps_newline, ["-v"], b"\n\n", b"\n\n");
+    rat_output_test!(rat_output_vT_single_tab_marker, ["-vT"], b"\t\n", b"^I\n");
+    rat_output_test!(rat_output_v_meta_newline, ["-v"], b"\x8a\n", b"M-^J\n");
+
+    rat_output_test!(rat_output_numbers_from, ["-n", "--numbers-from=1000"], b"a\nb\n", b"  1000 a\n  1001 b\n");
+    rat_output_test!(rat_output_number_radix_hex, ["-n", "--number-radix=hex", "--numbers-from=255"], b"a\nb\n", b"0x0000ff a\n0x000100 b\n");
+    rat_output_test!(rat_output_numbers_from_zero, ["-b", "--numbers-from=0"], b"a\n\nb\n", b"     0 a\n\n     1 b\n");
+
+    #[test]
+    fn rat_line_index_across_execs() {
+        let mut rat = Rat::new(mock_args(&["-n"], &[b"a\nb\n"]), Vec::new());
+        assert_eq!(rat.line_index(), 1);
+        rat = rat.exec().unwrap();
+        assert_eq!(rat.line_index(), 3);
+
+        // a line the first exec left unfinished is carried on, not numbered again
+        rat.args.files = vec![mock(b"c\nd")];
+        rat = rat.exec().unwrap();
+        rat.args.files = vec![mock(b"e\nf\n")];
+        rat = rat.exec().unwrap();
+        assert_eq!(String::from_utf8(std::mem::take(&mut rat.write_to)).unwrap(), "     1 a\n     2 b\n     3 c\n     4 de\n     5 f\n");
+
+        rat.set_line_index(10);
+        rat.args.files = vec![mock(b"g\n")];
+        rat = rat.exec().unwrap();
+        assert_eq!(rat.write_to, b"    10 g\n");
+        assert_eq!(rat.line_index(), 11);
+
+        let mut rat = Rat::new(mock_args(&["-n", "--numbers-from=5"], &[b"x\n"]), Vec::new());
+        assert_eq!(rat.line_index(), 5);
+        rat.set_line_index(7);
+        assert_eq!(rat.exec().unwrap().write_to, b"     7 x\n");
+    }
+
+    #[test]
+    fn rat_fold_case() {
+        let input = "Hello, World! MiXeD 123 ÀÉ\nsecond LINE\n".as_bytes();
+        #[cfg(not(feature = "unicode"))]
+        let (lower, upper) = ("hello, world! mixed 123 ÀÉ\nsecond line\n", "HELLO, WORLD! MIXED 123 ÀÉ\nSECOND LINE\n");
+        #[cfg(feature = "unicode")]
+        let (lower, upper) = ("hello, world! mixed 123 àé\nsecond line\n", "HELLO, WORLD! MIXED 123 ÀÉ\nSECOND LINE\n");
+
+        assert_eq!(String::from_utf8(rat_output(&["--fold-case"], input)).unwrap(), lower);
+        assert_eq!(String::from_utf8(rat_output(&["--fold-case=lower"], input)).unwrap(), lower);
+        assert_eq!(String::from_utf8(rat_output(&["--fold-case=upper"], input)).unwrap(), upper);
+
+        // -v shows the folded bytes, its own notation stays as it is
+        assert_eq!(rat_output(&["--fold-case", "-v", "-n"], b"A\x01B\n"), b"     1 a^Ab\n");
+        assert!(RatArgs::new(vec!["rat".into(), "--fold-case=title".into()]).is_err());
+    }
+
+    #[test]
+    fn rat_record_delimiter() {
+        let input: &[u8] = b"one;;two\nlines;;;;x;y;;";
+        for chunk in 1..=input.len() {
+            let mut rat_args = mock_args(&["-n", "-E", "--record-delimiter=;;"], &[]);
+            rat_args.files = vec![chunked(input, chunk)];
+            let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+            assert_eq!(rat.write_to, b"     1 one$;;     2 two\nlines$;;     3 $;;     4 x;y$;;", "in reads of {chunk}");
+        }
+
+        // the start of a delimiter that a FILE ends in is content, -s squeezes empty records
+        let rat = Rat::new(mock_args(&["-b", "-s", "--record-delimiter=\\0\\0"], &[b"a\0", b"\0\0\0\0\0\0b\0\0"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"     1 a\0\0\0\0\0     2 b\0\0");
+        assert_eq!(rat_output(&["-v", "--record-delimiter=|"], b"a\nb|c|"), b"a^Jb|c|");
+        assert_eq!(rat_output(&["-n", "--record-delimiter=abab"], b"xababab!"), b"     1 xabab     2 ab!");
+
+        for flags in [&["--record-delimiter="][..], &["--record-delimiter=;", "--squeeze-whitespace"], &["--record-delimiter=;", "--columns"]] {
+            let mut raw = vec!["rat".to_string()];
+            raw.extend(flags.iter().map(|flag| flag.to_string()));
+            assert!(RatArgs::new(raw).is_err(), "{flags:?}");
+        }
+    }
+
+    #[test]
+    fn rat_max_line_number() {
+        let input: &[u8] = b"a\nb\n\nc\nd\n";
+        assert_eq!(rat_output(&["-n", "--max-line-number=2"], input), b"     1 a\n     2 b\n\nc\nd\n");
+        assert_eq!(rat_output(&["-n", "--max-line-number=2", "--number-overflow=stop"], input), b"     1 a\n     2 b\n\nc\nd\n");
+        assert_eq!(rat_output(&["-n", "--max-line-number=2", "--number-overflow=wrap"], input), b"     1 a\n     2 b\n     1 \n     2 c\n     1 d\n");
+        assert_eq!(rat_output(&["-b", "--numbers-from=9", "--max-line-number=10", "--number-overflow=wrap"], input), b"     9 a\n    10 b\n\n     9 c\n    10 d\n");
+
+        // the lines --grep leaves out still count
+        #[cfg(feature = "regex")]
+        assert_eq!(rat_output(&["-n", "--grep=[ad]", "--max-line-number=3", "--number-overflow=wrap"], input), b"     1 a\n     2 d\n");
+
+        for (flags, message) in [
+            (&["--number-overflow=wrap"][..], "--number-overflow needs --max-line-number"),
+            (&["--numbers-from=5", "--max-line-number=4"], "--max-line-number cannot be less than --numbers-from"),
+        ] {
+            let mut raw = vec!["rat".to_string()];
+            raw.extend(flags.iter().map(|flag| flag.to_string()));
+            assert_eq!(RatArgs::new(raw).unwrap_err().to_string(), message);
+        }
+    }
+
+    #[test]
+    fn rat_tap() {
+        assert_eq!(rat_output(&["--tap"], b"first\n\n\tthird\n"), b"# first\n# \n# \tthird\n");
+        assert_eq!(rat_output(&["--tap"], b""), b"");
+
+        // the last line is ended, the one left open by a FILE goes on in the next like without --tap
+        let rat = Rat::new(mock_args(&["--tap", "-n"], &[b"one\ntw", b"o\nthree"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"#      1 one\n#      2 two\n#      3 three\n");
+
+        let err = RatArgs::new(vec!["rat".into(), "--tap".into(), "--hexdump".into()]).unwrap_err();
+        assert_eq!(err.to_string(), "--hexdump cannot be combined with --tap");
+    }
+
+    #[test]
+    fn rat_byte_offset() {
+        // offsets run on across FILEs and reads, a line continued by the next FILE keeps its own
+        for chunk in [1, 2, 5, 64] {
+            let mut rat_args = mock_args(&["--byte-offset"], &[]);
+            rat_args.files = vec![chunked(b"ab\n\ncde\nf", chunk), chunked(b"g\nhij\n", chunk)];
+            let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+            assert_eq!(String::from_utf8(rat.write_to).unwrap(), "     0 ab\n     3 \n     4 cde\n     8 fg\n    11 hij\n", "reads of {chunk}");
+        }
+
+        let input = "x\n".repeat(150);
+        let output = String::from_utf8(rat_output(&["--byte-offset=hex", "-n"], input.as_bytes())).unwrap();
+        assert!(output.starts_with("     1 0x000000 x\n     2 0x000002 x\n"));
+        assert!(output.ends_with("   150 0x00012a x\n"));
+
+        // the lines --grep leaves out still count
+        #[cfg(feature = "regex")]
+        {
+            assert_eq!(rat_output(&["--byte-offset", "--grep=^m"], b"a\nm1\nbb\nm2\n"), b"     2 m1\n     8 m2\n");
+            assert_eq!(rat_output(&["--byte-offset", "--grep=^m", "--context=1"], b"a\nb\nm\nc\nd\n"), b"     2 b\n     4 m\n     6 c\n");
+        }
+
+        let args = ["rat", "--byte-offset=oct"].map(String::from).to_vec();
+        assert!(RatArgs::new(args).is_err());
+        let args = ["rat", "--byte-offset", "--hexdump"].map(String::from).to_vec();
+        assert!(RatArgs::new(args).is_err());
+    }
+
+    #[test]
+    fn rat_args_numbers_from_invalid() {
+        for value in ["-1", "x", ""] {
+            let raw = vec!["rat".to_string(), format!("--numbers-from={value}")];
+            assert!(RatArgs::new(raw).is_err(), "accepted {value}");
+        }
+    }
+
+    #[cfg(feature = "base64")]
+    rat_output_test!(rat_output_base64_decode, ["--base64-decode", "-n"], b"aGVsbG8K\nd29ybGQK\n", b"     1 hello\n     2 world\n");
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn rat_base64_decode_split_reads() {
+        // 5 byte reads put a quantum boundary in the middle of almost every read
+        let output = rat_output(&["--base64-decode", "--input-buffer=5"], b"aGVsbG8s\nIHdvcmxk\nIQ==\n");
+        assert_eq!(output, b"hello, world!");
+        // the bytes of an unpadded last quantum only come out at the end of the source
+        assert_eq!(rat_output(&["--base64-decode"], b"cmF0cg"), b"ratr");
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn rat_base64_encode() {
+        // the padding only comes at the end of the last source
+        let rat_args = mock_args(&["--base64-encode", "--input-buffer=2"], &[b"hello", b", world", b"!"]);
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"aGVsbG8sIHdvcmxkIQ==\n");
+
+        assert_eq!(rat_output(&["--base64-encode"], b"ra"), b"cmE=\n");
+        assert_eq!(rat_output(&["--base64-encode", "--wrap=8"], b"hello, world"), b"aGVsbG8s\nIHdvcmxk\n");
+        assert_eq!(rat_output(&["--base64-encode", "--wrap=0"], b"hello, world"), b"aGVsbG8sIHdvcmxk");
+        assert!(rat_output(&["--base64-encode"], b"").is_empty());
+
+        let long = rat_output(&["--base64-encode"], &[b'x'; 100]);
+        assert_eq!(long.split(|&b| b == b'\n').map(<[u8]>::len).collect::<Vec<_>>(), [76, 60, 0]);
+
+        for flags in [&["-n"][..], &["-A"], &["--hexdump"], &["--wrap=-1"]] {
+            let mut raw = vec!["rat".to_string(), "--base64-encode".into()];
+            raw.extend(flags.iter().map(|f| f.to_string()));
+            assert!(RatArgs::new(raw).is_err(), "accepted {flags:?}");
+        }
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn rat_base64_decode_invalid() {
+        let rat_args = mock_args(&["--base64-decode"], &[b"cmF0\n*\n", b"cmF0"]);
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!(rat.exit_code(), 1);
+        // the read with the invalid character is dropped whole, the next FILE still goes
+        assert_eq!(rat.write_to, b"rat");
+        assert_eq!(String::from_utf8_lossy(&rat.err_to), "rat: mock: invalid base64 character '*' at byte 5\n");
+
+        let args = ["rat", "--base64-decode", "--columns"].map(String::from).to_vec();
+        assert!(RatArgs::new(args).is_err());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn rat_gzip() {
+        // gzip -9 of "hello\nworld\n"
+        let hello = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xcb\x48\xcd\xc9\xc9\xe7\x2a\xcf\x2f\xca\x49\xe1\x02\x00\xff\x5d\xc5\xc4\x0c\x00\x00\x00";
+
+        // a stream that comes a byte at a time, like a pipe, loses nothing to the sniffing
+        let mut rat_args = mock_args(&["--gzip", "-n"], &[]);
+        rat_args.files = vec![chunked(hello, 1), chunked(b"plain\n", 1)];
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"     1 hello\n     2 world\n     3 plain\n");
+
+        assert_eq!(rat_output(&["--base64-decode", "--gzip"], b"H4sIAAAAAAACA8tIzcnJ5yrPL8pJ4QIA/13FxAwAAAA="), b"hello\nworld\n");
+        // without --gzip the stream is just bytes
+        assert_eq!(rat_output(&[], hello), hello);
+
+        let rat_args = mock_args(&["--gzip"], &[&hello[..20], b"next\n"]);
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!(rat.exit_code(), 1);
+        // like zcat, what was decoded before the stream broke off is shown
+        assert_eq!(rat.write_to, b"hello\nwornext\n");
+        assert_eq!(String::from_utf8_lossy(&rat.err_to), "rat: mock: corrupt gzip stream does not have a matching checksum\n");
+
+        let args = ["rat", "--gzip", "--columns"].map(String::from).to_vec();
+        assert!(RatArgs::new(args).is_err());
+    }
+
+    rat_output_test!(rat_output_null_output, ["-Z"], b"a\nb\n\nc", b"a\0b\0\0c");
+    rat_output_test!(rat_output_null_output_numbered_ends, ["-nEZ"], b"a\nb\n", b"     1 a$\0     2 b$\0");
+    rat_output_test!(rat_output_null_output_reveal, ["--null-output", "--reveal=ascii"], b"a\n", b"a<LF>\0");
+
+    rat_output_test!(rat_output_E_final_newline, ["-E"], b"one\ntwo\n", b"one$\ntwo$\n");
+    rat_output_test!(rat_output_E_no_final_newline, ["-E"], b"one\ntwo", b"one$\ntwo");
+    rat_output_test!(rat_output_show_ends_eof, ["-E", "--show-ends-eof"], b"one\ntwo", b"one$\ntwo$");
+    rat_output_test!(rat_output_show_ends_eof_final_newline, ["-E", "--show-ends-eof"], b"one\ntwo\n", b"one$\ntwo$\n");
+
+    #[test]
+    fn rat_plain() {
+        let rat_args = mock_args(&["--plain", "-vET", "-n"], &[]);
+        assert!(!rat_args.show_ends && !rat_args.show_tabs && !rat_args.show_nonprinting);
+        assert!(!rat_args.number_lines && !rat_args.number_nonblank && !rat_args.squeeze_blank);
+
+        // what would clash or need another option is all gone too, wherever it was in the arguments
+        let flags = ["-bs", "--hexdump", "--delimiter=,", "--columns", "--stats-only", "--plain", "--wrap=3", "--reveal", "--replace=a/b"];
+        let mut rat_args = mock_args(&flags, &[]);
+        assert!(!rat_args.stats_only && rat_args.stats);
+        rat_args.stats = false;
+        assert!(rat_args.copies_verbatim());
+
+        let input = b"a\tb\x01\n\n\n\xffend";
+        let rat = Rat::new(mock_args(&["--plain", "-A", "--uniq", "--normalize-newlines=crlf"], &[input]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, input);
+
+        // and the FILEs come in the order given
+        let rat = Rat::new(mock_args(&["--reverse-files", "--plain"], &[b"a\n", b"b\n"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a\nb\n");
+    }
+
+    #[test]
+    fn rat_show_ends_eof() {
+        // every source is marked at its own end, the line still goes on into the next one
+        let inputs: &[&[u8]] = &[b"a\nb", b"c\n", b"", b"d"];
+        let rat = Rat::new(mock_args(&["-nE", "--show-ends-eof"], inputs), Vec::new()).exec().unwrap();
+        assert_eq!(String::from_utf8(rat.write_to).unwrap(), "     1 a$\n     2 b$c$\n     3 d$");
+        let rat = Rat::new(mock_args(&["-E", "--show-ends-eof", "--uniq"], &[b"a\na\nb", b"c\n"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a$\nb$c$\n");
+
+        assert!(RatArgs::new(vec!["rat".into(), "--show-ends-eof".into()]).is_err());
+    }
+
+    rat_output_test!(rat_output_end_marker, ["-E", "--end-marker=<<"], b"one\n\ntwo\n", b"one<<\n<<\ntwo<<\n");
+    rat_output_test!(rat_output_end_marker_alone, ["--end-marker=<<"], b"one\n", b"one\n");
+
+    #[test]
+    fn rat_args_markers_fit_output_buffer() {
+        let parse = |flags: &[String]| RatArgs::new(["rat".to_string()].into_iter().chain(flags.iter().cloned()).collect());
+        let long = "x".repeat(5000);
+        for flag in ["--end-marker", "--replace-tabs-with", "--ascii-placeholder", "--record-delimiter"] {
+            assert!(parse(&["-E".into(), "--ascii-only".into(), format!("{flag}={long}")]).is_err(), "{flag}");
+        }
+        assert!(parse(&["-E".into(), format!("--end-marker={}", "x".repeat(4000)), "--output-buffer=4K".into()]).is_ok());
+    }
+
+    #[cfg(feature = "encoding")]
+    rat_output_test!(rat_output_from_latin1, ["--from-encoding=latin1"], b"caf\xe9 \x80\n", "café \u{80}\n".as_bytes());
+    #[cfg(feature = "encoding")]
+    rat_output_test!(rat_output_from_cp1252, ["--from-encoding=cp1252"], b"\x93hi\x94 \x80\xe9\n", "\u{201C}hi\u{201D} €é\n".as_bytes());
+
+    #[test]
+    #[cfg(feature = "encoding")]
+    fn rat_args_from_encoding_errors() {
+        let parse = |flags: &[&str]| {
+            let mut raw = vec!["path/to/rat".to_string()];
+            raw.extend(flags.iter().map(|f| f.to_string()));
+            RatArgs::new(raw)
+        };
+
+        assert!(parse(&["--from-encoding=latin1", "-v"]).is_err());
+        assert!(parse(&["-A", "--from-encoding=cp1252"]).is_err());
+        assert!(parse(&["--from-encoding=ebcdic"]).is_err());
+        assert!(parse(&["--from-encoding"]).is_err());
+        assert_eq!(parse(&["--from-encoding=LATIN1"]).unwrap().from_encoding, Some(Encoding::Latin1));
+    }
+
+    #[test]
+    #[cfg(feature = "encoding")]
+    fn rat_output_from_encoding_fills_buffer() {
+        // every byte doubles, so the output buffer has to be flushed mid-chunk
+        let input = vec![0xe9u8; IO_BUFSIZE];
+        let output = rat_output(&["--from-encoding=latin1"], &input);
+        assert_eq!(output, "é".repeat(IO_BUFSIZE).into_bytes());
+    }
+
+    rat_output_test!(rat_output_replace_tabs, ["--replace-tabs-with=\\t->"], b"a\tb\t\n", b"a\t->b\t->\n");
+    rat_output_test!(rat_output_replace_tabs_multibyte, ["--replace-tabs-with=→  "], b"\tx\n", "→  x\n".as_bytes());
+    rat_output_test!(rat_output_replace_tabs_empty, ["--replace-tabs-with="], b"\ta\tb\n", b"ab\n");
+    rat_output_test!(rat_output_replace_tabs_wins_over_T, ["-T", "--replace-tabs-with=<\\\\>"], b"\t\n", b"<\\>\n");
+
+    #[test]
+    fn rat_args_unescape() {
+        assert_eq!(unescape("a\\tb\\nc\\\\d"), b"a\tb\nc\\d");
+        assert_eq!(unescape("\\0\\r"), b"\0\r");
+        assert_eq!(unescape("\\q\\"), b"\\q\\");
+    }
+
+    #[test]
+    fn rat_output_replace_tabs_fills_buffer() {
+        let input = vec![b'\t'; IO_BUFSIZE];
+        let output = rat_output(&["--replace-tabs-with=[tab]"], &input);
+        assert_eq!(output, b"[tab]".repeat(IO_BUFSIZE));
+    }
+
+    rat_output_test!(rat_output_reveal, ["--reveal"], b"a\tb\r\nc\n", "a→b␍⏎\nc⏎\n".as_bytes());
+    rat_output_test!(rat_output_reveal_ascii, ["--reveal=ascii", "-n"], b"a\tb\r\nc", b"     1 a->b<CR><LF>\n     2 c\n\\ No newline at end of file\n");
+    rat_output_test!(rat_output_reveal_over_A, ["-A", "--reveal"], b"\x01\t\n", "^A→⏎\n".as_bytes());
+
+    #[test]
+    fn rat_reveal_marks_each_source() {
+        let rat_args = mock_args(&["--reveal"], &[b"a\r\nb", b"", b"c\n", b"d"]);
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(
+            String::from_utf8(rat.write_to).unwrap(),
+            "a␍⏎\nb\n\\ No newline at end of file\nc⏎\nd\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn rat_args_reveal_style() {
+        assert!(RatArgs::new(vec!["path/to/rat".to_string(), "--reveal=fancy".to_string()]).is_err());
+    }
+
+    const WHITESPACE_INPUT: &[u8] = b"a\n\n  \n\t\nb\n \n\n\n";
+    rat_output_test!(rat_output_s_keeps_whitespace_lines, ["-s"], WHITESPACE_INPUT, b"a\n\n  \n\t\nb\n \n\n");
+    rat_output_test!(rat_output_squeeze_whitespace, ["--squeeze-whitespace"], WHITESPACE_INPUT, b"a\n\nb\n \n");
+    rat_output_test!(rat_output_squeeze_whitespace_numbered, ["--squeeze-whitespace", "-n"], b"\t\n  \nx\n",
+        b"     1 \t\n     2 x\n");
+
+    #[cfg(feature = "regex")]
+    rat_output_test!(rat_output_number_matching, ["--number-matching=^a"],
+        b"apple\nbanana\navocado\ncherry\nartichoke",
+        b"     1 apple\nbanana\n     2 avocado\ncherry\n     3 artichoke");
+    #[cfg(feature = "regex")]
+    rat_output_test!(rat_output_number_matching_overrides_n, ["-n", "--number-matching=[0-9]+$", "-E"],
+        b"a1\nb\n\nc22\n",
+        b"     1 a1$\nb$\n$\n     2 c22$\n");
+
+    rat_output_test!(rat_output_collapse_spaces, ["--collapse-spaces"],
+        b"a  b   c\t\t d \n  e\n",
+        b"a b c\t\t d \n e\n");
+
+    rat_output_test!(rat_output_collapse_spaces_numbered, ["-n", "--collapse-spaces"],
+        b"   x\n",
+        b"     1  x\n");
+
+    #[test]
+    fn rat_sample() {
+        let input = (1..=30).map(|n| format!("line {n}\n")).collect::<String>();
+        let sampled = |flags: &[&str]| String::from_utf8(rat_output(flags, input.as_bytes())).unwrap();
+
+        assert_eq!(sampled(&["--sample=10"]), "line 10\nline 20\nline 30\n");
+        assert_eq!(sampled(&["--sample=10", "--sample-offset=1"]), "line 1\nline 11\nline 21\n");
+        assert_eq!(sampled(&["--sample=10", "--sample-offset=5", "-n"]), "     5 line 5\n    15 line 15\n    25 line 25\n");
+        assert_eq!(sampled(&["--sample=1"]), input);
+
+        // -b doesn't count the blank lines left out either
+        assert_eq!(rat_output(&["--sample=3", "-b"], b"a\n\nb\nc\n\nd\n"), b"     2 b\n     4 d\n");
+        assert_eq!(rat_output(&["--sample=2", "-b"], b"a\n\nb\nc\n"), b"\n     3 c\n");
+
+        for flags in [&["--sample=0"][..], &["--sample=3", "--sample-offset=3"], &["--sample=3", "--hexdump"]] {
+            let mut raw = vec!["rat".to_string()];
+            raw.extend(flags.iter().map(|f| f.to_string()));
+            assert!(RatArgs::new(raw).is_err(), "accepted {flags:?}");
+        }
+    }
+
+    rat_output_test!(rat_output_strip_trailing_whitespace, ["--strip-trailing-whitespace"],
+        b"spaces   \ntabs\t\t\nmixed \t \n \t\n  kept  leading\nlast  ",
+        b"spaces\ntabs\nmixed\n\n  kept  leading\nlast  ");
+    rat_output_test!(rat_output_strip_trailing_whitespace_E, ["-E", "--strip-trailing-whitespace"],
+        b"a \t\nb\n",
+        b"a$\nb$\n");
+
+    #[test]
+    fn rat_strip_trailing_whitespace_across_reads() {
+        let mut rat_args = mock_args(&["--strip-trailing-whitespace"], &[]);
+        rat_args.files = vec![chunked(b"ab  \t  \t\ncd \t e  \n", 3)];
+
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"ab\ncd \t e\n");
+
+        for flag in ["-T", "-A", "-t"] {
+            let raw = vec!["rat".to_string(), "--strip-trailing-whitespace".into(), flag.into()];
+            assert!(RatArgs::new(raw).is_err(), "accepted {flag}");
+        }
+    }
+
+    #[test]
+    fn rat_collapse_spaces_across_reads() {
+        let mut rat_args = mock_args(&["--collapse-spaces"], &[]);
+        rat_args.files = vec![chunked(b"ab      cd  \n", 3)];
+
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"ab cd \n");
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn rat_number_matching_across_reads() {
+        let mut rat_args = mock_args(&["--number-matching=needle"], &[]);
+        rat_args.files = vec![chunked(b"hay\nhayneedlehay\nhay\nneedle\n", 3)];
+
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"hay\n     1 hayneedlehay\nhay\n     2 needle\n");
+    }
+
+    #[cfg(feature = "regex")]
+    rat_output_test!(rat_output_grep, ["--grep=^a", "-n"], b"apple\nbanana\navocado\ncherry", b"     1 apple\n     2 avocado\n");
+    #[cfg(feature = "regex")]
+    rat_output_test!(rat_output_grep_alternation, ["--grep=^(ch|b)[ae]", "-n"], b"apple\nbanana\ncherry\n", b"     1 banana\n     2 cherry\n");
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn rat_grep_context() {
+        // m3 and m5 share their context, m10 is a group of its own, m14 is on the last line
+        let input = b"1\n2\nm3\n4\nm5\n6\n7\n8\n9\nm10\n11\n12\n13\nm14";
+        let expected = "     2 2\n     3 m3\n     4 4\n     5 m5\n     6 6\n--\n     9 9\n    10 m10\n    11 11\n--\n    13 13\n    14 m14";
+        assert_eq!(String::from_utf8(rat_output(&["--grep=^m", "--context=1", "-n"], input)).unwrap(), expected);
+
+        let expected = "m3\n4\nm5\n6\n7\n8\n9\nm10\n11\n12\n13\nm14";
+        assert_eq!(String::from_utf8(rat_output(&["--grep=^m", "--context=2"], &input[4..])).unwrap(), expected);
+        assert_eq!(String::from_utf8(rat_output(&["--grep=^m", "--context=0"], input)).unwrap(), "m3\n--\nm5\n--\nm10\n--\nm14");
+        assert_eq!(rat_output(&["--grep=^m", "--context=5"], b"a\nm\nb\n"), b"a\nm\nb\n");
+        assert!(rat_output(&["--grep=^m", "--context=5"], b"a\nb\n").is_empty());
+
+        // the context goes on into the next FILE, like the numbering
+        let rat = Rat::new(mock_args(&["--grep=^m", "-nC1"], &[b"a\nm\n", b"b\nc\nd\nm\n"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"     1 a\n     2 m\n     3 b\n--\n     5 d\n     6 m\n");
+
+        for (flags, context) in [(&["-C", "2"][..], 2), (&["-C2"], 2), (&["-nC", "0"], 0), (&["--context=7"], 7)] {
+            let mut raw = vec!["rat".to_string(), "--grep=x".into()];
+            raw.extend(flags.iter().map(|f| f.to_string()));
+            assert_eq!(RatArgs::new(raw).unwrap().context, Some(context), "{flags:?}");
+        }
+        for flags in [&["--grep=x", "-C"][..], &["--grep=x", "-C-1"], &["--grep=x", "--context"], &["-C1"]] {
+            let mut raw = vec!["rat".to_string()];
+            raw.extend(flags.iter().map(|f| f.to_string()));
+            assert!(RatArgs::new(raw).is_err(), "accepted {flags:?}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn rat_grep_across_sources() {
+        // a source's unterminated last line isn't joined with the next source's first
+        let rat_args = mock_args(&["--grep=x$"], &[b"ax\nb\nlast x", b"yx\n"]);
+        assert_eq!(Rat::new(rat_args, Vec::new()).exec().unwrap().write_to, b"ax\nlast xyx\n");
+
+        let rat_args = mock_args(&["--grep=tx$"], &[b"ab\nlast", b"x\n"]);
+        assert!(Rat::new(rat_args, Vec::new()).exec().unwrap().write_to.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn rat_count_matching() {
+        let inputs: &[&[u8]] = &[b"error: one\nok\nerror: two\n", b"ok\nerror: three"];
+
+        let rat = Rat::new(mock_args(&["--grep=^error", "--count-matching"], inputs), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"3\n");
+
+        let rat = Rat::new(mock_args(&["--grep=^error", "--count-matching=per-file"], inputs), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"mock:2\nmock:1\n");
+
+        let rat = Rat::new(mock_args(&["--grep=nothing", "--count-matching"], inputs), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"0\n");
+
+        for flags in [&["--count-matching"][..], &["--grep=a", "--count-matching=per-line"]] {
+            let mut raw = vec!["rat".to_string()];
+            raw.extend(flags.iter().map(|f| f.to_string()));
+            assert!(RatArgs::new(raw).is_err(), "accepted {flags:?}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn rat_args_number_matching_invalid() {
+        assert!(RatArgs::new(vec!["path/to/rat".to_string(), "--number-matching=[a-".to_string()]).is_err());
+    }
+
+    #[test]
+    fn rat_stats() {
+        let rat_args = mock_args(&["--stats"], &[b"aab\x01\xff\n"]);
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"aab\x01\xff\n");
+
+        let report = String::from_utf8(rat.err_to).unwrap();
+        let mut lines = report.lines();
+        assert_eq!(lines.next(), Some("rat: 6 bytes, 4 printable, 1 control, 1 high"));
+        assert_eq!(lines.next(), Some("  0x01 ^A            1 ####################"));
+        assert_eq!(lines.next(), Some("  0x0a ^J            1 ####################"));
+        assert_eq!(lines.next(), Some("  0x61 a             2 ########################################"));
+        assert_eq!(lines.next(), Some("  0x62 b             1 ####################"));
+        assert_eq!(lines.next(), Some("  0xff M-^?          1 ####################"));
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn rat_line_stats() {
+        // lengths 3, 0, 10 and 1, the 10 spanning reads and the 1 without a newline
+        let input: &[u8] = b"abc\n\n0123456789\nx";
+        for chunk in [1, 4, 7, input.len()] {
+            let mut rat_args = mock_args(&["--line-stats"], &[]);
+            rat_args.files = vec![chunked(input, chunk), mock(b"\r\n")];
+            let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+            assert_eq!(rat.write_to, b"abc\n\n0123456789\nx\r\n");
+            assert_eq!(rat.err_to, b"rat: 5 lines, length min 0, max 10, mean 3.00\n", "in reads of {chunk}");
+        }
+
+        let rat = Rat::new(mock_args(&["--line-stats"], &[b""]), Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!(rat.err_to, b"rat: 0 lines\n");
+    }
+
+    #[test]
+    fn rat_stats_only() {
+        let rat_args = mock_args(&["--stats-only"], &[b"abc"]);
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert!(rat.write_to.is_empty());
+        assert!(String::from_utf8(rat.err_to).unwrap().starts_with("rat: 3 bytes, 3 printable"));
+    }
+
+    // hands out `data`, then blocks until the test is over
+    struct Stalling(Option<Vec<u8>>);
+
+    impl Read for Stalling {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.take() {
+                Some(data) => {
+                    buf[..data.len()].copy_from_slice(&data);
+                    Ok(data.len())
+                },
+                None => {
+                    std::thread::sleep(Duration::from_secs(60));
+                    Ok(0)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rat_args_read_timeout() {
+        let args = vec!["path/to/rat".to_string(), "--read-timeout=1.5".to_string(), "-".to_string()];
+        let rat_args = RatArgs::new(args).unwrap();
+        assert_eq!(rat_args.read_timeout, Some(Duration::from_millis(1500)));
+        assert!(matches!(rat_args.files[..], [Source::TimedStdin(_)]));
+
+        for bad in ["--read-timeout", "--read-timeout=0", "--read-timeout=-1", "--read-timeout=soon"] {
+            assert!(RatArgs::new(vec!["path/to/rat".to_string(), bad.to_string()]).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn rat_read_timeout_passes_data() {
+        let mut reader = TimedReader::new(std::io::Cursor::new(b"hello".to_vec()), Duration::from_secs(5));
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello");
+    }
+
+    #[test]
+    fn rat_read_timeout_expires() {
+        let mut rat_args = mock_args(&["-n"], &[]);
+        let reader = TimedReader::new(Stalling(Some(b"partial\n".to_vec())), Duration::from_millis(50));
+        rat_args.files = vec![Source::TimedStdin(reader), mock(b"never read\n")];
+
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"     1 partial\n");
+        assert_eq!(rat.err_to, b"rat: read timed out\n");
+        assert_eq!(rat.exit_code(), READ_TIMEOUT_STATUS);
+    }
+
+    // takes at most 3 bytes per write and interrupts every other call, like a slow pipe would
+    #[derive(Debug, Default)]
+    struct Trickle {
+        data: Vec<u8>,
+        calls: usize,
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.calls += 1;
+            if self.calls.is_multiple_of(2) {
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+
+            let len = buf.len().min(3);
+            self.data.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rat_short_writes() {
+        let input = "a\tline\x01\n\n\n".repeat(500);
+        let inputs: &[&[u8]] = &[input.as_bytes(), b"tail"];
+        for flags in [&[][..], &["-nsA"], &["--line-buffered"], &["--hexdump"], &["--output-separator=--", "--reveal"], &["--limit-rate=1G"]] {
+            let expected = Rat::new(mock_args(flags, inputs), Vec::new()).exec().unwrap().write_to;
+            let rat = Rat::new(mock_args(flags, inputs), Trickle::default()).exec().unwrap();
+            assert_eq!(rat.write_to.data, expected, "flags {flags:?}");
+        }
+    }
+
+    // remembers how much had been written every time it got flushed
+    #[derive(Default)]
+    struct FlushRecorder {
+        data: Vec<u8>,
+        flushes: Vec<usize>,
+    }
+
+    impl Write for FlushRecorder {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.flushes.push(self.data.len());
+            Ok(())
+        }
+    }
+
+    rat_output_test!(rat_output_lines_per_page, ["--lines-per-page=2"], b"a\nb\nc\nd\ne", b"a\nb\n\x0cc\nd\n\x0ce");
+    rat_output_test!(rat_output_lines_per_page_squeezed, ["--lines-per-page=2", "-ns"], b"a\n\n\n\nb\nc\n", b"     1 a\n     2 \n\x0c     3 b\n     4 c\n\x0c");
+
+    #[test]
+    fn rat_line_buffered() {
+        let rat_args = mock_args(&["--line-buffered", "-E"], &[b"ab\ncd\n\nef"]);
+        let rat = Rat::new(rat_args, FlushRecorder::default()).exec().unwrap();
+        assert_eq!(rat.write_to.data, b"ab$\ncd$\n$\nef");
+        // one flush per line, plus the final one at the end of exec
+        assert_eq!(rat.write_to.flushes, vec![4, 8, 10, 12]);
+    }
+
+    #[test]
+    fn rat_delay() {
+        let rat_args = mock_args(&["--delay=30"], &[b"ab\n", b"cd", b"ef\n"]);
+        let started = std::time::Instant::now();
+        let rat = Rat::new(rat_args, FlushRecorder::default()).exec().unwrap();
+        // a pause after each of the first two, everything before it flushed, none before the first
+        assert!(started.elapsed() >= Duration::from_millis(60));
+        assert_eq!(rat.write_to.data, b"ab\ncdef\n");
+        assert_eq!(rat.write_to.flushes, vec![3, 5, 8]);
+    }
+
+    #[test]
+    fn rat_block_buffered_by_default() {
+        let rat_args = mock_args(&[], &[b"ab\ncd\n"]);
+        let rat = Rat::new(rat_args, FlushRecorder::default()).exec().unwrap();
+        assert_eq!(rat.write_to.flushes, vec![6]);
+    }
+
+    #[test]
+    fn rat_args_pager() {
+        assert!(RatArgs::new(vec!["path/to/rat".to_string(), "--pager".to_string()]).unwrap().pager());
+        assert!(!RatArgs::new(vec!["path/to/rat".to_string(), "-n".to_string()]).unwrap().pager());
+    }
+
+    // fails the test if anything tries to read it
+    struct Untouchable;
+
+    impl Read for Untouchable {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            panic!("source after the failing one was read");
+        }
+    }
+
+    #[test]
+    fn rat_reports_and_continues() {
+        let mut rat_args = mock_args(&[], &[]);
+        rat_args.files = vec![mock(b"good\n"), Source::File("does/not/exist".into(), None), mock(b"also good\n")];
+
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"good\nalso good\n");
+        assert_eq!(rat.exit_code(), 1);
+        assert_eq!(String::from_utf8(rat.err_to).unwrap(), "rat: does/not/exist: No such file or directory\n");
+    }
+
+    #[test]
+    fn rat_abort_on_error() {
+        let mut rat_args = mock_args(&["--abort-on-error"], &[]);
+        rat_args.files = vec![mock(b"good\n"), Source::File("does/not/exist".into(), None), Source::Mock(Box::new(Untouchable))];
+
+        let mut out = Vec::new();
+        let err = Rat::new(rat_args, &mut out).err_to(Vec::new()).exec().unwrap_err();
+        assert!(matches!(&err, RatError::OpenFailed { path, source } if path == "does/not/exist" && source.kind() == std::io::ErrorKind::NotFound));
+        assert_eq!(err.to_string(), "does/not/exist: No such file or directory");
+        assert_eq!(out, b"good\n");
+    }
+
+    #[test]
+    fn rat_on_missing() {
+        let run = |policy: &str| {
+            let flag = format!("--on-missing={policy}");
+            let mut rat_args = mock_args(&[&flag, "--output-separator=--\n", "--skip-header", "--keep-first-header"], &[]);
+            rat_args.files = vec![mock(b"h\na\n"), Source::File("does/not/exist".into(), None), mock(b"h\nb\n")];
+            Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec()
+        };
+
+        let rat = run("report").unwrap();
+        assert_eq!((rat.exit_code(), rat.write_to.as_slice()), (1, &b"h\na\n--\n--\nb\n"[..]));
+        assert_eq!(rat.err_to, b"rat: does/not/exist: No such file or directory\n");
+
+        let rat = run("skip").unwrap();
+        assert_eq!((rat.exit_code(), rat.write_to.as_slice()), (0, &b"h\na\n--\nb\n"[..]));
+        assert!(rat.err_to.is_empty());
+
+        let rat = run("empty").unwrap();
+        assert_eq!((rat.exit_code(), rat.write_to.as_slice()), (0, &b"h\na\n--\n--\nb\n"[..]));
+        assert!(rat.err_to.is_empty());
+
+        let err = run("error").unwrap_err();
+        assert!(matches!(&err, RatError::OpenFailed { path, .. } if path == "does/not/exist"));
+
+        assert!(RatArgs::new(vec!["rat".into(), "--on-missing=create".into()]).is_err());
+    }
+
+    #[test]
+    fn rat_frame() {
+        let inputs: &[&[u8]] = &[b"one\ntwo\n", b"", b"three"];
+        let frames = |flags: &[&str]| {
+            let mut out = Rat::new(mock_args(flags, inputs), Vec::new()).exec().unwrap().write_to;
+            let mut frames = Vec::new();
+            while !out.is_empty() {
+                let len = u32::from_be_bytes(out[..4].try_into().unwrap()) as usize;
+                frames.push(String::from_utf8(out[4..4 + len].to_vec()).unwrap());
+                out.drain(..4 + len);
+            }
+            frames
+        };
+
+        assert_eq!(frames(&["--frame=length"]), ["one\ntwo\n", "", "three"]);
+        assert_eq!(frames(&["--frame=length", "--frame-unit=line", "-n"]), ["     1 one\n", "     2 two\n", "     3 three"]);
+        // the lines are what the output ends them with
+        assert_eq!(frames(&["--frame=length", "--frame-unit=line", "--normalize-newlines=crlf"]), ["one\r\n", "two\r\n", "three"]);
+
+        let out = Rat::new(mock_args(&["--frame=length"], &[b"abc"]), Vec::new()).exec().unwrap().write_to;
+        assert_eq!(out, b"\0\0\0\x03abc");
+
+        let parse = |flags: &[&str]| RatArgs::new(["rat"].iter().chain(flags).map(|arg| arg.to_string()).collect());
+        assert!(parse(&["--frame=json"]).is_err());
+        assert_eq!(parse(&["--frame-unit=line"]).unwrap_err().to_string(), "--frame-unit needs --frame");
+        assert_eq!(parse(&["--frame=length", "--columns"]).unwrap_err().to_string(), "--frame cannot be combined with --columns");
+    }
+
+    #[test]
+    fn rat_fail_if_empty() {
+        let run = |flags: &[&str], inputs: &[&[u8]]| Rat::new(mock_args(flags, inputs), Vec::new()).exec().unwrap();
+
+        assert_eq!(run(&["--fail-if-empty"], &[b"", b""]).exit_code(), 1);
+        assert_eq!(run(&["--fail-if-empty=3"], &[b"", b""]).exit_code(), 3);
+        let rat = run(&["--fail-if-empty=3"], &[b"", b"x"]);
+        assert_eq!((rat.exit_code(), rat.write_to.as_slice()), (0, &b"x"[..]));
+
+        // -n has no line to number, and what --grep filters away leaves nothing just the same
+        assert_eq!(run(&["--fail-if-empty", "-n"], &[b""]).exit_code(), 1);
+        #[cfg(feature = "regex")]
+        {
+            assert_eq!(run(&["--fail-if-empty", "--grep=x"], &[b"a\nb\n"]).exit_code(), 1);
+            assert_eq!(run(&["--fail-if-empty", "--grep=b"], &[b"a\nb\n"]).exit_code(), 0);
+        }
+        assert_eq!(run(&["--fail-if-empty=2", "--columns"], &[b"", b""]).exit_code(), 2);
+
+        for status in ["0", "256", "x"] {
+            assert!(RatArgs::new(vec!["rat".into(), format!("--fail-if-empty={status}")]).is_err());
+        }
+    }
+
+    #[test]
+    fn rat_on_empty() {
+        let inputs: &[&[u8]] = &[b"a\n", b"", b"b\n"];
+        let run = |flags: &[&str]| Rat::new(mock_args(flags, inputs), Vec::new()).err_to(Vec::new()).exec();
+
+        let rat = run(&["--on-empty=ignore"]).unwrap();
+        assert_eq!((rat.exit_code(), rat.write_to.as_slice()), (0, &b"a\nb\n"[..]));
+        assert!(rat.err_to.is_empty());
+
+        let rat = run(&["--on-empty=warn"]).unwrap();
+        assert_eq!((rat.exit_code(), rat.write_to.as_slice()), (0, &b"a\nb\n"[..]));
+        assert_eq!(rat.err_to, b"rat: mock: empty\n");
+
+        let err = run(&["--on-empty=error"]).unwrap_err();
+        assert!(matches!(&err, RatError::EmptySource { path } if path == "mock"));
+        assert_eq!(err.to_string(), "mock: empty");
+
+        // a source whose header is all it has still had bytes
+        let rat = run(&["--on-empty=warn", "--skip-header"]).unwrap();
+        assert_eq!(rat.err_to, b"rat: mock: empty\n");
+        let rat = Rat::new(mock_args(&["--on-empty=error", "--skip-header"], &[b"h\n", b"h\nb\n"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"b\n");
+
+        // the modes reading lines find out when they open the sources
+        let rat = run(&["--on-empty=warn", "--interleave=1"]).unwrap();
+        assert_eq!((rat.write_to.as_slice(), rat.err_to.as_slice()), (&b"a\nb\n"[..], &b"rat: mock: empty\n"[..]));
+        assert!(matches!(run(&["--on-empty=error", "--columns"]), Err(RatError::EmptySource { .. })));
+
+        assert!(RatArgs::new(vec!["rat".into(), "--on-empty=skip".into()]).is_err());
+    }
+
+    #[test]
+    fn rat_columns_on_missing() {
+        let run = |policy: &str| {
+            let mut rat_args = mock_args(&["--columns", "--column-width=3", &format!("--on-missing={policy}")], &[]);
+            rat_args.files = vec![mock(b"a\n"), Source::File("does/not/exist".into(), None)];
+            Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap()
+        };
+
+        assert_eq!(run("skip").write_to, b"a\n");
+        assert_eq!(run("empty").exit_code(), 0);
+        assert_eq!(run("report").exit_code(), 1);
+
+        let mut rat_args = mock_args(&["--columns", "--on-missing=skip"], &[]);
+        rat_args.files = vec![Source::File("does/not/exist".into(), None)];
+        assert!(Rat::new(rat_args, Vec::new()).exec().unwrap().write_to.is_empty());
+    }
+
+    // fails every read or write with "broken"
+    #[derive(Debug)]
+    struct Broken;
+
+    impl Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("broken"))
+        }
+    }
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("broken"))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rat_error_variants() {
+        let abort = |source: Source| {
+            let mut rat_args = mock_args(&["--abort-on-error"], &[]);
+            rat_args.files = vec![source];
+            Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap_err()
+        };
+
+        let err = abort(Source::Mock(Box::new(Broken)));
+        assert!(matches!(&err, RatError::ReadFailed { path, .. } if path == "mock"));
+        assert_eq!(err.to_string(), "mock: broken");
+
+        let dir = std::env::temp_dir().to_string_lossy().into_owned();
+        let err = abort(Source::File(dir.clone(), None));
+        assert!(matches!(&err, RatError::IsDirectory { path } if *path == dir));
+        assert_eq!(err.to_string(), format!("{dir}: Is a directory"));
+
+        let err = Rat::new(mock_args(&[], &[b"text\n"]), Broken).exec().unwrap_err();
+        assert!(matches!(err, RatError::WriteFailed { .. }));
+        assert_eq!(err.to_string(), "write error: broken");
+
+        let err = RatArgs::new(vec!["rat".into(), "--column-width=wide".into()]).unwrap_err();
+        assert!(matches!(err, RatError::Parse { .. }));
+    }
+
+    #[test]
+    fn rat_split_words() {
+        assert_eq!(split_words("  -n   --tab-size=4 ").unwrap(), ["-n", "--tab-size=4"]);
+        assert_eq!(split_words(r#"--end-marker='<< ' "--output-separator=a b" x\ y"#).unwrap(),
+            ["--end-marker=<< ", "--output-separator=a b", "x y"]);
+        assert_eq!(split_words(r#"'' "a\"b""#).unwrap(), ["", "a\"b"]);
+        assert!(split_words("'open").is_err());
+        assert!(split_words("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rat_args_env_options() {
+        let raw = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let env = Some("-n --tab-size=4".to_string());
+
+        let rat_args = RatArgs::with_env_options(raw(&["rat"]), env.clone()).unwrap();
+        assert!(rat_args.number_lines);
+        assert_eq!(rat_args.tab_size, 4);
+
+        // the command line wins
+        let rat_args = RatArgs::with_env_options(raw(&["rat", "--tab-size=2"]), env.clone()).unwrap();
+        assert!(rat_args.number_lines);
+        assert_eq!(rat_args.tab_size, 2);
+
+        let rat_args = RatArgs::with_env_options(raw(&["rat", "--no-env"]), env).unwrap();
+        assert!(!rat_args.number_lines);
+        assert_eq!(rat_args.tab_size, 8);
+
+        std::env::set_var("RAT_OPTIONS", "-E");
+        let rat_args = RatArgs::from_env_and_args(raw(&["rat"])).unwrap();
+        std::env::remove_var("RAT_OPTIONS");
+        assert!(rat_args.show_ends);
+    }
+
+    #[test]
+    fn rat_hexdump() {
+        let rat_args = mock_args(&["--hexdump"], &[b"0123456789abcdef", b"hello\n\x00\xff"]);
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(String::from_utf8(rat.write_to).unwrap(), concat!(
+            "00000000  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|\n",
+            "00000010  68 65 6c 6c 6f 0a 00 ff                           |hello...|\n",
+            "00000018\n",
+        ));
+
+        assert!(rat_output(&["--hexdump"], b"").is_empty());
+
+        for flag in ["-n", "-E", "-A", "-s", "--reveal"] {
+            let raw = vec!["rat".to_string(), "--hexdump".into(), flag.into()];
+            assert!(RatArgs::new(raw).is_err(), "accepted {flag}");
+        }
+    }
+
+    #[test]
+    fn rat_retry_open() {
+        let path = temp_path("late");
+        let late = path.clone();
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            // renamed into place, so it can't be opened half written
+            let partial = late.with_extension("partial");
+            std::fs::write(&partial, "finally\n").unwrap();
+            std::fs::rename(partial, late).unwrap();
+        });
+
+        let args = vec!["rat".to_string(), "--retry-open=5".into(), path.to_string_lossy().into_owned()];
+        let rat = Rat::new(RatArgs::new(args).unwrap(), Vec::new()).exec().unwrap();
+        writer.join().unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(rat.write_to, b"finally\n");
+        assert_eq!(rat.exit_code(), 0);
+    }
+
+    #[test]
+    fn rat_retry_open_gives_up() {
+        let path = temp_path("never").to_string_lossy().into_owned();
+        let args = vec!["rat".to_string(), "--retry-open=0.1".into(), path.clone()];
+
+        let started = std::time::Instant::now();
+        let rat = Rat::new(RatArgs::new(args).unwrap(), Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(rat.exit_code(), 1);
+        assert_eq!(String::from_utf8(rat.err_to).unwrap(), format!("rat: {path}: No such file or directory\n"));
+    }
+
+    // fails with each of `errors` in turn before reading `data`
+    struct Flaky {
+        errors: Vec<std::io::ErrorKind>,
+        data: std::io::Cursor<Vec<u8>>,
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.errors.pop() {
+                Some(kind) => Err(kind.into()),
+                None => self.data.read(buf),
+            }
+        }
+    }
+
+    #[test]
+    fn rat_retry_read() {
+        let run = |flags: &[&str], errors: &[std::io::ErrorKind]| {
+            let mut rat_args = mock_args(flags, &[]);
+            let flaky = Flaky { errors: errors.to_vec(), data: std::io::Cursor::new(b"data\n".to_vec()) };
+            rat_args.files = vec![Source::Mock(Box::new(flaky)), mock(b"more\n")];
+            Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap()
+        };
+        let transient = [std::io::ErrorKind::WouldBlock, std::io::ErrorKind::TimedOut];
+
+        let rat = run(&["--retry-read=2"], &transient);
+        assert_eq!((rat.exit_code(), rat.write_to.as_slice()), (0, &b"data\nmore\n"[..]));
+        assert!(rat.err_to.is_empty());
+
+        // one retry short, the source is given up on like without any
+        let rat = run(&["--retry-read=1"], &transient);
+        assert_eq!((rat.exit_code(), rat.write_to.as_slice()), (1, &b"more\n"[..]));
+        assert_eq!(rat.err_to, b"rat: mock: operation would block\n");
+
+        // an error that won't go away isn't tried again
+        let rat = run(&["--retry-read=3"], &[std::io::ErrorKind::NotFound]);
+        assert_eq!((rat.exit_code(), rat.write_to.as_slice()), (1, &b"more\n"[..]));
+
+        assert!(RatArgs::new(vec!["rat".into(), "--retry-read=0".into()]).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn rat_permission_denied() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_path("locked");
+        std::fs::write(&path, "secret\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o000)).unwrap();
+        // root reads it anyway, there's nothing to check then
+        if std::fs::File::open(&path).is_ok() {
+            std::fs::remove_file(&path).unwrap();
+            return;
+        }
+
+        let path = path.to_string_lossy().into_owned();
+        let mut rat_args = RatArgs::new(vec!["rat".to_string(), path.clone()]).unwrap();
+        rat_args.files.push(mock(b"after\n"));
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!(rat.exit_code(), 1);
+        assert_eq!(rat.write_to, b"after\n");
+        assert_eq!(String::from_utf8(rat.err_to).unwrap(), format!("rat: {path}: Permission denied\n"));
+
+        let args = vec!["rat".to_string(), "--abort-on-error".into(), path.clone()];
+        let err = Rat::new(RatArgs::new(args).unwrap(), Vec::new()).exec().unwrap_err();
+        assert_eq!(err.to_string(), format!("{path}: Permission denied"));
+        assert_eq!(err.exit_code(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rat_skip_header() {
+        let inputs: &[&[u8]] = &[b"id,name\n1,a\n", b"id,name\n2,b\n", b"id,name\n3,c"];
+
+        let rat = Rat::new(mock_args(&["--skip-header"], inputs), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"1,a\n2,b\n3,c");
+
+        let rat = Rat::new(mock_args(&["--skip-header", "--keep-first-header", "-n"], inputs), Vec::new()).exec().unwrap();
+        assert_eq!(String::from_utf8(rat.write_to).unwrap(), "     1 id,name\n     2 1,a\n     3 2,b\n     4 3,c");
+
+        // a header split across reads, and a source that is nothing but a header
+        let rat_args = mock_args(&["--skip-header", "--input-buffer=3"], &[b"long header\nbody\n", b"only header"]);
+        assert_eq!(Rat::new(rat_args, Vec::new()).exec().unwrap().write_to, b"body\n");
+
+        assert_eq!(rat_output(&["--keep-first-header"], b"header\nbody\n"), b"header\nbody\n");
+    }
+
+    #[test]
+    fn rat_squeeze_across_files() {
+        // the empty lines at the end of the first FILE and the start of the second make one run
+        let inputs: &[&[u8]] = &[b"a\n\n", b"\n\nb\n"];
+        let rat = Rat::new(mock_args(&["-s"], inputs), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a\n\nb\n");
+        let rat = Rat::new(mock_args(&["-s", "--no-squeeze-across-files"], inputs), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a\n\n\nb\n");
+
+        let inputs: &[&[u8]] = &[b"a\n \n", b"\t\n\nb\n"];
+        let rat = Rat::new(mock_args(&["--squeeze-whitespace"], inputs), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a\n \nb\n");
+        let rat = Rat::new(mock_args(&["--squeeze-whitespace", "--no-squeeze-across-files"], inputs), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a\n \n\t\nb\n");
+    }
+
+    #[test]
+    fn rat_fields() {
+        let input = b"a\tb\tc\td\te\n1\t2\n\nx\ty\tz";
+        assert_eq!(rat_output(&["--fields=1,3"], input), b"a\tc\n1\n\nx\tz");
+        assert_eq!(rat_output(&["--fields=2-4"], input), b"b\tc\td\n2\n\ny\tz");
+        assert_eq!(rat_output(&["--fields=3,1", "-n"], input), b"     1 c\ta\n     2 1\n     3 \n     4 z\tx");
+        assert_eq!(rat_output(&["--fields=4-,-1"], input), b"d\te\ta\n1\n\nx");
+        assert_eq!(rat_output(&["--fields=2", "--delimiter=::"], b"a::b::c\na:b\n"), b"b\n\n");
+        assert_eq!(rat_output(&["--fields=3,1", "--delimiter=,", "--input-buffer=2"], b"id,name,qty\n7,x,1\n"), b"qty,id\n1,7\n");
+
+        for flags in [&["--fields=0"][..], &["--fields=3-2"], &["--fields=1,,2"], &["--fields=-"], &["--fields=1", "--delimiter="], &["--delimiter=,"]] {
+            let mut args = vec!["rat".to_string()];
+            args.extend(flags.iter().map(|f| f.to_string()));
+            assert!(RatArgs::new(args).is_err(), "{flags:?}");
+        }
+    }
+
+    #[test]
+    fn rat_metadata() {
+        let path = temp_path("metadata");
+        std::fs::write(&path, "hello\n").unwrap();
+        let file = Source::File(path.display().to_string(), None);
+        let description = describe_source(&file).unwrap();
+        assert!(description.starts_with("(6 bytes, modified 20"), "{description}");
+        #[cfg(unix)]
+        assert!(description.contains(", mode 0"), "{description}");
+
+        let mut rat_args = mock_args(&["--metadata"], &[b"piped\n"]);
+        rat_args.files.insert(0, file);
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        let expected = format!("==> {} {description} <==\nhello\n\n==> mock (stream) <==\npiped\n", path.display());
+        assert_eq!(String::from_utf8(rat.write_to).unwrap(), expected);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(describe_source(&Source::File("does/not/exist".into(), None)).is_none());
+        assert_eq!(utc_timestamp(std::time::UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(utc_timestamp(std::time::UNIX_EPOCH + Duration::from_secs(951_827_696)), "2000-02-29T12:34:56Z");
+    }
+
+    #[test]
+    fn rat_preview() {
+        for chunk in [1, 3, 64] {
+            let mut rat_args = mock_args(&["--preview=6"], &[]);
+            rat_args.files = vec![chunked(b"line 1\nline 2\n", chunk), chunked(b"short", chunk), chunked(b"exact\n", chunk)];
+            let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+            assert_eq!(String::from_utf8(rat.write_to).unwrap(), concat!(
+                "==> mock <==\n",
+                "line 1\n",
+                "...\n",
+                "\n==> mock <==\n",
+                "short\n",
+                "\n==> mock <==\n",
+                "exact\n",
+            ), "reads of {chunk}");
+        }
+
+        // the marker goes on a line of its own, which isn't numbered
+        assert_eq!(rat_output(&["--preview=3", "-n"], b"abcdef\n"), b"==> mock <==\n     1 abc\n...\n");
+
+        let args = ["rat", "--preview=0"].map(String::from).to_vec();
+        assert!(RatArgs::new(args).is_err());
+        let args = ["rat", "--preview=4", "--hexdump"].map(String::from).to_vec();
+        assert!(RatArgs::new(args).is_err());
+    }
+
+    #[test]
+    fn rat_stop_at() {
+        let input: &[u8] = b"ok\nEN\nENDS\nEND\nafter\n";
+        for chunk in 1..=input.len() {
+            for (flags, expected) in [(&["--stop-at=END"][..], "ok\nEN\nENDS\n"), (&["--stop-at=END", "--include-marker"], "ok\nEN\nENDS\nEND\n")] {
+                // the next FILE isn't read anymore either
+                let mut rat_args = mock_args(flags, &[]);
+                rat_args.files = vec![chunked(input, chunk), mock(b"more\n")];
+                let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+                assert_eq!(String::from_utf8(rat.write_to).unwrap(), expected, "{flags:?} in reads of {chunk}");
+            }
+        }
+
+        // a last line without a newline counts, a line only starting like the marker is kept whole
+        assert_eq!(rat_output(&["--stop-at=END", "-n"], b"a\nEND"), b"     1 a\n");
+        assert_eq!(rat_output(&["--stop-at=END", "--include-marker"], b"a\nEND"), b"a\nEND");
+        assert_eq!(rat_output(&["--stop-at=END"], b"a\nEN"), b"a\nEN");
+        assert_eq!(rat_output(&["--stop-at="], b"a\n\nb\n"), b"a\n");
+
+        // the decoder gives nothing for a read without a whole quantum, that isn't the end of the source
+        #[cfg(feature = "base64")]
+        for chunk in 1..=12 {
+            let mut rat_args = mock_args(&["--base64-decode", "--stop-at=ENDX"], &[]);
+            rat_args.files = vec![chunked(b"YQpFTkRYWQo=", chunk)];
+            let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+            assert_eq!(rat.write_to, b"a\nENDXY\n", "in reads of {chunk}");
+        }
+
+        let args = ["rat", "--stop-at=x", "--columns"].map(String::from).to_vec();
+        assert!(RatArgs::new(args).is_err());
+    }
+
+    #[test]
+    fn rat_replace() {
+        let input: &[u8] = b"foo food\nfo\nofoo";
+        for chunk in 1..=input.len() {
+            // the second one replaces in what the first wrote
+            let mut rat_args = mock_args(&["--replace=foo/bar", "--replace=ar/\\n", "--stop-at=END"], &[]);
+            rat_args.files = vec![chunked(input, chunk), chunked(b"xfo", chunk), mock(b"o\nEND\nfoo")];
+            let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+            assert_eq!(rat.write_to, b"b\n b\nd\nfo\nob\nxfoo\n", "in reads of {chunk}");
+        }
+
+        // matches don't overlap, they're taken from the left
+        assert_eq!(rat_output(&["--replace=aa/b"], b"aaaaa"), b"bba");
+        assert_eq!(rat_output(&["--replace=aba/X"], b"ababab aba"), b"Xbab X");
+        assert_eq!(rat_output(&["--replace=a/aa"], b"aa"), b"aaaa");
+        assert_eq!(rat_output(&["--replace=x/"], b"axbx"), b"ab");
+
+        // --stop-at holding back the start of a line gives an empty read, that isn't the end of the source
+        for chunk in 1..=5 {
+            let mut rat_args = mock_args(&["--stop-at=bX", "--replace=a\\nb/Z"], &[]);
+            rat_args.files = vec![chunked(b"a\nbc\n", chunk)];
+            let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+            assert_eq!(rat.write_to, b"Zc\n", "in reads of {chunk}");
+        }
+        assert_eq!(rat_output(&["--replace=\\//\\t", "--replace=\\\\/x/"], b"a/b\\/"), b"a\tbx/\t");
+
+        assert_eq!(parse_replacement("a\\/b/c/d"), Ok((b"a/b".to_vec(), b"c/d".to_vec())));
+        assert!(parse_replacement("/x").is_err());
+        assert!(parse_replacement("ab").is_err());
+    }
+
+    #[test]
+    fn rat_check_final_newline() {
+        let rat_args = mock_args(&["--check-final-newline"], &[b"ok\n", b"", b"no newline", b"\n"]);
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+
+        assert_eq!(rat.exit_code(), 1);
+        assert_eq!(rat.write_to, b"ok\nno newline\n");
+        assert_eq!(rat.err_to, b"rat: mock: missing final newline\n");
+
+        let rat_args = mock_args(&["--check-final-newline"], &[b"fine\n", b""]);
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!(rat.exit_code(), 0);
+        assert!(rat.err_to.is_empty());
+    }
+
+    #[test]
+    fn rat_tsv_align() {
+        let tsv = "name\tqty\tnote\napple\t3\tfresh\nkiwi\t12\nb\n\nsmörgås\t1\tlast".as_bytes();
+        let rat_args = mock_args(&["--tsv-align"], &[tsv, b"x\ty\n"]);
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+
+        assert_eq!(String::from_utf8(rat.write_to).unwrap(), concat!(
+            "name     qty  note\n",
+            "apple    3    fresh\n",
+            "kiwi     12\n",
+            "b\n",
+            "\n",
+            "smörgås  1    last",
+            "x  y\n",
+        ));
+    }
+
+    #[test]
+    fn rat_tsv_align_numbers() {
+        let tsv = b"name\tqty\tprice\tnote\napple\t3\t-1.50\tfresh\nkiwi\t12\t\t7\nfig\t100\t0.25\n";
+        assert_eq!(String::from_utf8(rat_output(&["--tsv-align", "--align=auto"], tsv)).unwrap(), concat!(
+            "name   qty  price  note\n",
+            "apple    3  -1.50  fresh\n",
+            "kiwi    12         7\n",
+            "fig    100   0.25\n",
+        ));
+        assert_eq!(rat_output(&["--tsv-align", "--align=auto"], b"a\t1\nbb\t10\n"), b"a    1\nbb  10\n");
+        assert_eq!(rat_output(&["--tsv-align", "--align=left"], b"a\t1\nbb\t10\n"), b"a   1\nbb  10\n");
+
+        for flags in [&["--align=auto"][..], &["--tsv-align", "--align=right"]] {
+            let mut args = vec!["rat".to_string()];
+            args.extend(flags.iter().map(|f| f.to_string()));
+            assert!(RatArgs::new(args).is_err(), "{flags:?}");
+        }
+    }
+
+    #[test]
+    fn rat_cache_stdin() {
+        let args = ["path/to/rat", "--cache-stdin", "-", "-n", "-"].map(String::from).to_vec();
+        let rat_args = RatArgs::new(args).unwrap();
+
+        let [Source::CachedStdin(first), Source::CachedStdin(second)] = &rat_args.files[..] else {
+            panic!("not cached: {:?}", rat_args.files);
+        };
+        assert!(Rc::ptr_eq(&first.data, &second.data));
+
+        // stands in for reading stdin
+        first.data.set(b"line\n".to_vec()).unwrap();
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"     1 line\n     2 line\n");
+    }
+
+    // several sources reading the same stream, like every `-` does with stdin
+    struct Shared(std::rc::Rc<std::cell::RefCell<std::io::Cursor<Vec<u8>>>>);
+
+    impl Read for Shared {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().read(buf)
+        }
+    }
+
+    #[test]
+    fn rat_args_end_of_options() {
+        let args = ["path/to/rat", "-n", "--", "--", "-E", "file", "-"].map(String::from).to_vec();
+        let rat_args = RatArgs::new(args).unwrap();
+
+        assert!(rat_args.number_lines);
+        assert!(!rat_args.show_ends);
+        assert!(matches!(
+            &rat_args.files[..],
+            [Source::File(a, None), Source::File(b, None), Source::File(c, None), Source::Stdin(_)]
+                if a == "--" && b == "-E" && c == "file"
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "glob")]
+    fn rat_args_include_exclude() {
+        let files = |flags: &[&str]| {
+            let mut raw = vec!["rat".to_string()];
+            raw.extend(flags.iter().map(|f| f.to_string()));
+            raw.extend(["src/lib.rs", "-", "README.md", "src/main.rs", "notes.txt", "src/gen/table.rs"].map(String::from));
+            RatArgs::new(raw).unwrap().files.iter().map(|source| source.to_string()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(files(&["--include=*.rs"]), ["src/lib.rs", "standard input", "src/main.rs", "src/gen/table.rs"]);
+        assert_eq!(files(&["--include=*.rs", "--include=*.md"]), ["src/lib.rs", "standard input", "README.md", "src/main.rs", "src/gen/table.rs"]);
+        assert_eq!(files(&["--exclude=*.rs"]), ["standard input", "README.md", "notes.txt"]);
+        assert_eq!(files(&["--include=*.rs", "--exclude=src/*.rs"]), ["standard input", "src/gen/table.rs"]);
+        assert_eq!(files(&["--include=*.rs", "--exclude=*.rs"]), ["standard input"]);
+        assert!(RatArgs::new(vec!["rat".into(), "--include=[".into()]).is_err());
+    }
+
+    #[test]
+    fn rat_args_dash_keeps_position() {
+        let args = ["path/to/rat", "a", "-", "b", "-n", "-"].map(String::from).to_vec();
+        let rat_args = RatArgs::new(args).unwrap();
+
+        assert!(matches!(
+            &rat_args.files[..],
+            [Source::File(a, None), Source::Stdin(_), Source::File(b, None), Source::Stdin(_)] if a == "a" && b == "b"
+        ));
+    }
+
+    #[test]
+    fn rat_dash_between_files() {
+        let stdin = std::rc::Rc::new(std::cell::RefCell::new(std::io::Cursor::new(b"from stdin\n".to_vec())));
+        let mut rat_args = mock_args(&["-n"], &[]);
+        rat_args.files = vec![
+            mock(b"from a\n"),
+            Source::Mock(Box::new(Shared(stdin.clone()))),
+            mock(b"from b\n"),
+            // stdin is at EOF already, so the second `-` adds nothing
+            Source::Mock(Box::new(Shared(stdin))),
+        ];
+
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"     1 from a\n     2 from stdin\n     3 from b\n");
+    }
+
+    // a path in the temp dir unique to this test run
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        std::env::temp_dir().join(format!("rat-test-{}-{n}-{name}", std::process::id()))
+    }
+
+    #[cfg(unix)]
+    fn mkfifo(name: &str) -> String {
+        let path = temp_path(name);
+        let status = std::process::Command::new("mkfifo").arg(&path).status().unwrap();
+        assert!(status.success());
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn rat_fifo_without_writer() {
+        let fifo = mkfifo("lonely");
+        let mut rat_args = mock_args(&["--fifo-timeout=0.4"], &[]);
+        rat_args.files = vec![Source::File(fifo.clone(), None), mock(b"after\n")];
+
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"after\n");
+        assert_eq!(rat.exit_code(), 1);
+        assert_eq!(
+            String::from_utf8(rat.err_to).unwrap(),
+            format!("rat: {fifo}: waiting for data\nrat: {fifo}: timed out waiting for a writer\n")
+        );
+        std::fs::remove_file(fifo).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn rat_fifo_with_late_writer() {
+        let fifo = mkfifo("late");
+        let writer_path = fifo.clone();
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(400));
+            std::fs::write(writer_path, b"through the pipe\n").unwrap();
+        });
+
+        let mut rat_args = mock_args(&[], &[]);
+        rat_args.files = vec![Source::File(fifo.clone(), None)];
+
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        writer.join().unwrap();
+        assert_eq!(rat.write_to, b"through the pipe\n");
+        assert_eq!(String::from_utf8(rat.err_to).unwrap(), format!("rat: {fifo}: waiting for data\n"));
+        std::fs::remove_file(fifo).unwrap();
+    }
+
+    #[test]
+    fn rat_diff() {
+        let old: &[u8] = b"one\ntwo\nthree\nfour\n";
+        let new: &[u8] = b"one\n2\nthree\nfour\nfive";
+        let rat = Rat::new(mock_args(&["--diff"], &[old, new]), Vec::new()).exec().unwrap();
+        assert_eq!(
+            String::from_utf8(rat.write_to).unwrap(),
+            "--- mock\n+++ mock\n one\n-two\n+2\n three\n four\n+five\n"
+        );
+
+        let rat = Rat::new(mock_args(&["--diff"], &[b"a\nb\n", b"a\nb"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"--- mock\n+++ mock\n a\n b\n");
+
+        let rat = Rat::new(mock_args(&["--diff"], &[b"", b"x\n"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"--- mock\n+++ mock\n+x\n");
+
+        // the line ends of one don't make all its lines differ from the other's
+        let inputs: &[&[u8]] = &[b"a\r\nb\r\n", b"a\nc\n"];
+        let rat = Rat::new(mock_args(&["--diff", "--normalize-newlines=lf"], inputs), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"--- mock\n+++ mock\n a\n-b\n+c\n");
+
+        for inputs in [&[&b"a\n"[..]][..], &[b"a\n", b"b\n", b"c\n"]] {
+            let err = Rat::new(mock_args(&["--diff"], inputs), Vec::new()).exec().unwrap_err();
+            assert_eq!(err.to_string(), "--diff needs exactly two FILEs");
+        }
+    }
+
+    #[test]
+    fn rat_diff_lines_minimal() {
+        // every pair of short inputs over a few distinct lines, against a plain LCS table
+        let mut seed = 1u32;
+        for _ in 0..2000 {
+            let mut text = || {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                let len = (seed >> 16) % 9;
+                (0..len).map(|i| b"abc"[((seed >> (i + 3)) % 3) as usize]).flat_map(|line| [line, b'\n']).collect::<Vec<_>>()
+            };
+            let (a, b) = (text(), text());
+
+            let lines = diff_lines(&a, &b);
+            let kept = |skip: u8| lines.iter().filter(|&&(mark, _)| mark != skip).flat_map(|(_, line)| line.iter().copied()).collect::<Vec<_>>();
+            assert_eq!((kept(b'+'), kept(b'-')), (a.clone(), b.clone()));
+
+            let (a, b) = (a.chunks(2).collect::<Vec<_>>(), b.chunks(2).collect::<Vec<_>>());
+            let mut common = vec![vec![0; b.len() + 1]; a.len() + 1];
+            for i in (0..a.len()).rev() {
+                for j in (0..b.len()).rev() {
+                    common[i][j] = if a[i] == b[j] { common[i + 1][j + 1] + 1 } else { common[i + 1][j].max(common[i][j + 1]) };
+                }
+            }
+            assert_eq!(lines.iter().filter(|&&(mark, _)| mark == b' ').count(), common[0][0], "{a:?} {b:?}");
+        }
+    }
+
+    #[test]
+    fn rat_merge_sorted() {
+        let merge = |flags: &[&str], inputs: &[&[u8]]| {
+            let mut flags = flags.to_vec();
+            flags.insert(0, "--merge-sorted");
+            Rat::new(mock_args(&flags, inputs), Vec::new()).err_to(Vec::new()).exec().unwrap()
+        };
+
+        // sources of any length, the last lines get an LF and equal ones keep the order of the FILEs
+        let inputs: &[&[u8]] = &[b"apple\ncherry 1\nfig\n", b"banana\ncherry 1\ndate\negg\ngrape", b""];
+        let rat = merge(&[], inputs);
+        assert_eq!(rat.write_to, b"apple\nbanana\ncherry 1\ncherry 1\ndate\negg\nfig\ngrape\n");
+        assert_eq!(rat.exit_code(), 0);
+
+        // a shorter line sorts first no matter what follows the longer one
+        assert_eq!(merge(&["-n"], &[b"a\tb\n", b"a\n"]).write_to, b"     1 a\n     2 a\tb\n");
+
+        let logs: &[&[u8]] = &[b"web\t09:00\tstart\nweb\t09:30\tstop\n", b"db\t09:10\tstart\ndb\t09:20\n"];
+        let rat = merge(&["--key=2"], logs);
+        assert_eq!(rat.write_to, b"web\t09:00\tstart\ndb\t09:10\tstart\ndb\t09:20\nweb\t09:30\tstop\n");
+        let rat = merge(&["--key=2", "--delimiter=,"], &[b"b,2\nc,3\n", b"a,1\nd\n"]);
+        assert_eq!(rat.write_to, b"a,1\nd\nb,2\nc,3\n");
+
+        // unsorted input is still merged, --check-sorted only tells about it
+        let unsorted: &[&[u8]] = &[b"b\na\n0\n", b"c\n"];
+        let rat = merge(&[], unsorted);
+        assert_eq!((rat.write_to.as_slice(), rat.err_to.as_slice(), rat.exit_code()), (&b"b\na\n0\nc\n"[..], &b""[..], 0));
+        let rat = merge(&["--check-sorted"], unsorted);
+        assert_eq!(rat.write_to, b"b\na\n0\nc\n");
+        assert_eq!(rat.err_to, b"rat: mock:2: out of order\n");
+        assert_eq!(rat.exit_code(), 1);
+
+        for (flags, message) in [
+            (&["--key=1"][..], "--key needs --merge-sorted"),
+            (&["--check-sorted"], "--check-sorted needs --merge-sorted"),
+            (&["--merge-sorted", "--columns"], "--merge-sorted cannot be combined with --columns, --diff or --tsv-align"),
+        ] {
+            let mut raw = vec!["rat".to_string()];
+            raw.extend(flags.iter().map(|flag| flag.to_string()));
+            assert_eq!(RatArgs::new(raw).unwrap_err().to_string(), message);
+        }
+    }
+
+    #[test]
+    fn rat_interleave() {
+        let inputs: &[&[u8]] = &[b"a1\na2\na3\na4\na5", b"b1\nb2\nb3\n"];
+        let rat = Rat::new(mock_args(&["--interleave=2", "-n"], inputs), Vec::new()).exec().unwrap();
+        assert_eq!(
+            String::from_utf8(rat.write_to).unwrap(),
+            "     1 a1\n     2 a2\n     3 b1\n     4 b2\n     5 a3\n     6 a4\n     7 b3\n     8 a5\n"
+        );
+
+        // one that ran out is left out, in the middle of its turn too
+        let inputs: &[&[u8]] = &[b"a1\n", b"", b"c1\nc2\nc3\n"];
+        let rat = Rat::new(mock_args(&["--interleave=1"], inputs), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a1\nc1\nc2\nc3\n");
+        let rat = Rat::new(mock_args(&["--interleave=3"], &[b"a1\n", b"b1\nb2\nb3\nb4\n"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a1\nb1\nb2\nb3\nb4\n");
+
+        for flags in [&["--interleave=0"][..], &["--interleave=2", "--merge-sorted"], &["--interleave=2", "--stop-at=x"]] {
+            let mut raw = vec!["rat".to_string()];
+            raw.extend(flags.iter().map(|flag| flag.to_string()));
+            assert!(RatArgs::new(raw).is_err(), "{flags:?}");
+        }
+    }
+
+    #[test]
+    fn rat_max_memory() {
+        // the limit is per source for --tsv-align, which lets go of one before reading the next
+        let inputs: &[&[u8]] = &[b"a\tb\n", b"c\td\n"];
+        let rat = Rat::new(mock_args(&["--tsv-align", "--max-memory=4"], inputs), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a  b\nc  d\n");
+
+        let err = Rat::new(mock_args(&["--tsv-align", "--max-memory=3"], inputs), Vec::new()).exec().unwrap_err();
+        assert!(matches!(err, RatError::MemoryLimit { limit: 3 }));
+        assert_eq!(err.to_string(), "input exceeds memory limit");
+
+        // both --diff sources are kept at once
+        let rat = Rat::new(mock_args(&["--diff", "--max-memory=8"], inputs), Vec::new()).exec().unwrap();
+        assert!(rat.write_to.starts_with(b"--- mock\n"));
+        let err = Rat::new(mock_args(&["--diff", "--max-memory=7"], inputs), Vec::new()).exec().unwrap_err();
+        assert!(matches!(err, RatError::MemoryLimit { limit: 7 }));
+
+        assert!(RatArgs::new(vec!["rat".into(), "--max-memory=0".into()]).is_err());
+    }
+
+    #[test]
+    fn rat_add_bom() {
+        let rat = Rat::new(mock_args(&["--add-bom", "-n"], &[b"a\n", b"", b"b\n"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"\xef\xbb\xbf     1 a\n     2 b\n");
+
+        assert_eq!(rat_output(&["--add-bom"], b""), UTF8_BOM);
+        assert_eq!(rat_output(&["--add-bom", "--output-buffer=1"], &[b'x'; 5000])[..4], *b"\xef\xbb\xbfx");
+
+        let rat = Rat::new(mock_args(&["--add-bom", "--columns"], &[b"a\n", b"b\n"]), Vec::new()).exec().unwrap();
+        assert!(rat.write_to.starts_with(UTF8_BOM) && !rat.write_to[3..].starts_with(UTF8_BOM));
+    }
+
+    #[test]
+    fn rat_columns() {
+        let rat_args = mock_args(&["--columns", "--column-width=6"], &[b"one\ntwo\nthree\n", b"1\n22", "αβγ\n".as_bytes()]);
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(
+            String::from_utf8(rat.write_to).unwrap(),
+            "one    1      αβγ\ntwo    22\nthree\n"
+        );
+    }
+
+    #[test]
+    fn rat_columns_expand_tabs() {
+        let rat_args = mock_args(&["--columns", "--column-width=6", "--tab-size=4"], &[b"a\tb\n", b"c\n"]);
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a   b  c\n");
+    }
+
+    rat_output_test!(rat_output_expand_tabs, ["--expand-tabs"], b"ab\tc\n\t\td\n", b"ab      c\n                d\n");
+
+    rat_output_test!(rat_output_expand_tabs_numbered, ["-n", "--expand-tabs", "--tab-size=2"], b"a\tb\n", b"     1 a b\n");
+    rat_output_test!(rat_output_expand_tabs_loses_to_T, ["-T", "--expand-tabs"], b"a\tb\n", b"a^Ib\n");
+
+    #[test]
+    fn rat_transform_order() {
+        let input = b"x\t y\n\n\n\nz\n";
+        let flags = ["-n", "-s", "--collapse-spaces", "--expand-tabs", "--tab-size=4"];
+        assert_eq!(rat_output(&flags, input), b"     1 x    y\n     2 \n     3 z\n");
+        let reordered = [&flags[..], &["--transform-order=tabs,number,squeeze"]].concat();
+        assert_eq!(rat_output(&reordered, input), b" 1 x y\n 2 \n 5 z\n");
+
+        let parse = |flags: &[&str]| RatArgs::new(["rat"].iter().chain(flags).map(|arg| arg.to_string()).collect());
+        assert_eq!(parse(&["--transform-order=tabs,ends"]).unwrap().transform_options().order,
+            [Stage::Tabs, Stage::Ends, Stage::Squeeze, Stage::Nonprinting, Stage::Number]);
+        assert!(parse(&["--transform-order=tabs,wrap"]).is_err());
+        assert!(parse(&["--transform-order=tabs,tabs"]).is_err());
+        let long_marker = format!("--replace-tabs-with={}", "\\t".repeat(1100));
+        assert!(parse(&["-v", "-E", &long_marker, "--transform-order=ends,tabs,nonprinting"]).is_err());
+        assert!(parse(&["-v", "-E", &long_marker]).is_ok());
+    }
+
+    #[test]
+    fn rat_args_tab_size() {
+        let parse = |value: &str| RatArgs::new(vec!["rat".into(), format!("--tab-size={value}")]);
+        assert_eq!(parse("1").unwrap().tab_size, 1);
+        assert_eq!(parse("32").unwrap().tab_size, 32);
+        assert!(parse("0").is_err());
+        assert!(parse("33").is_err());
+        assert!(parse("four").is_err());
+    }
+
+    #[cfg(feature = "clipboard")]
+    #[test]
+    fn rat_args_clipboard() {
+        let args = ["rat", "a", "--clipboard", "b"].map(String::from).to_vec();
+        let rat_args = RatArgs::new(args).unwrap();
+        let names = rat_args.files.iter().map(Source::to_string).collect::<Vec<_>>();
+        assert_eq!(names, ["a", "clipboard", "b"]);
+    }
+
+    #[test]
+    fn rat_args_every_option_parses() {
+        // --fd reads a real descriptor and --files-from reads a real file, both have tests of their own
+        for option in OPTIONS.iter().filter(|option| !matches!(option.long, Some("--fd" | "--files-from"))) {
+            // a value the option accepts, going by how --help names it
+            let value = match option.value.trim_start_matches(['[', '=']).trim_end_matches(']') {
+                "" | "per-file" | "STYLE" => None,
+                "N" | "K" | "COLS" | "SECS" => Some("3"),
+                "STR" | "PATTERN" | "GLOB" => Some("x"),
+                "RADIX" => Some("hex"),
+                "ENC" => Some("latin1"),
+                "SIZE" | "RATE" => Some("8K"),
+                "POLICY" => Some("error"),
+                "EOL" => Some("crlf"),
+                "LABEL" => Some("x"),
+                "LIST" => Some("1,3-"),
+                "FROM/TO" => Some("x/y"),
+                "CASE" => Some("upper"),
+                "ACTION" => Some("wrap"),
+                "MODE" => Some("auto"),
+                "FORMAT" => Some("length"),
+                "UNIT" => Some("line"),
+                "FILE" => Some("x"),
+                "MS" => Some("3"),
+                "STAGES" => Some("tabs,squeeze"),
+                other => panic!("no sample value for {other}"),
+            };
+
+            let mut rat_args = RatArgs::default();
+            assert!(rat_args.apply_option(&option.name(), value).is_ok(), "{} rejected {value:?}", option.name());
+        }
+    }
+
+    #[test]
+    fn rat_usage_lists_every_option() {
+        let usage = usage();
+        for option in OPTIONS {
+            let listed = match (option.short, option.long) {
+                (_, Some(long)) => format!("{long}{}", option.value),
+                (Some(short), None) => format!("-{short} "),
+                (None, None) => unreachable!(),
+            };
+            assert!(usage.contains(&listed), "{listed} is missing from --help");
+        }
+        assert!(usage.lines().all(|line| line.chars().count() <= HELP_WIDTH));
+        assert!(usage.contains("\n  -n, --number             number all output lines\n"));
+        assert!(usage.contains("\n      --strip-trailing-whitespace\n                           drop the spaces"));
+    }
+
+    #[test]
+    fn rat_args_u_is_ignored() {
+        let rat_args = RatArgs::new(["rat", "-un"].map(String::from).to_vec()).unwrap();
+        assert!(rat_args.number_lines);
+    }
+
+    #[test]
+    fn rat_args_files() {
+        // the defaults are the same as without options on the command line
+        let path = temp_path("files");
+        std::fs::write(&path, "a\tb\n").unwrap();
+        let rat = Rat::new(RatArgs::files(vec![path.display().to_string()]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a\tb\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rat_args_unknown_options() {
+        let parse = |args: &[&str]| RatArgs::new(["rat"].iter().chain(args).map(|arg| arg.to_string()).collect());
+        // only what OPTIONS has, and a value only where it takes one
+        for (args, message) in [
+            (&["--bogus", "f1"][..], "unrecognized option '--bogus'"),
+            (&["--bogus=1"], "unrecognized option '--bogus'"),
+            (&["-nq"], "invalid option -- 'q'"),
+            (&["--number=5"], "option '--number' doesn't allow an argument"),
+        ] {
+            let err = parse(args).unwrap_err();
+            assert_eq!((err.to_string(), err.exit_code(), err.is_usage()), (message.to_string(), 2, true));
+        }
+        assert!(parse(&["--", "--bogus", "-q"]).is_ok());
+        assert!(parse(&["--wrap=5", "--reveal"]).is_ok());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn rat_fd() {
+        use std::os::unix::io::{AsRawFd, OwnedFd};
+
+        let (reader, mut writer) = std::io::pipe().unwrap();
+        writer.write_all(b"through a pipe\n").unwrap();
+        drop(writer);
+        let reader = OwnedFd::from(reader);
+        let fd = reader.as_raw_fd();
+
+        let rat_args = RatArgs::new(vec!["rat".into(), "-n".into(), format!("--fd={fd}")]).unwrap();
+        assert_eq!(rat_args.files.iter().map(Source::to_string).collect::<Vec<_>>(), [format!("fd {fd}")]);
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"     1 through a pipe\n");
+        drop(rat);
+
+        // rat read a duplicate, the descriptor is still open for its owner
+        assert!(RatArgs::new(vec!["rat".into(), format!("--fd={fd}"), format!("--fd={fd}")]).is_ok());
+        drop(reader);
+
+        // way past any descriptor limit, so never open
+        let err = RatArgs::new(vec!["rat".into(), "--fd=1000000".into()]).unwrap_err();
+        assert_eq!((err.to_string(), err.exit_code(), err.is_usage()), ("fd 1000000: Bad file descriptor".into(), 1, false));
+        assert!(RatArgs::new(vec!["rat".into(), "--fd=-1".into()]).is_err());
+        for fd in 0..=2 {
+            assert!(RatArgs::new(vec!["rat".into(), format!("--fd={fd}")]).unwrap_err().is_usage());
+        }
+    }
+
+    #[test]
+    fn rat_files_from() {
+        let list = temp_path("list");
+        std::fs::write(&list, "a.txt\r\n\nsome dir/b.txt\n-\n").unwrap();
+        let raw = vec!["rat".into(), "first".into(), format!("--files-from={}", list.display()), "last".into()];
+        let rat_args = RatArgs::new(raw).unwrap();
+        std::fs::remove_file(&list).unwrap();
+
+        // the listed FILEs go where the option is, the - among them is stdin
+        let names = rat_args.files.iter().map(Source::to_string).collect::<Vec<_>>();
+        assert_eq!(names, ["first", "a.txt", "some dir/b.txt", "standard input", "last"]);
+
+        // stdin can't be both the list and one of the FILEs in it
+        let mut rat_args = RatArgs::default();
+        let err = rat_args.add_listed_files(b"a.txt\n-\n", true).unwrap_err();
+        assert_eq!(err, "cannot read file list and stdin simultaneously");
+        assert!(rat_args.add_listed_files(b"a.txt\nb.txt", true).is_ok());
+        assert_eq!(rat_args.files.len(), 3);
+
+        // a list that can't be read fails like a FILE that can't be opened
+        let err = RatArgs::new(vec!["rat".into(), "--files-from=does/not/exist".into()]).unwrap_err();
+        assert!(matches!(&err, RatError::OpenFailed { path, .. } if path == "does/not/exist"));
+        assert_eq!((err.to_string(), err.exit_code()), ("does/not/exist: No such file or directory".into(), 1));
+    }
+
+    // takes `room` bytes, then fails like a full disk
+    #[derive(Debug)]
+    struct FullAfter {
+        data: Vec<u8>,
+        room: usize,
+    }
+
+    impl Write for FullAfter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.room - self.data.len());
+            if len == 0 {
+                return Err(std::io::ErrorKind::StorageFull.into());
+            }
+            self.data.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn rat_trace_events() {
+        #[derive(Clone, Default)]
+        struct Events(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+        impl Write for Events {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let events = Events::default();
+        let writer = events.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_max_level(tracing::Level::TRACE)
+            .with_ansi(false)
+            .without_time()
+            .with_target(false)
+            .finish();
+
+        let path = temp_path("traced");
+        std::fs::write(&path, "abc").unwrap();
+        let name = path.display().to_string();
+        tracing::subscriber::with_default(subscriber, || {
+            Rat::new(RatArgs::new(vec!["rat".into(), name.clone()]).unwrap(), Vec::new()).exec().unwrap();
+        });
+        std::fs::remove_file(&path).unwrap();
+
+        let events = String::from_utf8(events.0.lock().unwrap().clone()).unwrap();
+        let events = events.lines().filter(|event| event.contains(&name)).collect::<Vec<_>>();
+        assert_eq!(events, [
+            format!("DEBUG opened source source={name}"),
+            format!("TRACE read source={name} read=Ok(3)"),
+            format!("TRACE read source={name} read=Ok(0)"),
+        ]);
+    }
+
+    #[test]
+    fn rat_count_bytes_only() {
+        let path = temp_path("count");
+        std::fs::write(&path, "hello\n").unwrap();
+        let mut rat_args = mock_args(&["--count-bytes-only", "-n", "-A"], &[b"piped\n", b""]);
+        rat_args.files.insert(1, Source::File(path.display().to_string(), None));
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!((rat.exit_code(), rat.write_to.as_slice()), (0, &b"12\n"[..]));
+        std::fs::remove_file(&path).unwrap();
+
+        // a FILE that can't be opened counts for nothing and is reported like always
+        let mut rat_args = mock_args(&["--count-bytes-only"], &[b"abc"]);
+        rat_args.files.push(Source::File("does/not/exist".into(), None));
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!((rat.exit_code(), rat.write_to.as_slice()), (1, &b"3\n"[..]));
+        assert_eq!(rat.err_to, b"rat: does/not/exist: No such file or directory\n");
+
+        // --plain writes the FILEs themselves instead of their size
+        let rat = Rat::new(mock_args(&["--count-bytes-only", "--plain"], &[b"hello\n", b"piped\n"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"hello\npiped\n");
+    }
+
+    #[test]
+    fn rat_checkpoint_resume() {
+        let input_path = temp_path("input");
+        let checkpoint = temp_path("checkpoint");
+        let output_path = temp_path("output");
+        let input = (0..CHECKPOINT_EVERY * 3 + 1000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        std::fs::write(&input_path, &input).unwrap();
+        let raw = |resume: bool| {
+            let mut raw = vec!["rat".into(), format!("--checkpoint={}", checkpoint.display()), input_path.display().to_string()];
+            raw.extend(resume.then(|| "--resume".to_string()));
+            raw
+        };
+
+        // the copy dies a bit after the second checkpoint, having written more than it recorded
+        let room = (CHECKPOINT_EVERY * 2) as usize + 5000;
+        let err = Rat::new(RatArgs::new(raw(false)).unwrap(), FullAfter { data: Vec::new(), room }).exec().unwrap_err();
+        assert!(matches!(err, RatError::WriteFailed { .. }));
+        assert_eq!(std::fs::read_to_string(&checkpoint).unwrap(), format!("{}\n", CHECKPOINT_EVERY * 2));
+        std::fs::write(&output_path, &input[..room]).unwrap();
+
+        // the output is cut back to the checkpoint and the rest appended to it
+        let rat_args = RatArgs::new(raw(true)).unwrap();
+        let output = std::fs::OpenOptions::new().append(true).open(&output_path).unwrap();
+        rat_args.resume_output(&output).unwrap();
+        let rat = Rat::new(rat_args, output).exec().unwrap();
+        assert_eq!(rat.exit_code(), 0);
+        assert_eq!(std::fs::read(&output_path).unwrap(), input);
+        assert!(!checkpoint.exists());
+
+        // without the checkpoint there's nothing to resume, and an output shorter than it isn't the one it was for
+        let err = RatArgs::new(raw(true)).unwrap_err();
+        assert!(matches!(err, RatError::Checkpoint { .. }));
+        std::fs::write(&checkpoint, "10\n").unwrap();
+        std::fs::write(&output_path, "short").unwrap();
+        let output = std::fs::File::open(&output_path).unwrap();
+        let err = RatArgs::new(raw(true)).unwrap().resume_output(&output).unwrap_err();
+        assert!(matches!(err, RatError::Resume { .. }));
+        assert_eq!((err.to_string(), err.exit_code()), ("cannot resume the output: it has 5 bytes, not the 10 the checkpoint says were copied".into(), 1));
+
+        // one that can't be cut back fails resuming, not writing
+        std::fs::write(&output_path, "long enough output").unwrap();
+        let output = std::fs::File::open(&output_path).unwrap();
+        let err = RatArgs::new(raw(true)).unwrap().resume_output(&output).unwrap_err();
+        assert!(matches!(err, RatError::Resume { .. }));
+        assert_eq!(err.exit_code(), 1);
+
+        for path in [&input_path, &checkpoint, &output_path] {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        let parse = |flags: &[&str]| RatArgs::new(["rat"].iter().chain(flags).map(|arg| arg.to_string()).collect());
+        assert_eq!(parse(&["--checkpoint=c", "a", "b"]).unwrap_err().to_string(), "--checkpoint needs exactly one FILE");
+        assert!(parse(&["--checkpoint=c", "-n", "a"]).is_err());
+        assert!(parse(&["--checkpoint=c", "--stats", "a"]).is_err());
+        assert_eq!(parse(&["--resume", "a"]).unwrap_err().to_string(), "--resume needs --checkpoint");
+    }
+
+    #[test]
+    fn rat_args_max_sources() {
+        let args = ["rat", "--max-sources=2", "a", "b", "-", "c"].map(String::from).to_vec();
+        let err = RatArgs::new(args).unwrap_err();
+        assert!(matches!(err, RatError::TooManySources { count: 4, limit: 2 }));
+        assert_eq!(err.to_string(), "too many input sources (4 > 2)");
+        assert_eq!(err.exit_code(), 2);
+        assert!(!err.is_usage());
+
+        let args = ["rat", "--max-sources=2", "a", "b"].map(String::from).to_vec();
+        assert!(RatArgs::new(args).is_ok());
+        let args = ["rat", "--max-sources=0"].map(String::from).to_vec();
+        assert!(RatArgs::new(args).is_err());
+    }
+
+    #[test]
+    fn rat_columns_max_open_files() {
+        let paths = ["a", "b", "c"].map(|name| {
+            let path = temp_path(name);
+            std::fs::write(&path, format!("{name}\n")).unwrap();
+            path.to_string_lossy().into_owned()
+        });
+        let columns = |limit: &str| {
+            let mut args = vec!["rat".to_string(), "--columns".into(), "--column-width=1".into(), format!("--max-open-files={limit}")];
+            args.extend(paths.iter().cloned());
+            Rat::new(RatArgs::new(args).unwrap(), Vec::new()).exec()
+        };
+
+        let err = columns("2").unwrap_err();
+        assert!(matches!(err, RatError::TooManyOpenFiles { mode: "--columns", needed: 3, limit: 2 }));
+        assert_eq!(columns("3").unwrap().write_to, b"a b c\n");
+
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn rat_open_files_limit() {
+        assert!(open_files_limit().is_some_and(|limit| limit > 0));
+    }
+
+    #[test]
+    fn rat_columns_long_lines_and_numbers() {
+        let rat_args = mock_args(&["--columns", "--column-width=3", "-n"], &[b"abcdef\n\nx\n", b"1\n2\n"]);
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"     1 abcdef 1\n     2     2\n     3 x\n");
+    }
+
+    #[test]
+    fn rat_columns_missing_source() {
+        let mut rat_args = mock_args(&["--columns", "--column-width=2"], &[]);
+        rat_args.files = vec![mock(b"a\nb\n"), Source::File("does/not/exist".into(), None), mock(b"c\n")];
+
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a     c\nb\n");
+        assert_eq!(rat.exit_code(), 1);
+    }
+
+    #[test]
+    fn rat_args_limit_rate() {
+        assert_eq!(parse_size("100"), Some(100));
+        assert_eq!(parse_size("512K"), Some(512 * 1024));
+        assert_eq!(parse_size("1M"), Some(1024 * 1024));
+        assert_eq!(parse_size("2g"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("0"), None);
+        assert_eq!(parse_size("1T"), None);
+        assert_eq!(parse_size("K"), None);
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("99999999999999G"), None);
+
+        let args = vec!["path/to/rat".to_string(), "--limit-rate=4k".to_string()];
+        assert_eq!(RatArgs::new(args).unwrap().limit_rate, Some(4096));
+    }
+
+    #[test]
+    fn rat_limit_rate_slows_down() {
+        let rat_args = mock_args(&["--limit-rate=100"], &[&[b'x'; 30]]);
+        let start = std::time::Instant::now();
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+
+        assert_eq!(rat.write_to, [b'x'; 30]);
+        assert!(start.elapsed() >= Duration::from_millis(250), "took {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn rat_buffer_size() {
+        // smaller than a line, and raised to the minimum
+        let input = "x".repeat(3 * MIN_BUFSIZE) + "\n";
+        let rat_args = mock_args(&["-n", "--buffer-size=1"], &[input.as_bytes()]);
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, format!("     1 {input}").into_bytes());
+
+        let absurd = format!("--buffer-size={}", u64::MAX);
+        let rat_args = mock_args(&[&absurd], &[b"still works\n"]);
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"still works\n");
+        assert_eq!(String::from_utf8(rat.err_to).unwrap(), format!("rat: cannot allocate a {} byte buffer, using {IO_BUFSIZE} bytes\n", u64::MAX));
+
+        assert!(RatArgs::new(vec!["rat".into(), "--buffer-size=0".into()]).is_err());
+    }
+
+    #[test]
+    fn rat_input_output_buffers() {
+        let parse = |flags: &[&str]| mock_args(flags, &[]);
+        let rat_args = parse(&["--input-buffer=16", "--output-buffer=1M"]);
+        assert_eq!((rat_args.input_buffer, rat_args.output_buffer), (16, 1024 * 1024));
+        let rat_args = parse(&["--buffer-size=8K", "--input-buffer=1"]);
+        assert_eq!((rat_args.input_buffer, rat_args.output_buffer), (1, 8 * 1024));
+        let rat_args = parse(&["--output-buffer=1"]);
+        assert_eq!((rat_args.input_buffer, rat_args.output_buffer), (IO_BUFSIZE, MIN_BUFSIZE));
+        assert!(RatArgs::new(vec!["rat".into(), "--input-buffer=0".into()]).is_err());
+        assert!(RatArgs::new(vec!["rat".into(), "--output-buffer=0".into()]).is_err());
+
+        // the sizes never change what comes out
+        let input = "a\tb\x01\n\n\nc ".repeat(2000);
+        let expected = rat_output(&["-nsA"], input.as_bytes());
+        for (input_buffer, output_buffer) in [("1", "4K"), ("7", "1M"), ("64K", "4K"), ("1M", "1M")] {
+            let flags = ["-nsA", &format!("--input-buffer={input_buffer}"), &format!("--output-buffer={output_buffer}")];
+            assert_eq!(rat_output(&flags, input.as_bytes()), expected, "{input_buffer} in, {output_buffer} out");
+        }
+    }
+
+    #[test]
+    fn rat_output_separator() {
+        let rat_args = mock_args(&["--output-separator=---\\n"], &[b"a\n", b"b\n"]);
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a\n---\nb\n");
+
+        let rat_args = mock_args(&["--output-separator=|"], &[b"a", b"b", b"c"]);
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a|b|c");
+
+        let rat_args = mock_args(&["--output-separator=|"], &[b"only\n"]);
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"only\n");
+    }
+
+    #[test]
+    fn rat_looks_binary() {
+        assert!(!looks_binary(b"plain text\twith tabs\r\n", BINARY_THRESHOLD));
+        assert!(!looks_binary("ünïcödé\n".as_bytes(), BINARY_THRESHOLD));
+        assert!(!looks_binary(b"", BINARY_THRESHOLD));
+        assert!(looks_binary(b"text with a \0 in it", BINARY_THRESHOLD));
+        assert!(looks_binary(b"\x01\x02\x03\x04ab", BINARY_THRESHOLD));
+        // one control character in ten
+        assert!(looks_binary(b"\x01bcdefghij", 5));
+        assert!(!looks_binary(b"\x01bcdefghij", 10));
+        assert!(looks_binary(b"\0 still", 100));
+    }
+
+    #[test]
+    fn rat_detect_binary() {
+        let rat_args = mock_args(&["--detect-binary"], &[b"text\n", b"ELF\0\0\x01", b"more text\n"]);
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).to_terminal(true).exec().unwrap();
+
+        assert_eq!(rat.write_to, b"text\nmore text\n");
+        assert_eq!(rat.err_to, b"rat: mock: binary file, use -v to view\n");
+    }
+
+    #[test]
+    fn rat_quote_names() {
+        let missing = "no such dir/it's\nhere\x1b.txt";
+        let run = |flags: &[&str]| {
+            let mut raw = vec!["rat".to_string()];
+            raw.extend(flags.iter().map(|flag| flag.to_string()));
+            raw.push(missing.into());
+            let rat = Rat::new(RatArgs::new(raw).unwrap(), Vec::new()).err_to(Vec::new()).exec().unwrap();
+            String::from_utf8(rat.err_to).unwrap()
+        };
+        assert_eq!(run(&[]), format!("rat: {missing}: No such file or directory\n"));
+        assert_eq!(run(&["--quote-names"]), "rat: 'no such dir/it'\\''s'$'\\n''here'$'\\033''.txt': No such file or directory\n");
+
+        let err = Rat::new(RatArgs::new(vec!["rat".into(), "--quote-names".into(), "--abort-on-error".into(), "a b\n".into()]).unwrap(), Vec::new())
+            .exec().unwrap_err();
+        assert_eq!(err.to_string(), "'a b'$'\\n': No such file or directory");
+
+        for (name, quoted) in [("plain/name-1.txt", "plain/name-1.txt"), ("", "''"), ("tab\t\t", "'tab'$'\\t\\t'"), ("\u{85}é", "$'\\u0085''é'")] {
+            assert_eq!(shell_quote(name), quoted);
+        }
+    }
+
+    #[test]
+    fn rat_stdin_name() {
+        // stdin that was already read, so the test doesn't wait on the real one
+        let stdin = |input: &[u8]| {
+            let data = Rc::new(OnceCell::from(input.to_vec()));
+            Source::CachedStdin(StdinCache { data, pos: 0, timeout: None })
+        };
+
+        for (flags, name) in [(&["--check-final-newline"][..], "standard input"), (&["--check-final-newline", "--stdin-name=logs"], "logs")] {
+            let mut rat_args = mock_args(flags, &[]);
+            rat_args.files = vec![stdin(b"a\nb")];
+            let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+            assert_eq!(String::from_utf8(rat.err_to).unwrap(), format!("rat: {name}: missing final newline\n"));
+        }
+
+        #[cfg(feature = "regex")]
+        {
+            let mut rat_args = mock_args(&["--grep=a", "--count-matching=per-file", "--stdin-name=logs"], &[]);
+            rat_args.files = vec![stdin(b"a\nb\na\n"), mock(b"a\n")];
+            let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+            assert_eq!(rat.write_to, b"logs:2\nmock:1\n");
+        }
+    }
+
+    #[test]
+    fn rat_text_only() {
+        let inputs: &[&[u8]] = &[b"text\n", b"ELF\0\0\x01", b"more text\n"];
+        let err = Rat::new(mock_args(&["--text-only"], inputs), Vec::new()).exec().unwrap_err();
+        assert!(matches!(err, RatError::BinaryFile { .. }));
+        assert_eq!(err.to_string(), "mock: binary file detected");
+        assert_eq!(err.exit_code(), 1);
+
+        // --force doesn't help, a high enough threshold lets control characters through
+        let rat_args = mock_args(&["--text-only", "--force"], &[b"a\x01\x02\n"]);
+        assert!(Rat::new(rat_args, Vec::new()).exec().is_err());
+        let rat = Rat::new(mock_args(&["--text-only", "--binary-threshold=60"], &[b"a\x01\x02\n", b"b\n"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a\x01\x02\nb\n");
+
+        let args = ["rat", "--binary-threshold=101"].map(String::from).to_vec();
+        assert!(RatArgs::new(args).is_err());
+    }
+
+    #[test]
+    fn rat_detect_binary_overrides() {
+        let binary: &[u8] = b"ELF\0\x01";
+
+        // not a terminal
+        let rat_args = mock_args(&["--detect-binary"], &[binary]);
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, binary);
+
+        let rat_args = mock_args(&["--detect-binary", "--force"], &[binary]);
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).to_terminal(true).exec().unwrap();
+        assert_eq!(rat.write_to, binary);
+
+        let rat_args = mock_args(&["--detect-binary", "-v"], &[binary]);
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).to_terminal(true).exec().unwrap();
+        assert_eq!(rat.write_to, b"ELF^@^A");
+        assert!(rat.err_to.is_empty());
+    }
+
+    #[test]
+    fn rat_verbose_summary() {
+        let rat_args = mock_args(&["--verbose"], &[b"abc\n", b"de"]);
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"abc\nde");
+        assert_eq!(rat.err_to, b"rat: 2 files, 6 bytes\n");
+    }
+
+    #[test]
+    fn rat_verbose_not_for_help() {
+        let rat_args = mock_args(&["--verbose", "--version"], &[b"abc\n"]);
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert!(rat.err_to.is_empty());
+    }
+
+    // hands out its data at most `.1` bytes per read, to split input at awkward places
+    struct Chunked(std::io::Cursor<Vec<u8>>, usize);
+
+    impl Read for Chunked {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    fn chunked(input: &[u8], chunk: usize) -> Source {
+        Source::Mock(Box::new(Chunked(std::io::Cursor::new(input.to_vec()), chunk)))
+    }
+
+    fn utf8_errors(input: &[u8], chunk: usize) -> Option<u64> {
+        let mut check = Utf8Check::default();
+        input.chunks(chunk).for_each(|part| check.feed(part));
+        check.finish(true)
+    }
+
+    #[test]
+    fn rat_utf8_check_validator() {
+        let valid = "aé€😀z".as_bytes();
+        for chunk in 1..=valid.len() {
+            assert_eq!(utf8_errors(valid, chunk), None, "chunk {chunk}");
+        }
+
+        assert_eq!(utf8_errors(b"ab\xff", 1), Some(2));
+        assert_eq!(utf8_errors(b"a\xe2\x82x", 1), Some(1));
+        // overlong encoding and UTF-16 surrogate
+        assert_eq!(utf8_errors(b"\xc0\x80", 2), Some(0));
+        assert_eq!(utf8_errors(b"ok\xed\xa0\x80", 3), Some(2));
+        // cut short by EOF
+        assert_eq!(utf8_errors(b"abc\xf0\x9f\x98", 4), Some(3));
+    }
+
+    #[test]
+    fn rat_normalize_newlines() {
+        // every input line end, then the LF of a CRLF and two CRs in a row right where reads end
+        let inputs: [&[u8]; 4] = [b"a\nb\n\nc", b"a\r\nb\r\n\r\nc", b"a\rb\r\rc", b"a\r\nb\n\rc"];
+        for (eol, newline) in [("lf", "\n"), ("crlf", "\r\n"), ("cr", "\r")] {
+            let flag = format!("--normalize-newlines={eol}");
+            let expected = ["a", "b", "", "c"].join(newline);
+
+            for input in inputs {
+                for chunk in 1..=input.len() {
+                    let mut rat_args = mock_args(&[&flag], &[]);
+                    rat_args.files = vec![chunked(input, chunk)];
+                    let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+                    assert_eq!(rat.write_to, expected.as_bytes(), "{eol} of {:?} in reads of {chunk}", input.escape_ascii());
+                }
+            }
+        }
+
+        // a CR at the very end is a line end, one at the end of a read only maybe
+        let mut rat_args = mock_args(&["--normalize-newlines=crlf"], &[]);
+        rat_args.files = vec![chunked(b"a\r", 1), chunked(b"\nb\r", 2)];
+        let rat = Rat::new(rat_args, Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a\r\n\r\nb\r\n");
+
+        assert_eq!(rat_output(&["--normalize-newlines=crlf", "-nE"], b"a\rb\n"), b"     1 a$\r\n     2 b$\r\n");
+        assert_eq!(rat_output(&["--normalize-newlines=crlf", "--reveal=ascii"], b"a\r\n"), b"a<LF>\r\n");
+        assert_eq!(rat_output(&["--normalize-newlines=lf", "-s"], b"a\r\r\r\nb"), b"a\n\nb");
+
+        for flags in [&["--normalize-newlines=mac"][..], &["--normalize-newlines"], &["--normalize-newlines=lf", "-Z"],
+                      &["--normalize-newlines=lf", "--columns"], &["--normalize-newlines=lf", "--hexdump"]] {
+            let mut raw = vec!["rat".to_string()];
+            raw.extend(flags.iter().map(|f| f.to_string()));
+            assert!(RatArgs::new(raw).is_err(), "accepted {flags:?}");
+        }
+    }
+
+    #[test]
+    fn rat_with_line_transform() {
+        fn upper(line: &[u8]) -> Cow<'_, [u8]> {
+            match line.is_ascii() {
+                true => Cow::Owned(line.to_ascii_uppercase()),
+                false => Cow::Borrowed(line),
+            }
+        }
+
+        // the line split between sources is still passed whole, after the numbering
+        let rat_args = mock_args(&["-nE"], &[b"one\ntw", b"o\nd\xc3\xa9j\xc3\xa0\nend"]);
+        let rat = Rat::new(rat_args, Vec::new()).with_line_transform(upper).exec().unwrap();
+        assert_eq!(rat.write_to, "     1 ONE$\n     2 TWO$\n     3 déjà$\n     4 END".as_bytes());
+
+        // what --lines-per-page and --output-separator add isn't passed, but ends the line before
+        let seen = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let record = Rc::clone(&seen);
+        let rat_args = mock_args(&["--output-buffer=1", "--lines-per-page=1", "--output-separator=--\n"], &[b"a\nb", b"c\n"]);
+        let rat = Rat::new(rat_args, Vec::new())
+            .with_line_transform(move |line| {
+                record.borrow_mut().push(line.to_vec());
+                Cow::Owned([b">", line].concat())
+            })
+            .exec()
+            .unwrap();
+        assert_eq!(rat.write_to, b">a\n\x0c>b--\n>c\n\x0c");
+        assert_eq!(*seen.borrow(), [&b"a\n"[..], b"b", b"c\n"]);
+    }
+
+    #[cfg(feature = "crc32")]
+    #[test]
+    fn rat_checksum_per_line() {
+        assert_eq!(rat_output(&["--checksum-per-line"], b"hello\n\n123456789"), b"hello\t3610a686\n\t00000000\n123456789\tcbf43926");
+        assert_eq!(rat_output(&["--checksum-per-line", "-nE"], b"hello\n\n"), b"     1 hello\t3610a686$\n     2 \t00000000$\n");
+
+        // a line split between FILEs is one line, its checksum covers all of it
+        let rat = Rat::new(mock_args(&["--checksum-per-line"], &[b"hel", b"lo\n"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"hello\t3610a686\n");
+
+        assert!(RatArgs::new(["rat", "--checksum-per-line", "--hexdump"].map(String::from).to_vec()).is_err());
+    }
+
+    #[test]
+    fn rat_reverse_files() {
+        let inputs: &[&[u8]] = &[b"a1\na2\n", b"b1\n", b"c1\nc2\n"];
+        let rat = Rat::new(mock_args(&["--reverse-files", "-n"], inputs), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"     1 c1\n     2 c2\n     3 b1\n     4 a1\n     5 a2\n");
+        assert!(rat.args.reverse_files);
+
+        let rat = Rat::new(mock_args(&["--reverse-files", "--diff"], &[b"old\n", b"new\n"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"--- mock\n+++ mock\n-new\n+old\n");
+    }
+
+    #[test]
+    fn rat_uniq() {
+        let input = b"a\na\nb\na\nb\nb\n\n\nc\nc";
+        assert_eq!(rat_output(&["--uniq"], input), b"a\nb\na\nb\n\nc\n");
+        assert_eq!(rat_output(&["--uniq-global"], input), b"a\nb\n\nc\n");
+        // numbers go to the lines that are written
+        assert_eq!(rat_output(&["--uniq", "-n"], b"x\nx\ny\n"), b"     1 x\n     2 y\n");
+
+        // duplicates are looked for across FILEs too
+        let rat = Rat::new(mock_args(&["--uniq"], &[b"a\n", b"a\nb\n"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a\nb\n");
+
+        // only the distinct lines count against --max-memory
+        assert_eq!(rat_output(&["--uniq-global", "--max-memory=3"], b"ab\nab\nc\nab\n"), b"ab\nc\n");
+        let rat_args = mock_args(&["--uniq-global", "--max-memory=3"], &[b"ab\ncd\n"]);
+        let mut rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new());
+        rat.args.output_buffer = MIN_BUFSIZE;
+        let err = rat.exec().unwrap_err();
+        assert!(matches!(err, RatError::MemoryLimit { limit: 3 }), "{err:?}");
+    }
+
+    #[test]
+    fn rat_wrap() {
+        assert_eq!(rat_output(&["--wrap=4"], b"abcdefghij\nabcd\n\nab"), b"abcd\nefgh\nij\nabcd\n\nab");
+        // the breaks don't get numbers or end markers, a marker can start them instead
+        assert_eq!(rat_output(&["--wrap=3", "-nE"], b"abcdefg\nxy\n"), b"     1 abc\ndef\ng$\n     2 xy$\n");
+        assert_eq!(rat_output(&["--wrap=3", "-n", "--wrap-marker=     + "], b"abcdefg\n"), b"     1 abc\n     + def\n     + g\n");
+        // bytes of input are counted, not what they are shown as
+        assert_eq!(rat_output(&["--wrap=2", "-T"], b"\t\t\t\n"), b"^I^I\n^I\n");
+        assert_eq!(rat_output(&["--wrap=0"], b"abcdefg\n"), b"abcdefg\n");
+
+        // the column carries on into the next FILE like the line does
+        let rat = Rat::new(mock_args(&["--wrap=3"], &[b"ab", b"cd\n"]), Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"abc\nd\n");
+    }
+
+    #[test]
+    fn rat_time_it() {
+        let rat = Rat::new(mock_args(&["--time-it"], &[b"hello\n", b"world\n"]), Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"hello\nworld\n");
+
+        let report = String::from_utf8(rat.err_to).unwrap();
+        let rest = report.strip_prefix("rat: 12 bytes in ").unwrap();
+        let (seconds, rest) = rest.split_once("s, ").unwrap();
+        let throughput = rest.strip_suffix(" MB/s\n").unwrap();
+        assert!(seconds.parse::<f64>().is_ok() && seconds.split_once('.').unwrap().1.len() == 3, "{report}");
+        assert!(throughput.parse::<f64>().is_ok(), "{report}");
+
+        let rat = Rat::new(mock_args(&["--time-it", "--help"], &[b""]), Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert!(rat.err_to.is_empty());
+    }
+
+    #[test]
+    fn rat_ascii_only() {
+        let input = ["naïve café\n\x01".as_bytes(), b"\xff\n"].concat();
+        assert_eq!(rat_output(&["--ascii-only"], &input), b"na??ve caf??\n\x01?\n");
+        assert_eq!(rat_output(&["--ascii-only", "--ascii-placeholder=<?>", "-n"], "é!\n".as_bytes()), b"     1 <?><?>!\n");
+        assert_eq!(rat_output(&["--ascii-only", "--ascii-placeholder="], "déjà vu\n".as_bytes()), b"dj vu\n");
+        // the placeholders are as wide as they are when tabs are expanded
+        assert_eq!(rat_output(&["--ascii-only", "--expand-tabs", "--tab-size=4"], "é\tx\n".as_bytes()), b"??  x\n");
+
+        // a long placeholder for every byte still fits the output buffer
+        let long = format!("--ascii-placeholder={}", "x".repeat(1000));
+        let output = rat_output(&["--ascii-only", &long, "--output-buffer=1"], &[0xff; 20]);
+        assert_eq!(output, "x".repeat(20_000).as_bytes());
+
+        for flags in [&["--ascii-only", "-v"][..], &["--ascii-only", "-A"], &["--ascii-placeholder"]] {
+            let mut raw = vec!["rat".to_string()];
+            raw.extend(flags.iter().map(|f| f.to_string()));
+            assert!(RatArgs::new(raw).is_err(), "accepted {flags:?}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rat_end_at_eot() {
+        let mut reader = EndAtEot::new(std::io::Cursor::new(b"typed\x04not read".to_vec()));
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"typed");
+        assert_eq!(reader.read(&mut [0; 8]).unwrap(), 0);
+
+        // a Ctrl-D right away is the end too, and reads can be as short as a key each
+        let mut reader = EndAtEot::new(Chunked(std::io::Cursor::new(b"ab\x04".to_vec()), 1));
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"ab");
+
+        assert!(RatArgs::new(["rat", "--raw-tty", "--cache-stdin"].map(String::from).to_vec()).is_err());
+    }
+
+    #[test]
+    fn rat_check_utf8() {
+        let mut rat_args = mock_args(&["--check-utf8"], &[]);
+        // the euro sign is split between reads of the first source, the second one is truncated
+        rat_args.files = vec![chunked("a€b\n".as_bytes(), 2), chunked(b"xy\xe2\x82", 3)];
+
+        let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+        assert_eq!(rat.write_to, b"a\xe2\x82\xacb\nxy\xe2\x82");
+        assert_eq!(String::from_utf8(rat.err_to).unwrap(), "rat: mock: invalid UTF-8 at byte 2\n");
+
+        // what was read before --stop-at is still checked, what comes after it isn't there to end a sequence
+        for (input, expected) in [(&b"a\xff\nEND\nb\n"[..], "rat: mock: invalid UTF-8 at byte 1\n"), (b"a\nEND\nb\xe2", "")] {
+            let rat_args = mock_args(&["--check-utf8", "--stop-at=END"], &[input]);
+            let rat = Rat::new(rat_args, Vec::new()).err_to(Vec::new()).exec().unwrap();
+            assert_eq!(String::from_utf8(rat.err_to).unwrap(), expected);
+        }
+    }
+
+    rat_output_test!(rat_output_nv_control_starts_line, ["-nv"], b"\x01a\nb\n", b"     1 ^Aa\n     2 b\n");
 
     macro_rules! rat_args_test {
         ($name:ident, $flag:expr, $($field:ident => $expected:expr),+) => {
@@ -336,7 +7303,7 @@ mod tests {
             #[allow(non_snake_case)]
             fn $name() {
                 let args = vec!["path/to/rat".to_string(), $flag.to_string()];
-                let rat_args = RatArgs::new(args);
+                let rat_args = RatArgs::new(args).unwrap();
     
                 $(
                     assert_eq!(rat_args.$field, $expected, "Failed on {} for flag {}", stringify!($field), $flag);
@@ -1,12 +1,151 @@
 use std::env;
+use std::io::{IsTerminal, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
 use rat::*;
 
+// the pager being quit early is not an error, whatever is left is just dropped
+struct PagerPipe(ChildStdin);
+
+impl Write for PagerPipe {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.0.write(buf) {
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(buf.len()),
+            result => result,
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.0.flush() {
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+            result => result,
+        }
+    }
+}
+
+// $PAGER if set and not empty, `less` otherwise
+fn spawn_pager() -> Option<Child> {
+    let pager = env::var("PAGER").ok().filter(|p| !p.trim().is_empty()).unwrap_or("less".into());
+    let mut parts = pager.split_whitespace();
+
+    Command::new(parts.next()?)
+        .args(parts)
+        .stdin(Stdio::piped())
+        .spawn()
+        .ok()
+}
+
+// standard output as the file it was redirected to, for --resume to cut back
+fn stdout_file() -> std::io::Result<std::fs::File> {
+    #[cfg(unix)]
+    {
+        use std::os::fd::AsFd;
+        Ok(std::io::stdout().as_fd().try_clone_to_owned()?.into())
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::io::AsHandle;
+        Ok(std::io::stdout().as_handle().try_clone_to_owned()?.into())
+    }
+    #[cfg(not(any(unix, windows)))]
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+// the console renders output in its code page, which usually isn't UTF-8
+#[cfg(windows)]
+mod console {
+    const CP_UTF8: u32 = 65001;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetConsoleOutputCP() -> u32;
+        fn SetConsoleOutputCP(code_page: u32) -> i32;
+    }
+
+    // switches the console to UTF-8, returns the code page to restore
+    pub fn use_utf8() -> Option<u32> {
+        // SAFETY: both take and return plain integers and fail gracefully without a console
+        unsafe {
+            let previous = GetConsoleOutputCP();
+            (previous != 0 && previous != CP_UTF8 && SetConsoleOutputCP(CP_UTF8) != 0).then_some(previous)
+        }
+    }
+
+    pub fn restore(code_page: u32) {
+        // SAFETY: see use_utf8
+        unsafe {
+            SetConsoleOutputCP(code_page);
+        }
+    }
+}
 
 fn main() {
+    // RAT_TRACE=debug or trace, an unknown level is as good as none
+    #[cfg(feature = "trace")]
+    if let Some(level) = env::var("RAT_TRACE").ok().and_then(|level| match level.as_str() {
+        "debug" => Some(tracing::Level::DEBUG),
+        "trace" => Some(tracing::Level::TRACE),
+        _ => None,
+    }) {
+        tracing_subscriber::fmt().with_writer(std::io::stderr).with_max_level(level).init();
+    }
+
     let raw_args = env::args().collect::<Vec<String>>();
-    let rat_args = RatArgs::new(raw_args);
+    let rat_args = match RatArgs::from_env_and_args(raw_args) {
+        Ok(rat_args) => rat_args,
+        Err(e) => {
+            eprintln!("rat: {e}");
+            if e.is_usage() {
+                eprintln!("Try 'rat --help' for more information.");
+            }
+            std::process::exit(e.exit_code());
+        }
+    };
+
+    if rat_args.resumes() {
+        if let Err(e) = stdout_file().map_err(|source| RatError::Resume { source }).and_then(|output| rat_args.resume_output(&output)) {
+            eprintln!("rat: {e}");
+            if e.is_usage() {
+                eprintln!("Try 'rat --help' for more information.");
+            }
+            std::process::exit(e.exit_code());
+        }
+    }
+
+    // no pager when it can't be started or stdout isn't a terminal anyway
+    let to_terminal = std::io::stdout().is_terminal();
+    let mut pager = None;
+    if rat_args.pager() && to_terminal {
+        pager = spawn_pager();
+    }
+
+    let write_to: Box<dyn Write> = match pager.as_mut().and_then(|child| child.stdin.take()) {
+        Some(stdin) => Box::new(PagerPipe(stdin)),
+        None => Box::new(std::io::stdout()),
+    };
+
+    // only the console itself needs it, files and pipes get the bytes as they are
+    #[cfg(windows)]
+    let code_page = if to_terminal && pager.is_none() { console::use_utf8() } else { None };
+
+    let rat = Rat::new(rat_args, write_to).to_terminal(to_terminal);
+
+    let exit_code = match rat.exec() {
+        Ok(rat) => rat.exit_code(),
+        Err(e) => {
+            eprintln!("rat: {e}");
+            e.exit_code()
+        }
+    };
+
+    #[cfg(windows)]
+    if let Some(code_page) = code_page {
+        console::restore(code_page);
+    }
 
-    let rat = Rat::new(rat_args, std::io::stdout());
+    // the pager's stdin is closed by now, wait for it so it can restore the terminal
+    if let Some(mut child) = pager {
+        let _ = child.wait();
+    }
 
-    rat.exec();
+    std::process::exit(exit_code);
 }
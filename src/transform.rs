@@ -0,0 +1,809 @@
+//!
+//! The byte transformation behind rat's output, apart from everything that reads sources,
+//! parses arguments or talks to the terminal. Nothing in here touches `std`, there is no
+//! allocation either, so the module can be lifted into a `no_std` crate as it is.
+//!
+//! Available here: lines ended by newlines or by the caller (`--record-delimiter`), line prefixes like `--tap`'s, ASCII `--fold-case`, numbering (all lines, nonblank lines or lines the caller marks), `-s`,
+//! `--collapse-spaces`, `-E` with any end marker, `-T`, tab replacements and `--expand-tabs`, `-v`,
+//! the `--reveal` glyphs, and `--from-encoding` with the `encoding` feature, in [`Stage`]s `--transform-order`
+//! can reorder. What needs a whole line up front
+//! (`--squeeze-whitespace`, `--number-matching`'s matcher) or the OS (`--line-buffered`,
+//! `--limit-rate`, sources) stays with the std layer, which drives a [`Transform`] byte by byte.
+//!
+
+/// Where [`Transform::transform`] writes to, the std-less stand-in for `std::io::Write`.
+pub trait Sink {
+    type Error;
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+}
+
+impl<E, F: FnMut(&[u8]) -> Result<(), E>> Sink for F {
+    type Error = E;
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), E> {
+        self(bytes)
+    }
+}
+
+/// Legacy single-byte encodings `--from-encoding` can transcode to UTF-8.
+#[cfg(feature = "encoding")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Latin1,
+    Cp1252,
+}
+
+#[cfg(feature = "encoding")]
+impl Encoding {
+    // 0x80..=0x9F in windows-1252, undefined slots map to their C1 control like latin1 does
+    const CP1252_HIGH: [char; 32] = [
+        '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+        '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+        '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+        '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        let is = |alias: &str| name.eq_ignore_ascii_case(alias);
+        if is("latin1") || is("latin-1") || is("iso-8859-1") {
+            Some(Encoding::Latin1)
+        } else if is("cp1252") || is("windows-1252") {
+            Some(Encoding::Cp1252)
+        } else {
+            None
+        }
+    }
+
+    pub fn decode(self, byte: u8) -> char {
+        match self {
+            Encoding::Cp1252 if (0x80..=0x9F).contains(&byte) => Self::CP1252_HIGH[(byte - 0x80) as usize],
+            _ => byte as char,
+        }
+    }
+}
+
+/// Glyph sets `--reveal` can draw line endings and tabs with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reveal {
+    Unicode,
+    Ascii,
+}
+
+impl Reveal {
+    /// Marks a source without a final newline, same wording as diff uses.
+    pub const NO_NEWLINE: &'static [u8] = b"\n\\ No newline at end of file\n";
+
+    pub fn tab(self) -> &'static [u8] {
+        match self {
+            Reveal::Unicode => "→".as_bytes(),
+            Reveal::Ascii => b"->",
+        }
+    }
+
+    pub fn cr(self) -> &'static [u8] {
+        match self {
+            Reveal::Unicode => "␍".as_bytes(),
+            Reveal::Ascii => b"<CR>",
+        }
+    }
+
+    pub fn lf(self) -> &'static [u8] {
+        match self {
+            Reveal::Unicode => "⏎\n".as_bytes(),
+            Reveal::Ascii => b"<LF>\n",
+        }
+    }
+}
+
+/// Which lines get a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Numbering {
+    None,
+    All,
+    NonBlank,
+    /// Lines the caller marked with [`Transform::mark_line`].
+    Marked,
+}
+
+/// What happens to the numbering past [`Options::max_line_number`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// The lines after it get no number.
+    #[default]
+    Stop,
+    /// Numbering starts over at [`Options::number_start`].
+    Wrap,
+}
+
+/// How line numbers are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Radix {
+    /// Right aligned in 6 columns, like cat.
+    #[default]
+    Decimal,
+    /// `0x` and at least 6 hex digits.
+    Hex,
+}
+
+/// Which way `--fold-case` maps letters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    Lower,
+    Upper,
+}
+
+/// The steps rendering goes through, in the order of [`Options::order`]. Each one gets what the
+/// ones before it made of the input, so with tabs before squeeze `--collapse-spaces` also collapses
+/// the spaces tabs expand to, and with ends before nonprinting `-v` shows the end marker's control bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// `squeeze_blank` and `collapse_spaces`.
+    Squeeze,
+    /// `show_nonprinting`, `ascii_placeholder` and `from_encoding`.
+    Nonprinting,
+    /// `show_tabs`, `tab_replacement`, `expand_tabs` and the tab and CR glyphs of `reveal`.
+    Tabs,
+    /// `line_prefix`, `numbering` and `byte_offset`.
+    Number,
+    /// `show_ends` and the newline glyph of `reveal`.
+    Ends,
+}
+
+impl Stage {
+    /// The order everything but `--transform-order` renders in.
+    pub const DEFAULT_ORDER: [Stage; 5] = [Stage::Squeeze, Stage::Nonprinting, Stage::Tabs, Stage::Number, Stage::Ends];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "squeeze" => Some(Stage::Squeeze),
+            "nonprinting" => Some(Stage::Nonprinting),
+            "tabs" => Some(Stage::Tabs),
+            "number" => Some(Stage::Number),
+            "ends" => Some(Stage::Ends),
+            _ => None,
+        }
+    }
+}
+
+// what goes from one stage to the next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Piece {
+    Byte(u8),
+    End,
+}
+
+/// What a [`Transform`] does to its input, everything is off by default.
+#[derive(Debug, Clone, Copy)]
+pub struct Options<'a> {
+    /// Written at the start of every line, before its number.
+    pub line_prefix: &'a [u8],
+    pub numbering: Numbering,
+    /// ASCII letters of the input are mapped to this case before anything else looks at them,
+    /// so `-v` and the encodings see the folded bytes and what rat adds itself isn't folded.
+    pub fold_case: Option<Case>,
+    /// Number of the first numbered line.
+    pub number_start: u64,
+    /// Highest number a line gets, `number_overflow` says what comes after it.
+    pub max_line_number: Option<u64>,
+    pub number_overflow: Overflow,
+    pub number_radix: Radix,
+    /// Every line starts with the offset set by [`Transform::set_line_offset`], after its number.
+    pub byte_offset: Option<Radix>,
+    pub squeeze_blank: bool,
+    pub collapse_spaces: bool,
+    /// What each newline is written as, `--null-output` makes it a NUL,
+    /// `--normalize-newlines` a CRLF or a CR and `--record-delimiter` the delimiter.
+    pub line_end: &'a [u8],
+    /// Newlines are bytes like any other, lines only end where [`Transform::render_line_end`] is called.
+    pub explicit_line_ends: bool,
+    pub show_ends: bool,
+    /// Put before each newline by `show_ends`.
+    pub end_marker: &'a [u8],
+    pub show_tabs: bool,
+    /// Shown for tabs instead of `^I`, wins over `show_tabs`.
+    pub tab_replacement: Option<&'a [u8]>,
+    /// Tabs become spaces up to the next tab stop, the other tab options win over this.
+    pub expand_tabs: bool,
+    /// Columns between tab stops.
+    pub tab_size: usize,
+    pub show_nonprinting: bool,
+    /// Shown for every byte >= 128 instead of it, `show_nonprinting` wins over this.
+    pub ascii_placeholder: Option<&'a [u8]>,
+    /// Wins over `show_ends` and the tab options.
+    pub reveal: Option<Reveal>,
+    #[cfg(feature = "encoding")]
+    pub from_encoding: Option<Encoding>,
+    /// Holds every stage once, [`Stage::DEFAULT_ORDER`] unless set.
+    pub order: [Stage; 5],
+}
+
+impl Default for Options<'_> {
+    fn default() -> Self {
+        Self {
+            line_prefix: b"",
+            numbering: Numbering::None,
+            fold_case: None,
+            number_start: 1,
+            max_line_number: None,
+            number_overflow: Overflow::Stop,
+            number_radix: Radix::Decimal,
+            byte_offset: None,
+            squeeze_blank: false,
+            collapse_spaces: false,
+            line_end: b"\n",
+            explicit_line_ends: false,
+            show_ends: false,
+            end_marker: b"$",
+            show_tabs: false,
+            tab_replacement: None,
+            expand_tabs: false,
+            tab_size: 8,
+            show_nonprinting: false,
+            ascii_placeholder: None,
+            reveal: None,
+            #[cfg(feature = "encoding")]
+            from_encoding: None,
+            order: Stage::DEFAULT_ORDER,
+        }
+    }
+}
+
+impl Options<'_> {
+    /// Worst case amount of output bytes a single input byte can turn into.
+    pub fn max_expansion(&self) -> usize {
+        // line prefix, line number (u64::MAX is 20 digits) + separator, then "M-^X", the tab replacement
+        // or expansion, the ASCII placeholder, or the end marker and its newline
+        let expanded = if self.expand_tabs { self.tab_size } else { 0 };
+        let tab = self.tab_replacement.map_or(0, <[u8]>::len).max(expanded);
+        let placeholder = self.ascii_placeholder.map_or(0, <[u8]>::len);
+        let reveal = self.reveal.map_or(0, |reveal| (reveal.lf().len() + self.line_end.len()).max(reveal.cr().len()));
+        let offset = if self.byte_offset.is_some() { 21 } else { 0 };
+        if self.order != Stage::DEFAULT_ORDER {
+            // a stage can get to expand what an earlier one wrote: the prefix or the end marker can
+            // hold tabs, and what a tab becomes can hold bytes -v writes in up to 4
+            let marker = self.reveal.map_or(self.end_marker.len(), |reveal| reveal.lf().len() - 1);
+            let grown = tab.max(4) * placeholder.max(4);
+            return (self.line_prefix.len() + marker.max(1)) * grown + 21 + offset + self.line_end.len();
+        }
+        self.line_prefix.len() + 21 + offset + tab.max(placeholder).max(reveal).max(self.end_marker.len() + self.line_end.len()).max(4)
+    }
+}
+
+impl Options<'_> {
+    // whether `stage` has anything to do, the others are left out of the rendering
+    fn runs(&self, stage: Stage) -> bool {
+        match stage {
+            Stage::Squeeze => self.collapse_spaces,
+            Stage::Nonprinting => {
+                #[cfg(feature = "encoding")]
+                if self.from_encoding.is_some() {
+                    return true;
+                }
+                self.show_nonprinting || self.ascii_placeholder.is_some()
+            },
+            Stage::Tabs => self.show_tabs || self.tab_replacement.is_some() || self.expand_tabs || self.reveal.is_some(),
+            Stage::Number => !self.line_prefix.is_empty() || self.numbering != Numbering::None || self.byte_offset.is_some(),
+            Stage::Ends => self.show_ends || self.reveal.is_some(),
+        }
+    }
+}
+
+// size of the stack buffer Transform::transform renders into
+const CHUNK: usize = 4 * 1024;
+
+// writes byte in ^ and M- notation, returns amount of bytes written
+pub(crate) fn nonprinting(mut byte: u8, out: &mut [u8]) -> usize {
+    let mut pos = 0;
+    if byte >= 128 {
+        out[..2].copy_from_slice(b"M-");
+        pos += 2;
+        byte -= 128;
+    }
+
+    if byte < 32 || byte == 127 {
+        out[pos] = b'^';
+        out[pos + 1] = byte ^ 0x40;
+        pos + 2
+    } else {
+        out[pos] = byte;
+        pos + 1
+    }
+}
+
+// writes "{:6} " or "0x{:06x} " without needing format!, returns amount of bytes written
+fn line_number(mut index: u64, radix: Radix, out: &mut [u8]) -> usize {
+    let (base, prefix, pad_with): (u64, &[u8], u8) = match radix {
+        Radix::Decimal => (10, b"", b' '),
+        Radix::Hex => (16, b"0x", b'0'),
+    };
+
+    let mut digits = [0u8; 20];
+    let mut len = 0;
+    loop {
+        digits[len] = b"0123456789abcdef"[(index % base) as usize];
+        len += 1;
+        index /= base;
+        if index == 0 {
+            break;
+        }
+    }
+
+    out[..prefix.len()].copy_from_slice(prefix);
+    let mut pos = prefix.len();
+    let pad = 6usize.saturating_sub(len);
+    out[pos..pos + pad].fill(pad_with);
+    pos += pad;
+    for (slot, digit) in out[pos..pos + len].iter_mut().zip(digits[..len].iter().rev()) {
+        *slot = *digit;
+    }
+    out[pos + len] = b' ';
+    pos + len + 1
+}
+
+/// Where a [`Transform`] left off: the number of the next line and whether the last one was
+/// finished, for carrying them on into another one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineState {
+    /// Number the next numbered line gets.
+    pub index: u64,
+    prev: Option<u8>,
+    prev_prev: Option<u8>,
+    column: usize,
+    spaced: bool,
+}
+
+impl LineState {
+    /// The state before anything was rendered, with the next line numbered `index`.
+    pub fn new(index: u64) -> Self {
+        Self { index, prev: None, prev_prev: Some(b' '), column: 0, spaced: false }
+    }
+}
+
+/// The rendering state that carries over between chunks and sources.
+pub struct Transform<'a> {
+    options: Options<'a>,
+    index: u64,
+    // the last byte rendered, None for a line end
+    prev: Option<u8>,
+    // i should explain now, this one exists because of -s flag
+    // in original cat.c its logic implented via counting newlines, but i think this is more simple
+    prev_prev: Option<u8>,
+    marked: bool,
+    // where the line being rendered started in the input, for byte_offset
+    line_offset: u64,
+    // column of the line as it reaches the tab stage, for tab stops
+    column: usize,
+    // whether the squeeze stage last passed on a space, for collapse_spaces
+    spaced: bool,
+    // whether the unit being rendered starts a line and whether it ends one
+    line_start: bool,
+    unit_end: bool,
+    // the stages of the order that have something to do, the first `active` of them
+    stages: [Stage; 5],
+    active: usize,
+}
+
+impl<'a> Transform<'a> {
+    pub fn new(options: Options<'a>) -> Self {
+        let mut stages = Stage::DEFAULT_ORDER;
+        let mut active = 0;
+        for stage in options.order {
+            if options.runs(stage) {
+                stages[active] = stage;
+                active += 1;
+            }
+        }
+
+        Self {
+            options,
+            index: options.number_start,
+            prev: None,
+            prev_prev: Some(b' '),
+            marked: false,
+            line_offset: 0,
+            column: 0,
+            spaced: false,
+            line_start: false,
+            unit_end: false,
+            stages,
+            active,
+        }
+    }
+
+    pub fn options(&self) -> &Options<'a> {
+        &self.options
+    }
+
+    pub fn state(&self) -> LineState {
+        LineState { index: self.index, prev: self.prev, prev_prev: self.prev_prev, column: self.column, spaced: self.spaced }
+    }
+
+    /// Carries on from `state`, as if whatever left it had been rendered by this one.
+    pub fn resume(&mut self, state: LineState) {
+        (self.index, self.prev, self.prev_prev, self.column, self.spaced) =
+            (state.index, state.prev, state.prev_prev, state.column, state.spaced);
+    }
+
+    /// Whether the next line gets a number under [`Numbering::Marked`], set before its first byte.
+    pub fn mark_line(&mut self, marked: bool) {
+        self.marked = marked;
+    }
+
+    /// Where the next line starts in the input, shown before it with [`Options::byte_offset`].
+    pub fn set_line_offset(&mut self, offset: u64) {
+        self.line_offset = offset;
+    }
+
+    pub fn at_line_start(&self) -> bool {
+        self.prev.is_none()
+    }
+
+    /// Carries on as if a newline was rendered, for callers that wrote one of their own.
+    pub fn end_line(&mut self) {
+        self.column = 0;
+        self.spaced = false;
+        self.prev_prev = self.prev;
+        self.prev = None;
+    }
+
+    /// Forgets the empty lines rendered so far, so squeezing starts over with the next ones.
+    pub fn reset_squeeze(&mut self) {
+        self.prev_prev = Some(b' ');
+    }
+
+    /// Counts a line that is left out instead of rendered, so the numbers of the following ones
+    /// stay those of the input. `blank` is whether it is empty, for [`Numbering::NonBlank`].
+    pub fn skip_line(&mut self, blank: bool) {
+        let numbered = match self.options.numbering {
+            Numbering::None => false,
+            Numbering::All => true,
+            Numbering::NonBlank => !blank,
+            Numbering::Marked => self.marked,
+        };
+        if numbered {
+            self.next_number();
+        }
+    }
+
+    // the number of a numbered line, None once the numbers ran out; moves on to the next one
+    fn next_number(&mut self) -> Option<u64> {
+        let options = &self.options;
+        if options.max_line_number.is_some_and(|max| self.index > max) {
+            match options.number_overflow {
+                Overflow::Stop => return None,
+                Overflow::Wrap => self.index = options.number_start,
+            }
+        }
+        self.index += 1;
+        Some(self.index - 1)
+    }
+
+    /// Renders `byte` into `out`, which needs room for [`Options::max_expansion`] bytes,
+    /// and returns the amount of bytes written. Squeezed bytes write nothing.
+    pub fn render(&mut self, byte: u8, out: &mut [u8]) -> usize {
+        let end = byte == b'\n' && !self.options.explicit_line_ends;
+        self.render_unit(byte, end, out)
+    }
+
+    /// Renders the end of a line under [`Options::explicit_line_ends`], like [`Transform::render`] does a newline.
+    pub fn render_line_end(&mut self, out: &mut [u8]) -> usize {
+        self.render_unit(b'\n', true, out)
+    }
+
+    // a byte, or the end of a line when `end` is set
+    fn render_unit(&mut self, byte: u8, end: bool, out: &mut [u8]) -> usize {
+        let options = &self.options;
+        let byte = match options.fold_case {
+            Some(Case::Lower) => byte.to_ascii_lowercase(),
+            Some(Case::Upper) => byte.to_ascii_uppercase(),
+            None => byte,
+        };
+
+        // a squeezed line goes as a whole, still taking its number when the numbering comes first
+        if options.squeeze_blank && end && self.prev.is_none() && self.prev_prev.is_none() {
+            let order = options.order;
+            if order.iter().position(|&stage| stage == Stage::Number) < order.iter().position(|&stage| stage == Stage::Squeeze) {
+                self.skip_line(true);
+            }
+            return 0;
+        }
+
+        self.line_start = self.prev.is_none();
+        self.unit_end = end;
+        let mut pos = 0;
+        self.feed(0, if end { Piece::End } else { Piece::Byte(byte) }, out, &mut pos);
+
+        self.prev_prev = self.prev;
+        self.prev = (!end).then_some(byte);
+        pos
+    }
+
+    // hands `piece` to the stage at `at` in the order, which hands what it makes of it on to the
+    // next one; whatever comes out of the last stage is written to `out` at `pos`
+    fn feed(&mut self, mut at: usize, piece: Piece, out: &mut [u8], pos: &mut usize) {
+        // the stages that pass the piece on as it is leave it to the loop
+        while let Some(&stage) = self.stages[..self.active].get(at) {
+            at += 1;
+            let options = &self.options;
+            match (stage, piece) {
+                (Stage::Squeeze, Piece::Byte(b' ')) if options.collapse_spaces && self.spaced => return,
+                (Stage::Squeeze, _) => self.spaced = piece == Piece::Byte(b' '),
+
+                // the first piece of a line brings its prefix, number and offset along
+                (Stage::Number, _) if self.line_start => {
+                    self.line_start = false;
+                    let (prefix, radix, byte_offset) = (options.line_prefix, options.number_radix, options.byte_offset);
+                    let numbered = match options.numbering {
+                        Numbering::None => false,
+                        Numbering::All => true,
+                        Numbering::NonBlank => !self.unit_end,
+                        Numbering::Marked => self.marked,
+                    };
+
+                    self.feed_bytes(at, prefix, out, pos);
+                    let mut digits = [0u8; 24];
+                    if let Some(number) = numbered.then(|| self.next_number()).flatten() {
+                        let len = line_number(number, radix, &mut digits);
+                        self.feed_bytes(at, &digits[..len], out, pos);
+                    }
+                    if let Some(radix) = byte_offset {
+                        let len = line_number(self.line_offset, radix, &mut digits);
+                        self.feed_bytes(at, &digits[..len], out, pos);
+                    }
+                },
+                (Stage::Number, _) => {},
+
+                // printable ASCII stays as it is, TAB is left to the tab stage and so is CR when it is revealed
+                (Stage::Nonprinting, Piece::Byte(b'\t' | b' '..=b'~')) => {},
+                (Stage::Nonprinting, Piece::Byte(b'\r')) if options.reveal.is_some() => {},
+                (Stage::Nonprinting, Piece::Byte(byte)) if options.show_nonprinting => {
+                    let mut shown = [0u8; 4];
+                    let len = nonprinting(byte, &mut shown);
+                    return self.feed_bytes(at, &shown[..len], out, pos);
+                },
+                (Stage::Nonprinting, Piece::Byte(128..)) if options.ascii_placeholder.is_some() =>
+                    return self.feed_bytes(at, options.ascii_placeholder.unwrap(), out, pos),
+                #[cfg(feature = "encoding")]
+                (Stage::Nonprinting, Piece::Byte(byte @ 128..)) if options.from_encoding.is_some() => {
+                    let mut encoded = [0u8; 4];
+                    let len = options.from_encoding.unwrap().decode(byte).encode_utf8(&mut encoded).len();
+                    return self.feed_bytes(at, &encoded[..len], out, pos);
+                },
+                (Stage::Nonprinting, _) => {},
+
+                (Stage::Tabs, Piece::Byte(byte)) => {
+                    let column = self.column;
+                    // UTF-8 continuation bytes share the column of their leading byte
+                    self.column = match byte {
+                        b'\t' => (column / options.tab_size + 1) * options.tab_size,
+                        0x80..=0xbf => column,
+                        _ => column + 1,
+                    };
+
+                    match byte {
+                        b'\t' | b'\r' if options.reveal.is_some() => {
+                            let reveal = options.reveal.unwrap();
+                            return self.feed_bytes(at, if byte == b'\t' { reveal.tab() } else { reveal.cr() }, out, pos);
+                        },
+                        b'\t' if options.show_tabs || options.tab_replacement.is_some() =>
+                            return self.feed_bytes(at, options.tab_replacement.unwrap_or(b"^I"), out, pos),
+                        b'\t' if options.expand_tabs => {
+                            for _ in 0..options.tab_size - column % options.tab_size {
+                                self.feed(at, Piece::Byte(b' '), out, pos);
+                            }
+                            return;
+                        },
+                        _ => {},
+                    }
+                },
+                (Stage::Tabs, Piece::End) => self.column = 0,
+
+                // the newline glyph keeps its line break
+                (Stage::Ends, Piece::End) if options.reveal.is_some() => {
+                    let glyph = options.reveal.unwrap().lf();
+                    self.feed_bytes(at, &glyph[..glyph.len() - 1], out, pos);
+                },
+                (Stage::Ends, Piece::End) if options.show_ends => {
+                    let marker = options.end_marker;
+                    self.feed_bytes(at, marker, out, pos);
+                },
+                (Stage::Ends, _) => {},
+            }
+        }
+
+        let bytes = match piece {
+            Piece::Byte(ref byte) => core::slice::from_ref(byte),
+            Piece::End => self.options.line_end,
+        };
+        out[*pos..*pos + bytes.len()].copy_from_slice(bytes);
+        *pos += bytes.len();
+    }
+
+    fn feed_bytes(&mut self, at: usize, bytes: &[u8], out: &mut [u8], pos: &mut usize) {
+        for &byte in bytes {
+            self.feed(at, Piece::Byte(byte), out, pos);
+        }
+    }
+
+    /// Renders all of `input` to `sink`, through a small buffer on the stack.
+    ///
+    /// # Panics
+    ///
+    /// If [`Options::max_expansion`] is more than 4K, which takes a tab replacement or an end
+    /// marker about that long.
+    pub fn transform<S: Sink>(&mut self, input: &[u8], sink: &mut S) -> Result<(), S::Error> {
+        let reserve = self.options.max_expansion();
+        assert!(reserve <= CHUNK, "markers too long to transform");
+
+        let mut buf = [0u8; CHUNK];
+        let mut pos = 0;
+        for &byte in input {
+            if pos + reserve > CHUNK {
+                sink.write_all(&buf[..pos])?;
+                pos = 0;
+            }
+            pos += self.render(byte, &mut buf[pos..]);
+        }
+
+        if pos > 0 {
+            sink.write_all(&buf[..pos])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // collects into a fixed array, the way a caller without an allocator would
+    fn transform(options: Options, input: &[u8]) -> ([u8; 256], usize) {
+        let mut out = [0u8; 256];
+        let mut len = 0;
+        let mut sink = |bytes: &[u8]| -> Result<(), ()> {
+            out[len..len + bytes.len()].copy_from_slice(bytes);
+            len += bytes.len();
+            Ok(())
+        };
+
+        Transform::new(options).transform(input, &mut sink).unwrap();
+        (out, len)
+    }
+
+    #[test]
+    fn transform_defaults_copy() {
+        let (out, len) = transform(Options::default(), b"a\tb\x01\n\xe9");
+        assert_eq!(&out[..len], b"a\tb\x01\n\xe9");
+    }
+
+    #[test]
+    fn transform_options() {
+        let options = Options {
+            numbering: Numbering::NonBlank,
+            squeeze_blank: true,
+            show_ends: true,
+            end_marker: b"<",
+            show_nonprinting: true,
+            tab_replacement: Some(b"  "),
+            ..Options::default()
+        };
+
+        let (out, len) = transform(options, b"a\tb\x01\n\n\n\nc\n");
+        assert_eq!(&out[..len], b"     1 a  b^A<\n<\n     2 c<\n");
+    }
+
+    #[test]
+    fn transform_line_prefix() {
+        let options = Options { line_prefix: b"# ", numbering: Numbering::NonBlank, ..Options::default() };
+        let (out, len) = transform(options, b"a\n\nb");
+        assert_eq!(&out[..len], b"#      1 a\n# \n#      2 b");
+    }
+
+    #[test]
+    fn transform_fold_case() {
+        let options = Options { fold_case: Some(Case::Upper), show_nonprinting: true, ..Options::default() };
+        let (out, len) = transform(options, b"MiXed 42\x01\xe9z\n");
+        assert_eq!(&out[..len], b"MIXED 42^AM-iZ\n");
+
+        let options = Options { fold_case: Some(Case::Lower), show_ends: true, end_marker: b"END", ..Options::default() };
+        let (out, len) = transform(options, b"MiXed\tCASE\n");
+        assert_eq!(&out[..len], b"mixed\tcaseEND\n");
+    }
+
+    #[test]
+    fn transform_explicit_line_ends() {
+        let options = Options { numbering: Numbering::All, explicit_line_ends: true, line_end: b"\0", ..Options::default() };
+        let mut transform = Transform::new(options);
+        let mut out = [0u8; 64];
+        let mut len = 0;
+
+        for &byte in b"a\nb" {
+            len += transform.render(byte, &mut out[len..]);
+        }
+        len += transform.render_line_end(&mut out[len..]);
+        len += transform.render(b'c', &mut out[len..]);
+        assert_eq!(&out[..len], b"     1 a\nb\0     2 c");
+    }
+
+    #[test]
+    fn transform_number_overflow() {
+        let options = Options { numbering: Numbering::All, number_start: 8, max_line_number: Some(9), ..Options::default() };
+        let (out, len) = transform(options, b"a\nb\nc\nd\n");
+        assert_eq!(&out[..len], b"     8 a\n     9 b\nc\nd\n");
+
+        let options = Options { number_overflow: Overflow::Wrap, ..options };
+        let (out, len) = transform(options, b"a\nb\nc\nd\ne\n");
+        assert_eq!(&out[..len], b"     8 a\n     9 b\n     8 c\n     9 d\n     8 e\n");
+    }
+
+    #[test]
+    fn transform_marked_lines() {
+        let mut transform = Transform::new(Options { numbering: Numbering::Marked, ..Options::default() });
+        let mut out = [0u8; 64];
+        let mut len = 0;
+
+        for (line, marked) in [(&b"x\n"[..], false), (b"y\n", true)] {
+            transform.mark_line(marked);
+            for &byte in line {
+                len += transform.render(byte, &mut out[len..]);
+            }
+        }
+
+        assert_eq!(&out[..len], b"x\n     1 y\n");
+    }
+
+    #[test]
+    fn transform_expand_tabs() {
+        let options = Options { expand_tabs: true, tab_size: 4, ..Options::default() };
+        let (out, len) = transform(options, "a\tbc\t\td\n\té\tx\n".as_bytes());
+        assert_eq!(&out[..len], "a   bc      d\n    é   x\n".as_bytes());
+
+        // the explicit tab displays win
+        let options = Options { expand_tabs: true, show_tabs: true, ..Options::default() };
+        let (out, len) = transform(options, b"a\tb");
+        assert_eq!(&out[..len], b"a^Ib");
+    }
+
+    #[test]
+    fn transform_order() {
+        let order = |names: [&str; 5]| names.map(|name| Stage::parse(name).unwrap());
+
+        // squeezing after the tabs collapses the spaces they became
+        let options = Options { collapse_spaces: true, expand_tabs: true, tab_size: 4, ..Options::default() };
+        let (out, len) = transform(options, b"a\t b  c\n");
+        assert_eq!(&out[..len], b"a    b c\n");
+        let options = Options { order: order(["tabs", "squeeze", "nonprinting", "number", "ends"]), ..options };
+        let (out, len) = transform(options, b"a\t b  c\n");
+        assert_eq!(&out[..len], b"a b c\n");
+
+        // numbering before squeezing counts the squeezed lines
+        let options = Options { numbering: Numbering::All, squeeze_blank: true, ..Options::default() };
+        let (out, len) = transform(options, b"a\n\n\n\nb\n");
+        assert_eq!(&out[..len], b"     1 a\n     2 \n     3 b\n");
+        let options = Options { order: order(["number", "squeeze", "nonprinting", "tabs", "ends"]), ..options };
+        let (out, len) = transform(options, b"a\n\n\n\nb\n");
+        assert_eq!(&out[..len], b"     1 a\n     2 \n     5 b\n");
+
+        // -v after the ends shows the control bytes of the end marker
+        let options = Options { show_ends: true, end_marker: b"\x01", show_nonprinting: true, ..Options::default() };
+        let (out, len) = transform(options, b"a\x02\n");
+        assert_eq!(&out[..len], b"a^B\x01\n");
+        let options = Options { order: order(["squeeze", "ends", "nonprinting", "tabs", "number"]), ..options };
+        let (out, len) = transform(options, b"a\x02\n");
+        assert_eq!(&out[..len], b"a^B^A\n");
+    }
+
+    #[test]
+    fn transform_line_number_width() {
+        let mut out = [0u8; 32];
+        let len = line_number(1234567, Radix::Decimal, &mut out);
+        assert_eq!(&out[..len], b"1234567 ");
+        let len = line_number(0, Radix::Decimal, &mut out);
+        assert_eq!(&out[..len], b"     0 ");
+        let len = line_number(255, Radix::Hex, &mut out);
+        assert_eq!(&out[..len], b"0x0000ff ");
+        let len = line_number(u64::MAX, Radix::Hex, &mut out);
+        assert_eq!(&out[..len], b"0xffffffffffffffff ");
+    }
+}